@@ -0,0 +1,329 @@
+use aes_gcm::aead::OsRng;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::encode;
+use crate::files;
+use crate::entry::EntryKind;
+use crate::import::{ImportAttachment, ImportItem};
+use crate::template::Template;
+
+//
+// Portable encrypted backups ( .keebackup ) of the whole vault, protected by an
+// export password of their own instead of the master password.
+//
+// Header ( little endian ), authenticated as associated data:
+//   "KEEBACKUP"  magic
+//   u16          format version
+//   u32 u32 u32  Argon2id memory ( KiB ), iterations and parallelism
+//   16 bytes     Argon2id salt
+//   24 bytes     XChaCha20-Poly1305 nonce
+// followed by the JSON backup document encrypted with XChaCha20-Poly1305
+//
+
+const MAGIC: &[u8] = b"KEEBACKUP";
+const FORMAT_VERSION: u16 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const HEADER_LENGTH: usize = MAGIC.len() + 2 + 12 + SALT_LENGTH + NONCE_LENGTH;
+
+// Argon2id parameters of written backups
+const MEMORY_KIB: u32 = 64 * 1024;
+const ITERATIONS: u32 = 3;
+const PARALLELISM: u32 = 4;
+// Limits for reading, a crafted header must not make the import run out of memory
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 100;
+const MAX_PARALLELISM: u32 = 64;
+
+#[derive(Serialize, Deserialize)]
+struct BackupDocument {
+    created: String,
+    #[serde(default)]
+    templates: Vec<Template>,
+    #[serde(default)]
+    entries: Vec<BackupEntry>,
+}
+
+//
+// Entry with its timestamps ( unix seconds ), attachments and password history
+//
+#[derive(Serialize, Deserialize)]
+struct BackupEntry {
+    kind: EntryKind,
+    entry: Map<String, Value>,
+    #[serde(default)]
+    created: Option<i64>,
+    #[serde(default)]
+    modified: Option<i64>,
+    #[serde(default)]
+    accessed: Option<i64>,
+    #[serde(default)]
+    attachments: Vec<BackupAttachment>,
+    #[serde(default)]
+    password_history: Vec<BackupPassword>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupAttachment {
+    name: String,
+    // Base64
+    data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupPassword {
+    password: String,
+    replaced: i64,
+}
+
+//
+// Contents of a backup, templates go in before the entries that use them
+//
+pub struct Backup {
+    pub templates: Vec<Template>,
+    pub items: Vec<ImportItem>,
+}
+
+fn derive_key(password: &str, salt: &[u8], memory: u32, iterations: u32, parallelism: u32) -> Result<[u8; 32], String> {
+    let params = match Params::new(memory, iterations, parallelism, Some(32)) {
+        Ok(params) => params,
+        Err(e) => return Err(format!("Invalid Argon2 parameters: {}", e)),
+    };
+
+    let mut key = [0u8; 32];
+    if let Err(e) = Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(password.as_bytes(), salt, &mut key) {
+        return Err(format!("Argon2 key derivation failed: {}", e));
+    }
+    Ok(key)
+}
+
+//
+// Encrypts the templates and entries with the export password
+//
+pub fn write(templates: &[Template], items: &[ImportItem], password: &str) -> Result<Vec<u8>, String> {
+    if password.is_empty() {
+        return Err("Enter a password for the backup".into());
+    }
+
+    let entries: Vec<BackupEntry> = items
+        .iter()
+        .map(|item| BackupEntry {
+            kind: item.kind,
+            entry: item.entry.clone(),
+            created: item.created,
+            modified: item.modified,
+            accessed: item.accessed,
+            attachments: item
+                .attachments
+                .iter()
+                .map(|attachment| BackupAttachment {
+                    name: attachment.name.clone(),
+                    data: encode::encode_base64(attachment.data.clone()),
+                })
+                .collect(),
+            password_history: item
+                .password_history
+                .iter()
+                .map(|(password, replaced)| BackupPassword { password: password.clone(), replaced: *replaced })
+                .collect(),
+        })
+        .collect();
+    let document = BackupDocument {
+        created: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        templates: templates.to_vec(),
+        entries,
+    };
+    let plaintext = match serde_json::to_vec(&document) {
+        Ok(plaintext) => plaintext,
+        Err(e) => return Err(format!("Serializing the backup failed: {}", e)),
+    };
+
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = MAGIC.to_vec();
+    header.extend(FORMAT_VERSION.to_le_bytes());
+    header.extend(MEMORY_KIB.to_le_bytes());
+    header.extend(ITERATIONS.to_le_bytes());
+    header.extend(PARALLELISM.to_le_bytes());
+    header.extend(salt);
+    header.extend(nonce);
+
+    let key = derive_key(password, &salt, MEMORY_KIB, ITERATIONS, PARALLELISM)?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let ciphertext = match cipher.encrypt(XNonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &header }) {
+        Ok(ciphertext) => ciphertext,
+        Err(_) => return Err("Encrypting the backup failed".into()),
+    };
+
+    let mut data = header;
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+//
+// Decrypts a backup, a wrong password and a modified file fail the same authentication check
+//
+pub fn read(data: &[u8], password: &str) -> Result<Backup, String> {
+    if data.len() < HEADER_LENGTH || !data.starts_with(MAGIC) {
+        return Err("Not a KeeManager backup".into());
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LENGTH);
+    let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+
+    let mut offset = MAGIC.len();
+    let version = u16::from_le_bytes([header[offset], header[offset + 1]]);
+    if version > FORMAT_VERSION {
+        return Err(format!("Backup format {} needs a newer version of KeeManager", version));
+    }
+    offset += 2;
+    let (memory, iterations, parallelism) = (u32_at(offset), u32_at(offset + 4), u32_at(offset + 8));
+    if memory > MAX_MEMORY_KIB || iterations > MAX_ITERATIONS || parallelism > MAX_PARALLELISM {
+        return Err("The key derivation settings of the backup are out of range".into());
+    }
+    offset += 12;
+    let salt = &header[offset..offset + SALT_LENGTH];
+    let nonce = &header[offset + SALT_LENGTH..];
+
+    let key = derive_key(password, salt, memory, iterations, parallelism)?;
+    let cipher = XChaCha20Poly1305::new(&key.into());
+    let plaintext = match cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header }) {
+        Ok(plaintext) => plaintext,
+        Err(_) => return Err("Wrong password or damaged backup".into()),
+    };
+    let document: BackupDocument = match serde_json::from_slice(&plaintext) {
+        Ok(document) => document,
+        Err(e) => return Err(format!("Invalid backup contents: {}", e)),
+    };
+
+    let mut items = Vec::new();
+    for entry in document.entries {
+        let mut item = ImportItem::new(entry.kind);
+        item.entry = entry.entry;
+        item.created = entry.created;
+        item.modified = entry.modified;
+        item.accessed = entry.accessed;
+        for attachment in entry.attachments {
+            let data = match encode::decode_base64(&attachment.data) {
+                Ok(data) => data,
+                Err(e) => return Err(format!("Invalid attachment {} in the backup: {}", attachment.name, e)),
+            };
+            item.attachments.push(ImportAttachment { name: attachment.name, data });
+        }
+        item.password_history = entry.password_history.into_iter().map(|previous| (previous.password, previous.replaced)).collect();
+        items.push(item);
+    }
+
+    Ok(Backup {
+        templates: document.templates,
+        items,
+    })
+}
+
+pub fn write_file(path: &str, templates: &[Template], items: &[ImportItem], password: &str) -> Result<(), String> {
+    let data = write(templates, items, password)?;
+    files::write_private(path, &data)
+}
+
+pub fn read_file(path: &str, password: &str) -> Result<Backup, String> {
+    match std::fs::read(path) {
+        Ok(data) => read(&data, password),
+        Err(e) => Err(format!("Reading {} failed: {}", path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{TemplateField, TemplateFieldType};
+    use once_cell::sync::Lazy;
+
+    const PASSWORD: &str = "backup password";
+
+    // Written once, the key derivation is slow in debug builds
+    static BACKUP: Lazy<Vec<u8>> = Lazy::new(|| {
+        let template = Template {
+            id: "2b8f7f7e-3f6a-4c1e-9d55-0c3b8a1f6e21".into(),
+            name: "Server".into(),
+            fields: vec![TemplateField {
+                name: "hostname".into(),
+                field_type: TemplateFieldType::Text,
+                required: true,
+            }],
+            modified: String::new(),
+        };
+
+        let mut login = ImportItem::new(EntryKind::Login);
+        login.set("title", "Mail");
+        login.set("password", "current");
+        login.set("tags", vec!["work"]);
+        login.created = Some(1_600_000_000);
+        login.modified = Some(1_700_000_000);
+        login.accessed = Some(1_710_000_000);
+        login.attachments.push(ImportAttachment { name: "key.bin".into(), data: vec![0, 1, 2, 255] });
+        login.password_history = vec![("first".into(), 1_650_000_000), ("second".into(), 1_690_000_000)];
+
+        let mut server = ImportItem::new(EntryKind::Custom);
+        server.set("template_id", template.id.clone());
+        server.set("title", "Web server");
+
+        write(&[template], &[login, server], PASSWORD).unwrap()
+    });
+
+    #[test]
+    fn round_trip() {
+        let backup = read(&BACKUP, PASSWORD).unwrap();
+
+        assert_eq!(backup.templates.len(), 1);
+        assert_eq!(backup.templates[0].name, "Server");
+        assert_eq!(backup.templates[0].fields[0].name, "hostname");
+
+        assert_eq!(backup.items.len(), 2);
+        let login = &backup.items[0];
+        assert_eq!(login.kind, EntryKind::Login);
+        assert_eq!(login.title(), "Mail");
+        assert_eq!(login.tags(), vec!["work"]);
+        assert_eq!((login.created, login.modified, login.accessed), (Some(1_600_000_000), Some(1_700_000_000), Some(1_710_000_000)));
+        assert_eq!(login.attachments.len(), 1);
+        assert_eq!(login.attachments[0].name, "key.bin");
+        assert_eq!(login.attachments[0].data, vec![0, 1, 2, 255]);
+        assert_eq!(login.password_history, vec![("first".to_string(), 1_650_000_000), ("second".to_string(), 1_690_000_000)]);
+        assert_eq!(backup.items[1].kind, EntryKind::Custom);
+        assert_eq!(backup.items[1].title(), "Web server");
+    }
+
+    #[test]
+    fn wrong_password_fails() {
+        assert_eq!(read(&BACKUP, "wrong password").err().unwrap(), "Wrong password or damaged backup");
+    }
+
+    #[test]
+    fn changed_header_fails_authentication() {
+        // The format version, the key derivation does not depend on it
+        let mut data = BACKUP.clone();
+        data[MAGIC.len()] = 0;
+        assert_eq!(read(&data, PASSWORD).err().unwrap(), "Wrong password or damaged backup");
+    }
+
+    #[test]
+    fn invalid_headers_are_refused() {
+        assert_eq!(read(b"KEEBACKUP", PASSWORD).err().unwrap(), "Not a KeeManager backup");
+        assert!(write(&[], &[], "").is_err());
+
+        let parallelism = MAGIC.len() + 2 + 8;
+        let mut data = BACKUP.clone();
+        data[parallelism..parallelism + 4].copy_from_slice(&(MAX_PARALLELISM + 1).to_le_bytes());
+        assert_eq!(
+            read(&data, PASSWORD).err().unwrap(),
+            "The key derivation settings of the backup are out of range"
+        );
+    }
+}
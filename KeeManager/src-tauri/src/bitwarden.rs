@@ -0,0 +1,1087 @@
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::Aes256;
+use aes_gcm::aead::OsRng;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chrono::Datelike;
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::card::{self, CardNetwork};
+use crate::encode;
+use crate::entry::{format_timestamp, parse_timestamp, with_entry_type, Entry, EntryKind, FieldType};
+use crate::files;
+use crate::import::{ImportItem, Unmapped};
+use crate::plain_export;
+use crate::totp;
+use crate::validation;
+
+//
+// Bitwarden JSON exports, either plain or protected by an export password.
+// Password protected exports hold the plain export encrypted as a type 2
+// cipher string ( AES-256-CBC with HMAC-SHA256 ) with a key derived by
+// PBKDF2 or Argon2id from the password and the salt. Exports written by
+// KeeManager map the entries back the way the import maps them in
+//
+
+const ITEM_LOGIN: u32 = 1;
+const ITEM_SECURE_NOTE: u32 = 2;
+const ITEM_CARD: u32 = 3;
+const ITEM_IDENTITY: u32 = 4;
+const ITEM_SSH_KEY: u32 = 5;
+
+// Custom field types, text and hidden fields are imported as they are
+const FIELD_TEXT: u32 = 0;
+const FIELD_HIDDEN: u32 = 1;
+const FIELD_BOOLEAN: u32 = 2;
+const FIELD_LINKED: u32 = 3;
+
+// Type Bitwarden gives every secure note
+const SECURE_NOTE_GENERIC: u32 = 0;
+
+// Entry fields exported as hidden custom fields
+const HIDDEN_FIELDS: [&str; 6] = ["password", "secret", "api_key", "license_key", "passphrase", "cvv"];
+
+const KDF_PBKDF2: u32 = 0;
+const KDF_ARGON2ID: u32 = 1;
+
+// Key derivation settings of written exports, Bitwarden's defaults
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const ARGON2_MEMORY_MIB: u32 = 64;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 4;
+
+// Highest key derivation settings Bitwarden accepts, files asking for more are refused
+// before deriving the key so they cannot take the time or the memory of the import
+const MAX_PBKDF2_ITERATIONS: u32 = 2_000_000;
+const MAX_ARGON2_MEMORY_MIB: u32 = 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 10;
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+// Cipher string type of AES-256-CBC with an HMAC-SHA256 over IV and cipher text
+const CIPHER_AES_CBC_HMAC: &str = "2";
+
+//
+// Key derivation of password protected exports
+//
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportKdf {
+    #[default]
+    Pbkdf2,
+    Argon2id,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Export {
+    encrypted: bool,
+    password_protected: bool,
+    salt: String,
+    kdf_type: u32,
+    kdf_iterations: u32,
+    kdf_memory: Option<u32>,
+    kdf_parallelism: Option<u32>,
+    #[serde(rename = "encKeyValidation_DO_NOT_EDIT")]
+    key_validation: String,
+    data: String,
+    folders: Vec<Folder>,
+    collections: Vec<Folder>,
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Item {
+    id: Option<String>,
+    folder_id: Option<String>,
+    collection_ids: Option<Vec<String>>,
+    #[serde(rename = "type")]
+    item_type: u32,
+    name: String,
+    notes: Option<String>,
+    favorite: bool,
+    reprompt: u32,
+    fields: Option<Vec<Field>>,
+    login: Option<Login>,
+    card: Option<Card>,
+    identity: Option<Identity>,
+    ssh_key: Option<SshKey>,
+    password_history: Option<Vec<PreviousPassword>>,
+    creation_date: Option<String>,
+    revision_date: Option<String>,
+    deleted_date: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Field {
+    name: Option<String>,
+    value: Option<String>,
+    #[serde(rename = "type")]
+    field_type: u32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Login {
+    uris: Option<Vec<Uri>>,
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    fido2_credentials: Option<Vec<Value>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Uri {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Card {
+    cardholder_name: Option<String>,
+    number: Option<String>,
+    exp_month: Option<String>,
+    exp_year: Option<String>,
+    code: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Identity {
+    title: Option<String>,
+    first_name: Option<String>,
+    middle_name: Option<String>,
+    last_name: Option<String>,
+    address1: Option<String>,
+    address2: Option<String>,
+    address3: Option<String>,
+    city: Option<String>,
+    state: Option<String>,
+    postal_code: Option<String>,
+    country: Option<String>,
+    company: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+    ssn: Option<String>,
+    username: Option<String>,
+    passport_number: Option<String>,
+    license_number: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct SshKey {
+    private_key: Option<String>,
+    public_key: Option<String>,
+    key_fingerprint: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct PreviousPassword {
+    last_used_date: Option<String>,
+    password: Option<String>,
+}
+
+//
+// Reads a Bitwarden JSON export, the password is only needed for password protected exports
+//
+pub fn read_file(path: &str, password: Option<&str>) -> Result<(Vec<ImportItem>, Vec<String>), String> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) => return Err(format!("Reading {} failed: {}", path, e)),
+    };
+    import_items(json.trim_start_matches('\u{feff}'), password)
+}
+
+pub fn import_items(json: &str, password: Option<&str>) -> Result<(Vec<ImportItem>, Vec<String>), String> {
+    let mut export: Export = match serde_json::from_str(json) {
+        Ok(export) => export,
+        Err(e) => return Err(format!("Not a Bitwarden JSON export: {}", e)),
+    };
+
+    if export.encrypted {
+        if !export.password_protected {
+            return Err("Exports encrypted with the Bitwarden account key cannot be imported, export with a file password instead".into());
+        }
+        let password = match password.filter(|password| !password.is_empty()) {
+            Some(password) => password,
+            None => return Err("This Bitwarden export is password protected, enter its password".into()),
+        };
+        let data = decrypt_export(&export, password)?;
+        export = match serde_json::from_str(&data) {
+            Ok(export) => export,
+            Err(e) => return Err(format!("Decrypted Bitwarden export is invalid: {}", e)),
+        };
+    }
+
+    let folders: HashMap<&str, &str> = export.folders.iter().map(|folder| (folder.id.as_str(), folder.name.as_str())).collect();
+    let collections: HashMap<&str, &str> =
+        export.collections.iter().map(|collection| (collection.id.as_str(), collection.name.as_str())).collect();
+
+    let mut items = Vec::new();
+    let mut warnings = Vec::new();
+    for item in &export.items {
+        if item.deleted_date.is_some() {
+            warnings.push(format!("{}: deleted item not imported", item.name));
+            continue;
+        }
+        if let Some(imported) = import_item(item, &folders, &collections, &mut warnings) {
+            items.push(imported);
+        }
+    }
+    Ok((items, warnings))
+}
+
+fn text(value: &Option<String>) -> String {
+    value.as_deref().unwrap_or("").to_string()
+}
+
+fn import_item(
+    item: &Item,
+    folders: &HashMap<&str, &str>,
+    collections: &HashMap<&str, &str>,
+    warnings: &mut Vec<String>,
+) -> Option<ImportItem> {
+    let kind = match item.item_type {
+        ITEM_LOGIN => EntryKind::Login,
+        ITEM_SECURE_NOTE => EntryKind::Note,
+        ITEM_CARD => EntryKind::CreditCard,
+        ITEM_IDENTITY => EntryKind::Identity,
+        ITEM_SSH_KEY => EntryKind::SshKey,
+        item_type => {
+            warnings.push(format!("{}: unsupported item type {}", item.name, item_type));
+            return None;
+        }
+    };
+
+    let mut imported = ImportItem::new(kind);
+    let mut unmapped = Unmapped::default();
+    imported.set("id", text(&item.id));
+    imported.set("title", item.name.clone());
+    imported.set("favorite", item.favorite);
+    imported.created = item.creation_date.as_deref().and_then(|date| parse_timestamp(date).ok().flatten());
+    imported.modified = item.revision_date.as_deref().and_then(|date| parse_timestamp(date).ok().flatten());
+
+    if let Some(folder) = item.folder_id.as_deref().and_then(|id| folders.get(id)) {
+        imported.group = folder.to_string();
+    }
+    let tags: Vec<String> = item
+        .collection_ids
+        .iter()
+        .flatten()
+        .filter_map(|id| collections.get(id.as_str()).map(|name| name.to_string()))
+        .collect();
+    imported.set("tags", tags);
+
+    let notes_field = match kind {
+        EntryKind::Login => {
+            import_login(&mut imported, item.login.as_ref().unwrap_or(&Login::default()), &mut unmapped, warnings);
+            imported.set("notes", text(&item.notes));
+
+            let mut history: Vec<(String, i64)> = item
+                .password_history
+                .iter()
+                .flatten()
+                .filter(|previous| !text(&previous.password).is_empty())
+                .map(|previous| {
+                    let replaced = previous.last_used_date.as_deref().and_then(|date| parse_timestamp(date).ok().flatten());
+                    (text(&previous.password), replaced.unwrap_or(0))
+                })
+                .collect();
+            history.sort_by_key(|(_, replaced)| *replaced);
+            imported.password_history = history;
+            Some("notes")
+        }
+        EntryKind::Note => {
+            imported.set("content", text(&item.notes));
+            Some("content")
+        }
+        EntryKind::CreditCard => {
+            import_card(&mut imported, item.card.as_ref().unwrap_or(&Card::default()), &mut unmapped);
+            unmapped.add("Notes", &text(&item.notes));
+            None
+        }
+        EntryKind::Identity => {
+            import_identity(&mut imported, item.identity.as_ref().unwrap_or(&Identity::default()), &mut unmapped);
+            imported.set("notes", text(&item.notes));
+            Some("notes")
+        }
+        _ => {
+            let ssh_key = item.ssh_key.as_ref();
+            imported.set("private_key", ssh_key.map(|key| text(&key.private_key)).unwrap_or_default());
+            imported.set("public_key", ssh_key.map(|key| text(&key.public_key)).unwrap_or_default());
+            imported.set("fingerprint", ssh_key.map(|key| text(&key.key_fingerprint)).unwrap_or_default());
+            imported.set("passphrase", "");
+            imported.set("comment", "");
+            imported.set("key_type", "");
+            imported.set("notes", text(&item.notes));
+            Some("notes")
+        }
+    };
+
+    for field in item.fields.iter().flatten() {
+        let name = text(&field.name);
+        match field.field_type {
+            FIELD_LINKED => warnings.push(format!("{}: linked field {} not imported", item.name, name)),
+            FIELD_BOOLEAN => unmapped.add(&name, &text(&field.value).to_ascii_lowercase()),
+            _ => unmapped.add(&name, &text(&field.value)),
+        }
+    }
+    if item.reprompt != 0 {
+        warnings.push(format!("{}: master password reprompt is not supported", item.name));
+    }
+
+    unmapped.finish(&mut imported, notes_field, warnings);
+    Some(imported)
+}
+
+fn import_login(imported: &mut ImportItem, login: &Login, unmapped: &mut Unmapped, warnings: &mut Vec<String>) {
+    imported.set("username", text(&login.username));
+    imported.set("password", text(&login.password));
+
+    let uris: Vec<String> = login.uris.iter().flatten().map(|uri| text(&uri.uri)).filter(|uri| !uri.is_empty()).collect();
+    imported.set("url", uris.first().cloned().unwrap_or_default());
+    for (index, uri) in uris.iter().enumerate().skip(1) {
+        unmapped.add(&format!("URL {}", index + 1), uri);
+    }
+
+    let otp = text(&login.totp);
+    match totp::parse(&otp) {
+        _ if otp.is_empty() => imported.set("otp", ""),
+        Ok(_) => imported.set("otp", otp),
+        Err(_) => {
+            imported.set("otp", "");
+            unmapped.add("TOTP", &otp);
+        }
+    }
+
+    if login.fido2_credentials.as_ref().is_some_and(|credentials| !credentials.is_empty()) {
+        warnings.push(format!("{}: passkeys are not imported", imported.title()));
+    }
+}
+
+fn import_card(imported: &mut ImportItem, card: &Card, unmapped: &mut Unmapped) {
+    imported.set("cardholder_name", text(&card.cardholder_name));
+    imported.set("card_number", text(&card.number));
+    imported.set("cvv", text(&card.code));
+
+    let month = text(&card.exp_month);
+    let year = text(&card.exp_year);
+    if month.is_empty() || year.is_empty() {
+        imported.set("expiry_date", "");
+        unmapped.add("Expiry month", &month);
+        unmapped.add("Expiry year", &year);
+    } else {
+        imported.set("expiry_date", format!("{:0>2}/{}", month.trim(), year.trim()));
+    }
+}
+
+//
+// The identity number is the passport number, the driver's license number or
+// the social security number, whichever comes first, the rest ends up in the notes
+//
+fn import_identity(imported: &mut ImportItem, identity: &Identity, unmapped: &mut Unmapped) {
+    let full_name: Vec<String> = [&identity.first_name, &identity.middle_name, &identity.last_name]
+        .into_iter()
+        .map(text)
+        .filter(|name| !name.is_empty())
+        .collect();
+    imported.set("full_name", full_name.join(" "));
+
+    let numbers = [
+        ("Passport number", &identity.passport_number),
+        ("License number", &identity.license_number),
+        ("Social security number", &identity.ssn),
+    ];
+    let mut identification_number = String::new();
+    for (name, number) in numbers {
+        if identification_number.is_empty() {
+            identification_number = text(number);
+        } else {
+            unmapped.add(name, &text(number));
+        }
+    }
+    imported.set("identification_number", identification_number);
+
+    for field in ["date_of_birth", "nationality", "issue_date", "expiry_date", "issuer"] {
+        imported.set(field, "");
+    }
+
+    unmapped.add("Title", &text(&identity.title));
+    unmapped.add("Company", &text(&identity.company));
+    unmapped.add("Email", &text(&identity.email));
+    unmapped.add("Phone", &text(&identity.phone));
+    unmapped.add("Username", &text(&identity.username));
+    let address: Vec<String> = [
+        &identity.address1,
+        &identity.address2,
+        &identity.address3,
+        &identity.postal_code,
+        &identity.city,
+        &identity.state,
+        &identity.country,
+    ]
+    .into_iter()
+    .map(text)
+    .filter(|line| !line.is_empty())
+    .collect();
+    if !address.is_empty() {
+        unmapped.add("Address", &address.join(", "));
+    }
+}
+
+//
+// Writes the items as a Bitwarden JSON export, password protected when a password is given,
+// returns what Bitwarden has no place for ( attachments, expiry times, further tags )
+//
+pub fn write_file(path: &str, items: &[ImportItem], password: Option<&str>, kdf: ExportKdf) -> Result<Vec<String>, String> {
+    let (json, warnings) = export_items(items, password, kdf)?;
+    files::write_private(path, json.as_bytes())?;
+    Ok(warnings)
+}
+
+//
+// Bitwarden JSON of the items, the first tag of an entry becomes its folder
+//
+pub fn export_items(items: &[ImportItem], password: Option<&str>, kdf: ExportKdf) -> Result<(String, Vec<String>), String> {
+    if password.is_some_and(str::is_empty) {
+        return Err("Enter a password for the export".into());
+    }
+
+    let mut folders: Vec<(String, String)> = Vec::new();
+    let mut exported = Vec::new();
+    let mut warnings = Vec::new();
+    for item in items {
+        let tags = item.tags();
+        let folder_id = tags.first().map(|tag| match folders.iter().find(|(name, _)| name == tag) {
+            Some((_, id)) => id.clone(),
+            None => {
+                let id = Uuid::new_v4().to_string();
+                folders.push((tag.clone(), id.clone()));
+                id
+            }
+        });
+        if tags.len() > 1 {
+            warnings.push(format!("{}: only the tag {} is exported, as its folder", item.title(), tags[0]));
+        }
+        exported.push(export_item(item, folder_id, &mut warnings));
+    }
+
+    let folders: Vec<Value> = folders.into_iter().map(|(name, id)| json!({ "id": id, "name": name })).collect();
+    let export = json!({ "encrypted": false, "folders": folders, "items": exported });
+    let export = match password {
+        Some(password) => encrypt_export(&export.to_string(), password, &kdf_settings(kdf))?,
+        None => export,
+    };
+    match serde_json::to_string_pretty(&export) {
+        Ok(json) => Ok((json, warnings)),
+        Err(e) => Err(format!("Serializing the Bitwarden export failed: {}", e)),
+    }
+}
+
+// Bitwarden writes empty values as null
+fn optional(value: String) -> Value {
+    if value.is_empty() {
+        Value::Null
+    } else {
+        Value::String(value)
+    }
+}
+
+fn custom_field(name: &str, value: &str) -> Value {
+    let field_type = if HIDDEN_FIELDS.contains(&name) { FIELD_HIDDEN } else { FIELD_TEXT };
+    json!({ "name": plain_export::label(name), "value": value, "type": field_type, "linkedId": null })
+}
+
+fn export_item(item: &ImportItem, folder_id: Option<String>, warnings: &mut Vec<String>) -> Value {
+    let value = |field: &str| match item.entry.get(field) {
+        Some(Value::String(value)) => value.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    };
+
+    let mut exported = json!({
+        "id": optional(value("id")),
+        "organizationId": null,
+        "folderId": folder_id,
+        "reprompt": 0,
+        "name": item.title(),
+        "notes": optional(value("notes")),
+        "favorite": item.entry.get("favorite").and_then(Value::as_bool).unwrap_or(false),
+        "collectionIds": null,
+        "creationDate": item.created.map(format_timestamp),
+        "revisionDate": item.modified.map(format_timestamp),
+        "deletedDate": null,
+    });
+    let mut fields: Vec<Value> = Vec::new();
+
+    let item_type = match item.kind {
+        EntryKind::Login => {
+            let url = value("url");
+            let uris = if url.is_empty() { json!([]) } else { json!([{ "match": null, "uri": url }]) };
+            exported["login"] = json!({
+                "uris": uris,
+                "username": optional(value("username")),
+                "password": optional(value("password")),
+                "totp": optional(value("otp")),
+                "fido2Credentials": [],
+            });
+            // Bitwarden lists the newest password first
+            let history: Vec<Value> = item
+                .password_history
+                .iter()
+                .rev()
+                .map(|(password, replaced)| json!({ "lastUsedDate": format_timestamp(*replaced), "password": password }))
+                .collect();
+            if !history.is_empty() {
+                exported["passwordHistory"] = Value::Array(history);
+            }
+            ITEM_LOGIN
+        }
+        EntryKind::CreditCard => {
+            let number = value("card_number");
+            let brand = match card::detect_network(&number) {
+                CardNetwork::Amex => Some("Amex"),
+                CardNetwork::Unknown => None,
+                network => Some(network.name()),
+            };
+            let expiry = validation::parse_card_expiry(&value("expiry_date")).ok().flatten();
+            exported["card"] = json!({
+                "cardholderName": optional(value("cardholder_name")),
+                "brand": brand,
+                "number": optional(number),
+                "expMonth": expiry.map(|expiry| expiry.month().to_string()),
+                "expYear": expiry.map(|expiry| expiry.year().to_string()),
+                "code": optional(value("cvv")),
+            });
+            ITEM_CARD
+        }
+        EntryKind::Note => {
+            exported["notes"] = optional(value("content"));
+            exported["secureNote"] = json!({ "type": SECURE_NOTE_GENERIC });
+            ITEM_SECURE_NOTE
+        }
+        EntryKind::Identity => {
+            // Middle names are the words between the first and the last one
+            let full_name = value("full_name");
+            let names: Vec<&str> = full_name.split_whitespace().collect();
+            let (first_name, middle_name, last_name) = match names.as_slice() {
+                [] => (String::new(), String::new(), String::new()),
+                [name] => (name.to_string(), String::new(), String::new()),
+                [first, middle @ .., last] => (first.to_string(), middle.join(" "), last.to_string()),
+            };
+            exported["identity"] = json!({
+                "title": null,
+                "firstName": optional(first_name),
+                "middleName": optional(middle_name),
+                "lastName": optional(last_name),
+                "address1": null,
+                "address2": null,
+                "address3": null,
+                "city": null,
+                "state": null,
+                "postalCode": null,
+                "country": null,
+                "company": null,
+                "email": null,
+                "phone": null,
+                "ssn": null,
+                "username": null,
+                "passportNumber": optional(value("identification_number")),
+                "licenseNumber": null,
+            });
+            for field in ["date_of_birth", "nationality", "issue_date", "expiry_date", "issuer"] {
+                if !value(field).is_empty() {
+                    fields.push(custom_field(field, &value(field)));
+                }
+            }
+            ITEM_IDENTITY
+        }
+        EntryKind::SshKey => {
+            exported["sshKey"] = json!({
+                "privateKey": optional(value("private_key")),
+                "publicKey": optional(value("public_key")),
+                "keyFingerprint": optional(value("fingerprint")),
+            });
+            for field in ["passphrase", "comment"] {
+                if !value(field).is_empty() {
+                    fields.push(custom_field(field, &value(field)));
+                }
+            }
+            ITEM_SSH_KEY
+        }
+        // Bitwarden has no item type for the others, their fields become custom fields of a note
+        kind => {
+            for field in with_entry_type!(kind, T => T::FIELDS) {
+                match (field.name, field.field_type) {
+                    ("title", _) | ("notes", _) | ("template_id", _) => (),
+                    (_, FieldType::Json) => {
+                        if let Some(Value::Object(values)) = item.entry.get(field.name) {
+                            for (name, value) in values {
+                                let value = value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string());
+                                fields.push(json!({ "name": name, "value": value, "type": FIELD_TEXT, "linkedId": null }));
+                            }
+                        }
+                    }
+                    (name, _) if !value(name).is_empty() => fields.push(custom_field(name, &value(name))),
+                    _ => (),
+                }
+            }
+            exported["secureNote"] = json!({ "type": SECURE_NOTE_GENERIC });
+            warnings.push(format!("{}: exported as a secure note", item.title()));
+            ITEM_SECURE_NOTE
+        }
+    };
+    exported["type"] = json!(item_type);
+    exported["fields"] = Value::Array(fields);
+
+    if !value("expires").is_empty() {
+        warnings.push(format!("{}: the expiry time is not exported", item.title()));
+    }
+    if !item.attachments.is_empty() {
+        let names: Vec<&str> = item.attachments.iter().map(|attachment| attachment.name.as_str()).collect();
+        warnings.push(format!("{}: attachments are not exported: {}", item.title(), names.join(", ")));
+    }
+    exported
+}
+
+//
+// Decrypts the data of a password protected export, the key validation
+// string fails its HMAC check when the password is wrong
+//
+fn decrypt_export(export: &Export, password: &str) -> Result<String, String> {
+    let key = export_key(export, password)?;
+    let (encryption_key, mac_key) = stretch_key(&key)?;
+
+    if decrypt_cipher_string(&export.key_validation, &encryption_key, &mac_key).is_err() {
+        return Err("Wrong password for the Bitwarden export".into());
+    }
+    let data = decrypt_cipher_string(&export.data, &encryption_key, &mac_key)?;
+    match String::from_utf8(data) {
+        Ok(data) => Ok(data),
+        Err(_) => Err("Decrypted Bitwarden export is not UTF-8".into()),
+    }
+}
+
+fn kdf_settings(kdf: ExportKdf) -> Export {
+    let mut settings = Export::default();
+    match kdf {
+        ExportKdf::Pbkdf2 => {
+            settings.kdf_type = KDF_PBKDF2;
+            settings.kdf_iterations = PBKDF2_ITERATIONS;
+        }
+        ExportKdf::Argon2id => {
+            settings.kdf_type = KDF_ARGON2ID;
+            settings.kdf_iterations = ARGON2_ITERATIONS;
+            settings.kdf_memory = Some(ARGON2_MEMORY_MIB);
+            settings.kdf_parallelism = Some(ARGON2_PARALLELISM);
+        }
+    }
+    settings
+}
+
+//
+// Password protected export of the plain export data with a new salt, the key
+// validation string is a random UUID encrypted with the same keys
+//
+fn encrypt_export(data: &str, password: &str, settings: &Export) -> Result<Value, String> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let export = Export {
+        salt: encode::encode_base64(salt.to_vec()),
+        kdf_type: settings.kdf_type,
+        kdf_iterations: settings.kdf_iterations,
+        kdf_memory: settings.kdf_memory,
+        kdf_parallelism: settings.kdf_parallelism,
+        ..Default::default()
+    };
+    let key = export_key(&export, password)?;
+    let (encryption_key, mac_key) = stretch_key(&key)?;
+
+    Ok(json!({
+        "encrypted": true,
+        "passwordProtected": true,
+        "salt": export.salt,
+        "kdfType": export.kdf_type,
+        "kdfIterations": export.kdf_iterations,
+        "kdfMemory": export.kdf_memory,
+        "kdfParallelism": export.kdf_parallelism,
+        "encKeyValidation_DO_NOT_EDIT": encrypt_cipher_string(Uuid::new_v4().to_string().as_bytes(), &encryption_key, &mac_key)?,
+        "data": encrypt_cipher_string(data.as_bytes(), &encryption_key, &mac_key)?,
+    }))
+}
+
+//
+// Key of the export password, the salt is used as text ( Argon2id takes its SHA-256 hash )
+//
+fn export_key(export: &Export, password: &str) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    match export.kdf_type {
+        KDF_PBKDF2 => {
+            if export.kdf_iterations == 0 {
+                return Err("Bitwarden export has no PBKDF2 iteration count".into());
+            }
+            check_limit("iteration count", export.kdf_iterations, MAX_PBKDF2_ITERATIONS)?;
+            if let Err(e) = pbkdf2::<Hmac<Sha256>>(password.as_bytes(), export.salt.as_bytes(), export.kdf_iterations, &mut key) {
+                return Err(format!("PBKDF2 key derivation failed: {}", e));
+            }
+        }
+        KDF_ARGON2ID => {
+            // Memory is given in MiB
+            let memory = export.kdf_memory.unwrap_or(ARGON2_MEMORY_MIB);
+            let parallelism = export.kdf_parallelism.unwrap_or(ARGON2_PARALLELISM);
+            check_limit("memory in MiB", memory, MAX_ARGON2_MEMORY_MIB)?;
+            check_limit("iteration count", export.kdf_iterations, MAX_ARGON2_ITERATIONS)?;
+            check_limit("parallelism", parallelism, MAX_ARGON2_PARALLELISM)?;
+            let params = match Params::new(memory * 1024, export.kdf_iterations, parallelism, Some(32)) {
+                Ok(params) => params,
+                Err(e) => return Err(format!("Invalid Argon2 parameters: {}", e)),
+            };
+            let salt = Sha256::digest(export.salt.as_bytes());
+            if let Err(e) = Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(password.as_bytes(), &salt, &mut key) {
+                return Err(format!("Argon2 key derivation failed: {}", e));
+            }
+        }
+        kdf_type => return Err(format!("Unsupported Bitwarden KDF type {}", kdf_type)),
+    }
+    Ok(key)
+}
+
+fn check_limit(name: &str, value: u32, maximum: u32) -> Result<(), String> {
+    if value > maximum {
+        return Err(format!("The {} of the key derivation ( {} ) is more than the {} allowed", name, value, maximum));
+    }
+    Ok(())
+}
+
+//
+// Encryption and MAC keys expanded from the derived key with HKDF-Expand ( SHA-256 )
+//
+fn stretch_key(key: &[u8; 32]) -> Result<([u8; 32], [u8; 32]), String> {
+    let expand = |info: &[u8]| -> Result<[u8; 32], String> {
+        let mut mac = match <Hmac<Sha256> as Mac>::new_from_slice(key) {
+            Ok(mac) => mac,
+            Err(e) => return Err(format!("HKDF failed: {}", e)),
+        };
+        mac.update(info);
+        mac.update(&[1]);
+        Ok(mac.finalize().into_bytes().into())
+    };
+    Ok((expand(b"enc")?, expand(b"mac")?))
+}
+
+//
+// "2.<iv>|<cipher text>|<mac>" with base64 parts, the MAC covers IV and cipher text
+//
+fn decrypt_cipher_string(cipher_string: &str, encryption_key: &[u8; 32], mac_key: &[u8; 32]) -> Result<Vec<u8>, String> {
+    let invalid = || "Invalid Bitwarden cipher string".to_string();
+
+    let (cipher_type, parts) = cipher_string.trim().split_once('.').ok_or_else(invalid)?;
+    if cipher_type != CIPHER_AES_CBC_HMAC {
+        return Err(format!("Unsupported Bitwarden cipher string type {}", cipher_type));
+    }
+    let parts: Vec<Vec<u8>> = parts
+        .split('|')
+        .map(|part| encode::decode_base64(part).map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (iv, cipher_text, expected_mac) = match parts.as_slice() {
+        [iv, cipher_text, mac] if iv.len() == 16 => (iv, cipher_text, mac),
+        _ => return Err(invalid()),
+    };
+
+    let mut mac = match <Hmac<Sha256> as Mac>::new_from_slice(mac_key) {
+        Ok(mac) => mac,
+        Err(e) => return Err(format!("HMAC failed: {}", e)),
+    };
+    mac.update(iv);
+    mac.update(cipher_text);
+    if mac.verify_slice(expected_mac).is_err() {
+        return Err("Bitwarden cipher string MAC mismatch".into());
+    }
+
+    match cbc::Decryptor::<Aes256>::new(encryption_key.into(), iv.as_slice().into()).decrypt_padded_vec_mut::<Pkcs7>(cipher_text) {
+        Ok(plain_text) => Ok(plain_text),
+        Err(_) => Err("Bitwarden cipher string decryption failed".into()),
+    }
+}
+
+//
+// Type 2 cipher string of the plain text with a random IV
+//
+fn encrypt_cipher_string(plain_text: &[u8], encryption_key: &[u8; 32], mac_key: &[u8; 32]) -> Result<String, String> {
+    let mut iv = [0u8; 16];
+    OsRng.fill_bytes(&mut iv);
+    let cipher_text = cbc::Encryptor::<Aes256>::new(encryption_key.into(), &iv.into()).encrypt_padded_vec_mut::<Pkcs7>(plain_text);
+
+    let mut mac = match <Hmac<Sha256> as Mac>::new_from_slice(mac_key) {
+        Ok(mac) => mac,
+        Err(e) => return Err(format!("HMAC failed: {}", e)),
+    };
+    mac.update(&iv);
+    mac.update(&cipher_text);
+
+    Ok(format!(
+        "{}.{}|{}|{}",
+        CIPHER_AES_CBC_HMAC,
+        encode::encode_base64(iv.to_vec()),
+        encode::encode_base64(cipher_text),
+        encode::encode_base64(mac.finalize().into_bytes().to_vec())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::ImportAttachment;
+
+    const ENCRYPTION_KEY: [u8; 32] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31,
+    ];
+    const MAC_KEY: [u8; 32] = [
+        32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61,
+        62, 63,
+    ];
+    // "KeeManager" encrypted with the keys above by another implementation
+    const CIPHER_STRING: &str = "2.ZGVmZ2hpamtsbW5vcHFycw==|XmtOmW+Q66mX3D+kODWQzg==|c8bb6UZB5EYtYBVvjsN6wVRr5eEcAkiQIWUbyitNdAQ=";
+
+    fn item(kind: EntryKind, fields: &[(&str, &str)]) -> ImportItem {
+        let mut item = ImportItem::new(kind);
+        item.set("id", Uuid::new_v4().to_string());
+        for (field, value) in fields {
+            item.set(field, *value);
+        }
+        item
+    }
+
+    fn vault() -> Vec<ImportItem> {
+        let mut login = item(
+            EntryKind::Login,
+            &[
+                ("title", "GitHub"),
+                ("username", "octocat"),
+                ("password", "current"),
+                ("url", "https://github.com"),
+                ("notes", "my notes"),
+                ("otp", "JBSWY3DPEHPK3PXP"),
+            ],
+        );
+        login.set("favorite", true);
+        login.set("tags", vec!["Work"]);
+        login.created = Some(1_600_000_000);
+        login.modified = Some(1_700_000_000);
+        login.password_history = vec![("first".into(), 1_650_000_000), ("second".into(), 1_690_000_000)];
+        login.attachments.push(ImportAttachment { name: "key.bin".into(), data: vec![1, 2, 3] });
+
+        vec![
+            login,
+            item(
+                EntryKind::CreditCard,
+                &[
+                    ("title", "Visa"),
+                    ("card_number", "4111111111111111"),
+                    ("expiry_date", "03/31"),
+                    ("cardholder_name", "Jane Doe"),
+                    ("cvv", "123"),
+                ],
+            ),
+            item(EntryKind::Note, &[("title", "Note"), ("content", "secret text")]),
+            item(
+                EntryKind::Identity,
+                &[
+                    ("title", "Passport"),
+                    ("full_name", "Ada King Lovelace"),
+                    ("identification_number", "P123"),
+                    ("date_of_birth", "1815-12-10"),
+                    ("notes", "identity notes"),
+                ],
+            ),
+            item(EntryKind::Wifi, &[("title", "Home"), ("ssid", "home-net"), ("security_type", "WPA2"), ("password", "wifi-password")]),
+        ]
+    }
+
+    fn check_round_trip(items: &[ImportItem]) {
+        assert_eq!(items.len(), 5);
+
+        let login = &items[0];
+        assert_eq!(login.kind, EntryKind::Login);
+        for (field, value) in [
+            ("title", "GitHub"),
+            ("username", "octocat"),
+            ("password", "current"),
+            ("url", "https://github.com"),
+            ("notes", "my notes"),
+            ("otp", "JBSWY3DPEHPK3PXP"),
+        ] {
+            assert_eq!(login.entry[field], value, "{}", field);
+        }
+        assert_eq!(login.entry["favorite"], true);
+        assert_eq!(login.group, "Work");
+        assert_eq!(login.tags(), vec!["Work"]);
+        assert_eq!((login.created, login.modified), (Some(1_600_000_000), Some(1_700_000_000)));
+        assert_eq!(login.password_history, vec![("first".to_string(), 1_650_000_000), ("second".to_string(), 1_690_000_000)]);
+
+        let card = &items[1];
+        assert_eq!(card.kind, EntryKind::CreditCard);
+        assert_eq!(card.entry["card_number"], "4111111111111111");
+        assert_eq!(card.entry["expiry_date"], "03/2031");
+        assert_eq!(card.entry["cardholder_name"], "Jane Doe");
+        assert_eq!(card.entry["cvv"], "123");
+
+        assert_eq!(items[2].kind, EntryKind::Note);
+        assert_eq!(items[2].entry["content"], "secret text");
+
+        let identity = &items[3];
+        assert_eq!(identity.kind, EntryKind::Identity);
+        assert_eq!(identity.entry["full_name"], "Ada King Lovelace");
+        assert_eq!(identity.entry["identification_number"], "P123");
+        assert_eq!(identity.entry["notes"], "identity notes\n\nDate of birth: 1815-12-10");
+
+        // Bitwarden has no Wi-Fi items, the fields come back in a note
+        assert_eq!(items[4].kind, EntryKind::Note);
+        assert_eq!(items[4].entry["content"], "Ssid: home-net\nSecurity type: WPA2\nPassword: wifi-password");
+    }
+
+    #[test]
+    fn plain_export_round_trip() {
+        let (json, warnings) = export_items(&vault(), None, ExportKdf::default()).unwrap();
+        assert!(warnings.contains(&"GitHub: attachments are not exported: key.bin".to_string()), "{:?}", warnings);
+        assert!(warnings.contains(&"Home: exported as a secure note".to_string()), "{:?}", warnings);
+
+        let export: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(export["encrypted"], false);
+        assert_eq!(export["items"][1]["card"]["brand"], "Visa");
+        assert_eq!(export["items"][1]["card"]["expMonth"], "3");
+        assert_eq!(export["items"][4]["fields"][2]["type"], FIELD_HIDDEN);
+
+        let (items, _) = import_items(&json, None).unwrap();
+        check_round_trip(&items);
+    }
+
+    #[test]
+    fn encrypted_export_round_trip() {
+        let (argon2, _) = export_items(&vault(), Some("export password"), ExportKdf::Argon2id).unwrap();
+        // 600000 PBKDF2 iterations take long in debug builds
+        let (plain, _) = export_items(&vault(), None, ExportKdf::Pbkdf2).unwrap();
+        let settings = Export { kdf_iterations: 1000, ..kdf_settings(ExportKdf::Pbkdf2) };
+        let pbkdf2 = encrypt_export(&plain, "export password", &settings).unwrap().to_string();
+
+        for json in [argon2, pbkdf2] {
+            let export: Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(export["encrypted"], true);
+            assert_eq!(export["passwordProtected"], true);
+            assert!(export["encKeyValidation_DO_NOT_EDIT"].as_str().unwrap().starts_with("2."));
+            assert!(export["data"].as_str().unwrap().starts_with("2."));
+            assert!(!json.contains("octocat"));
+
+            assert_eq!(import_items(&json, Some("wrong")).err().unwrap(), "Wrong password for the Bitwarden export");
+            let (items, _) = import_items(&json, Some("export password")).unwrap();
+            check_round_trip(&items);
+        }
+        assert!(export_items(&vault(), Some(""), ExportKdf::default()).is_err());
+    }
+
+    #[test]
+    fn cipher_strings() {
+        assert_eq!(decrypt_cipher_string(CIPHER_STRING, &ENCRYPTION_KEY, &MAC_KEY).unwrap(), b"KeeManager");
+
+        let encrypted = encrypt_cipher_string(b"round trip", &ENCRYPTION_KEY, &MAC_KEY).unwrap();
+        assert_eq!(decrypt_cipher_string(&encrypted, &ENCRYPTION_KEY, &MAC_KEY).unwrap(), b"round trip");
+        assert_ne!(encrypted, encrypt_cipher_string(b"round trip", &ENCRYPTION_KEY, &MAC_KEY).unwrap());
+
+        assert_eq!(
+            decrypt_cipher_string(CIPHER_STRING, &ENCRYPTION_KEY, &ENCRYPTION_KEY).unwrap_err(),
+            "Bitwarden cipher string MAC mismatch"
+        );
+        let changed = CIPHER_STRING.replace("XmtOmW", "XmtOmX");
+        assert_eq!(decrypt_cipher_string(&changed, &ENCRYPTION_KEY, &MAC_KEY).unwrap_err(), "Bitwarden cipher string MAC mismatch");
+        assert_eq!(
+            decrypt_cipher_string(&CIPHER_STRING.replacen('2', "0", 1), &ENCRYPTION_KEY, &MAC_KEY).unwrap_err(),
+            "Unsupported Bitwarden cipher string type 0"
+        );
+        for invalid in ["", "2.", "2.AAAA|AAAA", "2.ZGVmZ2hpamtsbW5vcHFycw==|XmtOmW+Q66mX3D+kODWQzg==|!"] {
+            assert_eq!(decrypt_cipher_string(invalid, &ENCRYPTION_KEY, &MAC_KEY).unwrap_err(), "Invalid Bitwarden cipher string");
+        }
+    }
+
+    #[test]
+    fn export_keys() {
+        let pbkdf2 = Export { salt: "salt".into(), kdf_type: KDF_PBKDF2, kdf_iterations: 1, ..Default::default() };
+        let expected: Vec<u8> = (0..32)
+            .map(|index| u8::from_str_radix(&"120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"[index * 2..index * 2 + 2], 16).unwrap())
+            .collect();
+        assert_eq!(export_key(&pbkdf2, "password").unwrap().to_vec(), expected);
+
+        // Argon2id takes the SHA-256 hash of the salt and the memory in MiB
+        let argon2 = Export {
+            salt: "salt".into(),
+            kdf_type: KDF_ARGON2ID,
+            kdf_iterations: 2,
+            kdf_memory: Some(1),
+            kdf_parallelism: Some(1),
+            ..Default::default()
+        };
+        let mut expected = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::new(1024, 2, 1, Some(32)).unwrap())
+            .hash_password_into(b"password", &Sha256::digest(b"salt"), &mut expected)
+            .unwrap();
+        assert_eq!(export_key(&argon2, "password").unwrap(), expected);
+
+        let no_iterations = Export { kdf_iterations: 0, ..pbkdf2 };
+        assert!(export_key(&no_iterations, "password").is_err());
+        let unknown = Export { kdf_type: 7, kdf_iterations: 1, ..Default::default() };
+        assert_eq!(export_key(&unknown, "password").unwrap_err(), "Unsupported Bitwarden KDF type 7");
+    }
+
+    #[test]
+    fn excessive_kdf_settings_are_refused() {
+        let pbkdf2 = Export { salt: "salt".into(), kdf_type: KDF_PBKDF2, kdf_iterations: u32::MAX, ..Default::default() };
+        assert_eq!(
+            export_key(&pbkdf2, "password").unwrap_err(),
+            "The iteration count of the key derivation ( 4294967295 ) is more than the 2000000 allowed"
+        );
+
+        let argon2 = |memory, iterations, parallelism| Export {
+            salt: "salt".into(),
+            kdf_type: KDF_ARGON2ID,
+            kdf_iterations: iterations,
+            kdf_memory: Some(memory),
+            kdf_parallelism: Some(parallelism),
+            ..Default::default()
+        };
+        for (export, error) in [
+            (argon2(u32::MAX, 3, 4), "The memory in MiB of the key derivation ( 4294967295 ) is more than the 1024 allowed"),
+            (argon2(64, 11, 4), "The iteration count of the key derivation ( 11 ) is more than the 10 allowed"),
+            (argon2(64, 3, 17), "The parallelism of the key derivation ( 17 ) is more than the 16 allowed"),
+        ] {
+            assert_eq!(export_key(&export, "password").unwrap_err(), error);
+        }
+
+        // A crafted file is refused by the import before any key is derived
+        let json = json!({
+            "encrypted": true,
+            "passwordProtected": true,
+            "salt": "salt",
+            "kdfType": KDF_PBKDF2,
+            "kdfIterations": u32::MAX,
+            "encKeyValidation_DO_NOT_EDIT": "2.AAAA|AAAA|AAAA",
+            "data": "2.AAAA|AAAA|AAAA",
+        });
+        assert!(import_items(&json.to_string(), Some("password")).err().unwrap().contains("more than the 2000000 allowed"));
+    }
+}
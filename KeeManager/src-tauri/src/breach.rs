@@ -0,0 +1,460 @@
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// Header of the binary index, followed by sorted 24 byte records
+// ( 20 byte SHA-1 hash and a big endian 32 bit count )
+const INDEX_MAGIC: &[u8; 8] = b"KMHIBP01";
+const INDEX_RECORD_LENGTH: u64 = 24;
+
+const HASH_HEX_LENGTH: usize = 40;
+const RANGE_PREFIX_LENGTH: usize = 5;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+//
+// Where breached password hashes are looked up, passwords never leave the machine:
+// the hash file is local and the range API only receives the first 5 hex digits of a hash
+//
+pub enum BreachSource {
+    // Pwned Passwords SHA-1 dump ordered by hash ( "HASH:COUNT" lines ) or a binary index of it
+    HashFile(String),
+    // k-anonymity range API ( "<endpoint>/<first 5 hex digits>" returns "SUFFIX:COUNT" lines )
+    RangeApi(String),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BreachedLogin {
+    pub id: String,
+    pub title: String,
+    // How often the password appears in breaches
+    pub count: u64,
+}
+
+//
+// Looks up SHA-1 hashes in one source, range responses are kept
+// so passwords sharing a hash prefix need only one request
+//
+pub struct BreachChecker {
+    source: BreachSource,
+    hash_file: Option<(File, u64, bool)>,
+    ranges: HashMap<String, HashMap<String, u64>>,
+}
+
+impl BreachChecker {
+    pub fn new(source: BreachSource) -> Result<Self, String> {
+        let hash_file = match &source {
+            BreachSource::HashFile(path) => {
+                let mut file = match File::open(path) {
+                    Ok(file) => file,
+                    Err(e) => return Err(format!("Opening hash file {} failed: {}", path, e)),
+                };
+                let length = match file.metadata() {
+                    Ok(metadata) => metadata.len(),
+                    Err(e) => return Err(format!("Reading hash file {} failed: {}", path, e)),
+                };
+
+                let mut magic = [0u8; 8];
+                let is_index = length >= magic.len() as u64
+                    && file.read_exact(&mut magic).is_ok()
+                    && &magic == INDEX_MAGIC;
+                if is_index && !(length - magic.len() as u64).is_multiple_of(INDEX_RECORD_LENGTH) {
+                    return Err(format!("Hash index {} is truncated", path));
+                }
+                Some((file, length, is_index))
+            }
+            BreachSource::RangeApi(endpoint) => {
+                if !endpoint.trim().to_ascii_lowercase().starts_with("http://") {
+                    return Err(format!("Range API endpoint must be a local http:// URL: {}", endpoint));
+                }
+                None
+            }
+        };
+
+        Ok(BreachChecker {
+            source,
+            hash_file,
+            ranges: HashMap::new(),
+        })
+    }
+
+    //
+    // Number of times the password was seen in breaches, 0 if never
+    //
+    pub fn count(&mut self, password: &str) -> Result<u64, String> {
+        let hash: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+
+        match (&mut self.hash_file, &self.source) {
+            (Some((file, length, true)), _) => search_index(file, *length, &hash),
+            (Some((file, length, false)), _) => search_text_file(file, *length, &hex(&hash)),
+            (None, BreachSource::RangeApi(endpoint)) => {
+                let hash = hex(&hash);
+                let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+                if !self.ranges.contains_key(prefix) {
+                    let range = request_range(endpoint, prefix)?;
+                    self.ranges.insert(prefix.to_string(), range);
+                }
+                Ok(self.ranges[prefix].get(suffix).copied().unwrap_or(0))
+            }
+            (None, BreachSource::HashFile(path)) => Err(format!("Hash file {} is not open", path)),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn parse_hex(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != HASH_HEX_LENGTH || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; 20];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+//
+// Splits a "HASH:COUNT" line, the count is optional
+//
+fn parse_line(line: &str) -> Option<(&str, u64)> {
+    let line = line.trim();
+    match line.split_once(':') {
+        Some((hash, count)) => Some((hash, count.trim().parse().unwrap_or(0))),
+        None if !line.is_empty() => Some((line, 1)),
+        None => None,
+    }
+}
+
+//
+// First line starting at or after the position with its start and end offsets
+//
+fn line_at(file: &mut File, position: u64) -> Result<Option<(u64, u64, String)>, String> {
+    let mut start = position;
+    if position > 0 {
+        // Unless the previous byte ends a line the position is inside a line
+        start = position - 1;
+    }
+    if let Err(e) = file.seek(SeekFrom::Start(start)) {
+        return Err(format!("Reading hash file failed: {}", e));
+    }
+
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    if position > 0 {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(length) => start += length as u64,
+            Err(e) => return Err(format!("Reading hash file failed: {}", e)),
+        };
+        line.clear();
+    }
+
+    let length = match reader.read_until(b'\n', &mut line) {
+        Ok(0) => return Ok(None),
+        Ok(length) => length as u64,
+        Err(e) => return Err(format!("Reading hash file failed: {}", e)),
+    };
+
+    Ok(Some((start, start + length, String::from_utf8_lossy(&line).into_owned())))
+}
+
+//
+// Binary search over the byte offsets of a file sorted by hash
+//
+fn search_text_file(file: &mut File, length: u64, hash: &str) -> Result<u64, String> {
+    let mut low = 0;
+    let mut high = length;
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let (end, line) = match line_at(file, middle)? {
+            Some((start, end, line)) if start < high => (end, line),
+            _ => {
+                high = middle;
+                continue;
+            }
+        };
+
+        let (line_hash, count) = match parse_line(&line) {
+            Some(entry) => entry,
+            None => {
+                // Empty line, keep searching after it
+                low = end;
+                continue;
+            }
+        };
+
+        match line_hash.to_ascii_uppercase().as_str().cmp(hash) {
+            Ordering::Equal => return Ok(count),
+            Ordering::Less => low = end,
+            Ordering::Greater => high = middle,
+        }
+    }
+
+    Ok(0)
+}
+
+fn search_index(file: &mut File, length: u64, hash: &[u8; 20]) -> Result<u64, String> {
+    let mut low = 0;
+    let mut high = (length - INDEX_MAGIC.len() as u64) / INDEX_RECORD_LENGTH;
+    let mut record = [0u8; INDEX_RECORD_LENGTH as usize];
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let read = file
+            .seek(SeekFrom::Start(INDEX_MAGIC.len() as u64 + middle * INDEX_RECORD_LENGTH))
+            .and_then(|_| file.read_exact(&mut record));
+        if let Err(e) = read {
+            return Err(format!("Reading hash index failed: {}", e));
+        }
+
+        match record[..20].cmp(&hash[..]) {
+            Ordering::Equal => {
+                return Ok(u32::from_be_bytes([record[20], record[21], record[22], record[23]]) as u64);
+            }
+            Ordering::Less => low = middle + 1,
+            Ordering::Greater => high = middle,
+        }
+    }
+
+    Ok(0)
+}
+
+//
+// Converts a hash dump ordered by hash into the smaller binary index,
+// returns the number of hashes written
+//
+pub fn build_index(source: &str, target: &str) -> Result<u64, String> {
+    let reader = match File::open(source) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => return Err(format!("Opening hash file {} failed: {}", source, e)),
+    };
+    let mut writer = match File::create(target) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => return Err(format!("Creating hash index {} failed: {}", target, e)),
+    };
+
+    let write_error = |e: std::io::Error| format!("Writing hash index {} failed: {}", target, e);
+    writer.write_all(INDEX_MAGIC).map_err(write_error)?;
+
+    let mut previous: Option<[u8; 20]> = None;
+    let mut written = 0;
+    for (number, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Err(format!("Reading hash file {} failed: {}", source, e)),
+        };
+        let (hash, count) = match parse_line(line.trim_start_matches('\u{feff}')) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let hash = match parse_hex(hash) {
+            Some(hash) => hash,
+            None => return Err(format!("Line {} is not a SHA-1 hash", number + 1)),
+        };
+        if previous.is_some_and(|previous| previous >= hash) {
+            return Err(format!("Hash file is not ordered by hash at line {}", number + 1));
+        }
+        previous = Some(hash);
+
+        writer.write_all(&hash).map_err(write_error)?;
+        writer
+            .write_all(&(count.min(u32::MAX as u64) as u32).to_be_bytes())
+            .map_err(write_error)?;
+        written += 1;
+    }
+
+    writer.flush().map_err(write_error)?;
+    Ok(written)
+}
+
+//
+// Minimal HTTP/1.0 GET for the local range endpoint
+//
+fn request_range(endpoint: &str, prefix: &str) -> Result<HashMap<String, u64>, String> {
+    let endpoint = endpoint.trim();
+    let without_scheme = &endpoint["http://".len()..];
+    let (authority, path) = match without_scheme.find('/') {
+        Some(index) => (&without_scheme[..index], &without_scheme[index..]),
+        None => (without_scheme, "/"),
+    };
+    let path = format!("{}/{}", path.trim_end_matches('/'), prefix);
+    let address = if authority.contains(':') && !authority.ends_with(']') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let socket_address = match address.to_socket_addrs().ok().and_then(|mut addresses| addresses.next()) {
+        Some(socket_address) => socket_address,
+        None => return Err(format!("Cannot resolve range API host {}", authority)),
+    };
+    let mut stream = match TcpStream::connect_timeout(&socket_address, HTTP_TIMEOUT) {
+        Ok(stream) => stream,
+        Err(e) => return Err(format!("Connecting to range API {} failed: {}", endpoint, e)),
+    };
+    let _ = stream.set_read_timeout(Some(HTTP_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(HTTP_TIMEOUT));
+
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: text/plain\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    if let Err(e) = stream.write_all(request.as_bytes()) {
+        return Err(format!("Range API request failed: {}", e));
+    }
+
+    let mut response = Vec::new();
+    if let Err(e) = stream.read_to_end(&mut response) {
+        return Err(format!("Reading range API response failed: {}", e));
+    }
+    let response = String::from_utf8_lossy(&response);
+
+    let (head, body) = match response.split_once("\r\n\r\n") {
+        Some(parts) => parts,
+        None => return Err("Range API response has no body".into()),
+    };
+    let status = head.lines().next().unwrap_or("");
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("Range API returned {}", status));
+    }
+
+    let mut range = HashMap::new();
+    for line in body.lines() {
+        if let Some((suffix, count)) = parse_line(line) {
+            // Padding entries have a count of 0
+            if count > 0 {
+                range.insert(suffix.to_ascii_uppercase(), count);
+            }
+        }
+    }
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const ABSENT: [&str; 3] = [
+        "0000000000000000000000000000000000000000",
+        "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+        // SHA-1 of "not in the file"
+        "DE479815B663FC22C499AC2936A4B75A4247A364",
+    ];
+
+    // Hashes of "password0" to "password99" ordered by hash, the count of "passwordN" is N + 1
+    fn hashes() -> Vec<(String, u64)> {
+        let mut hashes: Vec<(String, u64)> = (0..100)
+            .map(|number| (hex(&Sha1::digest(format!("password{}", number).as_bytes())), number + 1))
+            .collect();
+        hashes.sort();
+        hashes
+    }
+
+    fn write_hash_file(name: &str, line_end: &str, trailer: &str) -> String {
+        let lines: Vec<String> = hashes().iter().map(|(hash, count)| format!("{}:{}", hash, count)).collect();
+        let path = std::env::temp_dir().join(format!("keemanager-{}-{}.txt", name, std::process::id()));
+        fs::write(&path, format!("{}{}", lines.join(line_end), trailer)).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn search(path: &str, hash: &str) -> u64 {
+        let mut file = File::open(path).unwrap();
+        let length = file.metadata().unwrap().len();
+        search_text_file(&mut file, length, hash).unwrap()
+    }
+
+    fn assert_finds_every_hash(path: &str) {
+        let hashes = hashes();
+        for index in [0, hashes.len() / 2, hashes.len() - 1] {
+            assert_eq!(search(path, &hashes[index].0), hashes[index].1, "line {}", index);
+        }
+        for (hash, count) in &hashes {
+            assert_eq!(search(path, hash), *count, "{}", hash);
+        }
+        for hash in ABSENT {
+            assert_eq!(search(path, hash), 0, "{}", hash);
+        }
+    }
+
+    #[test]
+    fn text_file_search() {
+        assert_finds_every_hash(&write_hash_file("hibp-lf", "\n", ""));
+    }
+
+    #[test]
+    fn text_file_search_with_crlf_line_ends() {
+        assert_finds_every_hash(&write_hash_file("hibp-crlf", "\r\n", "\r\n"));
+    }
+
+    #[test]
+    fn text_file_search_with_trailing_empty_line() {
+        assert_finds_every_hash(&write_hash_file("hibp-trailing", "\n", "\n\n"));
+        assert_finds_every_hash(&write_hash_file("hibp-trailing-crlf", "\r\n", "\r\n\r\n"));
+    }
+
+    #[test]
+    fn lines_at_positions() {
+        let path = write_hash_file("hibp-lines", "\r\n", "\r\n");
+        let mut file = File::open(&path).unwrap();
+        let hashes = hashes();
+        // "<40 hex digits>:<count>\r\n"
+        let first_length = 40 + 1 + hashes[0].1.to_string().len() as u64 + 2;
+
+        let (start, end, line) = line_at(&mut file, 0).unwrap().unwrap();
+        assert_eq!((start, end), (0, first_length));
+        assert_eq!(parse_line(&line), Some((hashes[0].0.as_str(), hashes[0].1)));
+
+        // A position inside a line or at its start gives the next whole line
+        for position in [1, first_length - 1, first_length] {
+            let (start, _, line) = line_at(&mut file, position).unwrap().unwrap();
+            assert_eq!(start, first_length, "{}", position);
+            assert_eq!(parse_line(&line).unwrap().0, hashes[1].0);
+        }
+
+        let length = file.metadata().unwrap().len();
+        assert!(line_at(&mut file, length).unwrap().is_none());
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let source = write_hash_file("hibp-source", "\r\n", "\r\n");
+        let target = format!("{}.index", source);
+        assert_eq!(build_index(&source, &target).unwrap(), 100);
+
+        let mut file = File::open(&target).unwrap();
+        let length = file.metadata().unwrap().len();
+        assert_eq!(length, INDEX_MAGIC.len() as u64 + 100 * INDEX_RECORD_LENGTH);
+        for (hash, count) in hashes() {
+            assert_eq!(search_index(&mut file, length, &parse_hex(&hash).unwrap()).unwrap(), count, "{}", hash);
+        }
+        for hash in ABSENT {
+            assert_eq!(search_index(&mut file, length, &parse_hex(hash).unwrap()).unwrap(), 0, "{}", hash);
+        }
+
+        // The checker tells the index and the text file apart
+        for path in [&source, &target] {
+            let mut checker = BreachChecker::new(BreachSource::HashFile(path.clone())).unwrap();
+            assert_eq!(checker.count("password41").unwrap(), 42);
+            assert_eq!(checker.count("not in the file").unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn unordered_hash_files_are_refused() {
+        let path = std::env::temp_dir().join(format!("keemanager-hibp-unordered-{}.txt", std::process::id()));
+        fs::write(&path, format!("{}:2\n{}:1\n", ABSENT[1], ABSENT[0])).unwrap();
+        let path = path.to_string_lossy().to_string();
+        assert_eq!(
+            build_index(&path, &format!("{}.index", path)).unwrap_err(),
+            "Hash file is not ordered by hash at line 2"
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::validation;
+
+const MASK_CHARACTER: char = '•';
+// Digits left visible at the end of a masked card number
+const VISIBLE_DIGITS: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CardNetwork {
+    Visa,
+    Mastercard,
+    Amex,
+    Discover,
+    Jcb,
+    UnionPay,
+    #[default]
+    Unknown,
+}
+
+//
+// IIN ( issuer identification number ) ranges of the networks, the first
+// matching range wins so Discover's co-branded UnionPay range comes before
+// the other UnionPay numbers
+//
+const IIN_RANGES: [(u32, u32, CardNetwork); 12] = [
+    (4, 4, CardNetwork::Visa),
+    (51, 55, CardNetwork::Mastercard),
+    (2221, 2720, CardNetwork::Mastercard),
+    (34, 34, CardNetwork::Amex),
+    (37, 37, CardNetwork::Amex),
+    (6011, 6011, CardNetwork::Discover),
+    (644, 649, CardNetwork::Discover),
+    (65, 65, CardNetwork::Discover),
+    (622126, 622925, CardNetwork::Discover),
+    (3528, 3589, CardNetwork::Jcb),
+    (62, 62, CardNetwork::UnionPay),
+    (81, 81, CardNetwork::UnionPay),
+];
+
+impl CardNetwork {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CardNetwork::Visa => "Visa",
+            CardNetwork::Mastercard => "Mastercard",
+            CardNetwork::Amex => "American Express",
+            CardNetwork::Discover => "Discover",
+            CardNetwork::Jcb => "JCB",
+            CardNetwork::UnionPay => "UnionPay",
+            CardNetwork::Unknown => "Unknown",
+        }
+    }
+
+    fn lengths(&self) -> &'static [usize] {
+        match self {
+            CardNetwork::Visa => &[13, 16, 19],
+            CardNetwork::Mastercard => &[16],
+            CardNetwork::Amex => &[15],
+            CardNetwork::Discover | CardNetwork::Jcb | CardNetwork::UnionPay => &[16, 17, 18, 19],
+            CardNetwork::Unknown => &[12, 13, 14, 15, 16, 17, 18, 19],
+        }
+    }
+
+    fn cvv_lengths(&self) -> &'static [usize] {
+        match self {
+            CardNetwork::Amex => &[4],
+            CardNetwork::Unknown => &[3, 4],
+            _ => &[3],
+        }
+    }
+
+    // Not every UnionPay card number has a Luhn check digit
+    fn uses_luhn(&self) -> bool {
+        *self != CardNetwork::UnionPay
+    }
+
+    // Sizes of the digit groups the number is printed in
+    fn groups(&self, length: usize) -> Vec<usize> {
+        match (self, length) {
+            (CardNetwork::Amex, 15) => vec![4, 6, 5],
+            _ => {
+                let mut groups = vec![4; length / 4];
+                let rest = length % 4;
+                if rest > 0 {
+                    groups.push(rest);
+                }
+                groups
+            }
+        }
+    }
+}
+
+//
+// Card number, network and masked number after validation
+//
+#[derive(Serialize, Deserialize)]
+pub struct CardInfo {
+    pub card_number: String,
+    pub network: CardNetwork,
+    pub masked_number: String,
+}
+
+//
+// Card number without the spaces and dashes it is often written with
+//
+pub fn normalize_number(card_number: &str) -> String {
+    card_number
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect()
+}
+
+//
+// Network from the leading digits of the card number
+//
+pub fn detect_network(card_number: &str) -> CardNetwork {
+    let card_number = normalize_number(card_number);
+    if !card_number.chars().all(|c| c.is_ascii_digit()) {
+        return CardNetwork::Unknown;
+    }
+
+    for (low, high, network) in IIN_RANGES {
+        let digits = low.to_string().len();
+        if card_number.len() < digits {
+            continue;
+        }
+        if let Ok(prefix) = card_number[..digits].parse::<u32>() {
+            if (low..=high).contains(&prefix) {
+                return network;
+            }
+        }
+    }
+
+    CardNetwork::Unknown
+}
+
+//
+// Luhn ( mod 10 ) checksum, every second digit from the right is doubled
+//
+pub fn luhn(card_number: &str) -> bool {
+    let mut sum = 0;
+    for (index, c) in card_number.chars().rev().enumerate() {
+        let mut digit = match c.to_digit(10) {
+            Some(digit) => digit,
+            None => return false,
+        };
+        if index % 2 == 1 {
+            digit *= 2;
+            if digit > 9 {
+                digit -= 9;
+            }
+        }
+        sum += digit;
+    }
+    !card_number.is_empty() && sum % 10 == 0
+}
+
+//
+// Validates the card number and returns it without separators with its network
+//
+pub fn validate_number(card_number: &str) -> Result<(String, CardNetwork), String> {
+    let card_number = normalize_number(card_number);
+    if card_number.is_empty() {
+        return Err("Card number is empty".into());
+    }
+    if !card_number.chars().all(|c| c.is_ascii_digit()) {
+        return Err("Card number may only contain digits".into());
+    }
+
+    let network = detect_network(&card_number);
+    if !network.lengths().contains(&card_number.len()) {
+        return Err(format!(
+            "{} card numbers must be {} digits long",
+            network.name(),
+            join_lengths(network.lengths())
+        ));
+    }
+    if network.uses_luhn() && !luhn(&card_number) {
+        return Err("Card number checksum is invalid".into());
+    }
+
+    Ok((card_number, network))
+}
+
+//
+// Optional CVV, its length depends on the network ( 4 digits for American Express )
+//
+pub fn validate_cvv(cvv: &str, network: CardNetwork) -> Result<(), String> {
+    let cvv = cvv.trim();
+    if cvv.is_empty() {
+        return Ok(());
+    }
+
+    if !cvv.chars().all(|c| c.is_ascii_digit()) || !network.cvv_lengths().contains(&cvv.len()) {
+        let lengths = join_lengths(network.cvv_lengths());
+        return Err(match network {
+            CardNetwork::Unknown => format!("CVV must be {} digits", lengths),
+            _ => format!("{} CVVs are {} digits", network.name(), lengths),
+        });
+    }
+    Ok(())
+}
+
+//
+// Refuses a card that expired before the given day
+//
+pub fn check_not_expired(expiry_date: &str, today: NaiveDate) -> Result<(), String> {
+    match validation::parse_card_expiry(expiry_date)? {
+        Some(expires_on) if expires_on < today => Err(format!("Card expired on {}", expiry_date.trim())),
+        _ => Ok(()),
+    }
+}
+
+//
+// Validates number, CVV and expiry of a card the user enters
+//
+pub fn validate_card(card_number: &str, cvv: &str, expiry_date: &str, today: NaiveDate) -> Result<CardInfo, String> {
+    let (card_number, network) = validate_number(card_number)?;
+    validate_cvv(cvv, network)?;
+    check_not_expired(expiry_date, today)?;
+
+    Ok(CardInfo {
+        masked_number: mask_number(&card_number),
+        card_number,
+        network,
+    })
+}
+
+//
+// Card number with all but the last four digits masked,
+// grouped the way the network prints it ( "•••• •••• •••• 1111" )
+//
+pub fn mask_number(card_number: &str) -> String {
+    let card_number = normalize_number(card_number);
+    let length = card_number.chars().count();
+    if length == 0 {
+        return String::new();
+    }
+
+    let visible = if length > VISIBLE_DIGITS { VISIBLE_DIGITS } else { 0 };
+    let masked: Vec<char> = card_number
+        .chars()
+        .enumerate()
+        .map(|(index, c)| if index < length - visible { MASK_CHARACTER } else { c })
+        .collect();
+
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for size in detect_network(&card_number).groups(length) {
+        groups.push(masked[start..start + size].iter().collect::<String>());
+        start += size;
+    }
+    groups.join(" ")
+}
+
+fn join_lengths(lengths: &[usize]) -> String {
+    let lengths: Vec<String> = lengths.iter().map(|length| length.to_string()).collect();
+    match lengths.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Appends the Luhn check digit to the digits
+    fn with_check_digit(digits: &str) -> String {
+        (0..10)
+            .map(|check| format!("{}{}", digits, check))
+            .find(|number| luhn(number))
+            .unwrap()
+    }
+
+    #[test]
+    fn valid_numbers_per_network() {
+        for (number, network) in [
+            ("4111 1111 1111 1111", CardNetwork::Visa),
+            ("4222222222222", CardNetwork::Visa),
+            ("5555-5555-5555-4444", CardNetwork::Mastercard),
+            ("2223003122003222", CardNetwork::Mastercard),
+            ("378282246310005", CardNetwork::Amex),
+            ("6011111111111117", CardNetwork::Discover),
+            ("3530111333300000", CardNetwork::Jcb),
+            // UnionPay numbers do not need a check digit
+            ("6200000000000001", CardNetwork::UnionPay),
+            ("1234567890128", CardNetwork::Unknown),
+        ] {
+            let (normalized, detected) = validate_number(number).unwrap();
+            assert_eq!(normalized, normalize_number(number));
+            assert_eq!(detected, network, "{}", number);
+        }
+    }
+
+    #[test]
+    fn invalid_numbers_per_network() {
+        for (number, error) in [
+            ("", "Card number is empty"),
+            ("4111 1111 1111 111a", "Card number may only contain digits"),
+            ("4111111111111112", "Card number checksum is invalid"),
+            ("41111111111111", "Visa card numbers must be 13, 16 or 19 digits long"),
+            ("555555555555444", "Mastercard card numbers must be 16 digits long"),
+            ("3782822463100050", "American Express card numbers must be 15 digits long"),
+            ("6011111111111", "Discover card numbers must be 16, 17, 18 or 19 digits long"),
+            ("6011111111111118", "Card number checksum is invalid"),
+            ("3530111333300001", "Card number checksum is invalid"),
+            ("62000000000", "UnionPay card numbers must be 16, 17, 18 or 19 digits long"),
+            ("12345678901", "Unknown card numbers must be 12, 13, 14, 15, 16, 17, 18 or 19 digits long"),
+        ] {
+            assert_eq!(validate_number(number).unwrap_err(), error, "{}", number);
+        }
+    }
+
+    #[test]
+    fn iin_range_boundaries() {
+        for (prefix, network) in [
+            ("2220", CardNetwork::Unknown),
+            ("2221", CardNetwork::Mastercard),
+            ("2720", CardNetwork::Mastercard),
+            ("2721", CardNetwork::Unknown),
+            ("622125", CardNetwork::UnionPay),
+            ("622126", CardNetwork::Discover),
+            ("622925", CardNetwork::Discover),
+            ("622926", CardNetwork::UnionPay),
+            ("3527", CardNetwork::Unknown),
+            ("3528", CardNetwork::Jcb),
+            ("3589", CardNetwork::Jcb),
+            ("3590", CardNetwork::Unknown),
+        ] {
+            let number = with_check_digit(&format!("{:0<15}", prefix));
+            assert_eq!(detect_network(&number), network, "{}", prefix);
+            assert_eq!(validate_number(&number).unwrap().1, network, "{}", prefix);
+        }
+    }
+
+    #[test]
+    fn cvv_length_per_network() {
+        for (network, valid, invalid) in [
+            (CardNetwork::Visa, "123", "1234"),
+            (CardNetwork::Mastercard, "123", "12"),
+            (CardNetwork::Amex, "1234", "123"),
+            (CardNetwork::Discover, "123", "1234"),
+            (CardNetwork::Jcb, "123", "1234"),
+            (CardNetwork::UnionPay, "123", "1234"),
+            (CardNetwork::Unknown, "1234", "12345"),
+        ] {
+            assert!(validate_cvv(valid, network).is_ok(), "{:?} {}", network, valid);
+            assert!(validate_cvv(invalid, network).is_err(), "{:?} {}", network, invalid);
+        }
+        assert!(validate_cvv("", CardNetwork::Amex).is_ok());
+        assert!(validate_cvv(" 123 ", CardNetwork::Visa).is_ok());
+        assert!(validate_cvv("12a", CardNetwork::Visa).is_err());
+        assert_eq!(validate_cvv("123", CardNetwork::Amex).unwrap_err(), "American Express CVVs are 4 digits");
+        assert_eq!(validate_cvv("12", CardNetwork::Unknown).unwrap_err(), "CVV must be 3 or 4 digits");
+    }
+
+    #[test]
+    fn masked_numbers() {
+        assert_eq!(mask_number("4111 1111 1111 1111"), "•••• •••• •••• 1111");
+        assert_eq!(mask_number("4111-1111-1111-1111"), "•••• •••• •••• 1111");
+        assert_eq!(mask_number("3782 822463 10005"), "•••• •••••• •0005");
+        assert_eq!(mask_number("4222222222222"), "•••• •••• •222 2");
+        // Numbers too short to keep digits visible are masked completely
+        assert_eq!(mask_number("1234"), "••••");
+        assert_eq!(mask_number("123"), "•••");
+        assert_eq!(mask_number(" "), "");
+        assert_eq!(mask_number(""), "");
+    }
+}
@@ -4,11 +4,38 @@ use std::error::Error;
 use std::path::PathBuf; // Building and handling filesystem paths
 use std::{fs, io};
 
+use crate::expiry::DEFAULT_WARNING_DAYS;
+use crate::generator::PasswordPolicy;
+use crate::strength::DEFAULT_MIN_MASTER_PASSWORD_SCORE;
+
 // Fortmating Config struct to a specific
 // format to be able to send and recieve Config struct
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub database_path: String,
+    // Named password generator policies
+    #[serde(default)]
+    pub password_policies: Vec<PasswordPolicy>,
+    // Strength score ( 0 - 4 ) a new master password needs
+    #[serde(default = "default_min_master_password_score")]
+    pub min_master_password_score: u8,
+    // Pwned Passwords hash file ( or binary index ) for the breach check
+    #[serde(default)]
+    pub breach_hash_file: String,
+    // Local k-anonymity range API used when there is no hash file
+    #[serde(default)]
+    pub breach_range_endpoint: String,
+    // Cards and documents expiring within this many days are reported when the vault is opened
+    #[serde(default = "default_expiry_warning_days")]
+    pub expiry_warning_days: u32,
+}
+
+fn default_min_master_password_score() -> u8 {
+    DEFAULT_MIN_MASTER_PASSWORD_SCORE
+}
+
+fn default_expiry_warning_days() -> u32 {
+    DEFAULT_WARNING_DAYS
 }
 
 impl Config {
@@ -61,6 +88,11 @@ impl Config {
             // ( Default configuration )
             Ok(Config {
                 database_path: String::new(),
+                password_policies: Vec::new(),
+                min_master_password_score: DEFAULT_MIN_MASTER_PASSWORD_SCORE,
+                breach_hash_file: String::new(),
+                breach_range_endpoint: String::new(),
+                expiry_warning_days: DEFAULT_WARNING_DAYS,
             })
         }
     }
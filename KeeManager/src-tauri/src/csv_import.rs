@@ -0,0 +1,460 @@
+use csv::{ReaderBuilder, StringRecord};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::entry::{parse_timestamp, EntryKind};
+use crate::import::ImportItem;
+use crate::totp;
+
+//
+// CSV exports of browsers and other password managers, every row becomes a login.
+// The presets know the column names each exporter writes, the generic mode uses
+// the columns the user mapped to the login fields
+//
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvPreset {
+    Chrome,
+    Firefox,
+    Edge,
+    Safari,
+    LastPass,
+    OnePassword,
+    Dashlane,
+    Generic,
+}
+
+// Login fields a column can be mapped to, the times are unix seconds,
+// unix milliseconds or RFC 3339 and tags are separated by commas
+pub const TARGETS: [&str; 12] = [
+    "title", "username", "password", "url", "notes", "otp", "tags", "group", "favorite", "created", "modified", "accessed",
+];
+
+// Url LastPass gives its secure notes
+const LASTPASS_NOTE_URL: &str = "http://sn";
+
+type Columns = &'static [(&'static str, &'static [&'static str])];
+
+impl CsvPreset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsvPreset::Chrome => "Chrome",
+            CsvPreset::Firefox => "Firefox",
+            CsvPreset::Edge => "Edge",
+            CsvPreset::Safari => "Safari",
+            CsvPreset::LastPass => "LastPass",
+            CsvPreset::OnePassword => "1Password",
+            CsvPreset::Dashlane => "Dashlane",
+            CsvPreset::Generic => "CSV",
+        }
+    }
+
+    //
+    // Login field -> column names of the export ( lowercase ), the first one found is used
+    //
+    fn columns(&self) -> Columns {
+        match self {
+            CsvPreset::Chrome | CsvPreset::Edge => &[
+                ("title", &["name"]),
+                ("url", &["url"]),
+                ("username", &["username"]),
+                ("password", &["password"]),
+                ("notes", &["note"]),
+            ],
+            CsvPreset::Firefox => &[
+                ("url", &["url"]),
+                ("username", &["username"]),
+                ("password", &["password"]),
+                ("created", &["timecreated"]),
+                ("modified", &["timepasswordchanged"]),
+                ("accessed", &["timelastused"]),
+            ],
+            CsvPreset::Safari => &[
+                ("title", &["title"]),
+                ("url", &["url"]),
+                ("username", &["username"]),
+                ("password", &["password"]),
+                ("notes", &["notes"]),
+                ("otp", &["otpauth"]),
+            ],
+            CsvPreset::LastPass => &[
+                ("title", &["name"]),
+                ("url", &["url"]),
+                ("username", &["username"]),
+                ("password", &["password"]),
+                ("notes", &["extra"]),
+                ("otp", &["totp"]),
+                ("group", &["grouping"]),
+                ("favorite", &["fav"]),
+            ],
+            CsvPreset::OnePassword => &[
+                ("title", &["title"]),
+                ("url", &["url", "website", "urls"]),
+                ("username", &["username"]),
+                ("password", &["password"]),
+                ("notes", &["notes", "notesplain"]),
+                ("otp", &["otpauth", "one-time password"]),
+                ("tags", &["tags"]),
+                ("favorite", &["favorite"]),
+            ],
+            CsvPreset::Dashlane => &[
+                ("title", &["title"]),
+                ("url", &["url"]),
+                ("username", &["username", "login", "email"]),
+                ("password", &["password"]),
+                ("notes", &["note"]),
+                ("otp", &["otpsecret", "otpurl"]),
+                ("group", &["category"]),
+            ],
+            CsvPreset::Generic => &[],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CsvOptions {
+    pub preset: CsvPreset,
+    // Generic mode: login field -> column name
+    #[serde(default)]
+    pub mapping: HashMap<String, String>,
+    // Encoding label ( like "windows-1252" ) for files without a byte order mark,
+    // UTF-8 is assumed when not set and the file is valid UTF-8
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+//
+// Decodes the file, a byte order mark wins over the encoding that was asked for
+//
+fn decode(bytes: &[u8], encoding: Option<&str>) -> Result<String, String> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return Ok(text.into_owned());
+    }
+
+    let encoding = match encoding.map(str::trim).filter(|label| !label.is_empty()) {
+        Some(label) => match Encoding::for_label(label.as_bytes()) {
+            Some(encoding) => encoding,
+            None => return Err(format!("Unknown encoding {}", label)),
+        },
+        // Older exports made on Windows are often Windows-1252
+        None if std::str::from_utf8(bytes).is_ok() => UTF_8,
+        None => WINDOWS_1252,
+    };
+    let (text, malformed) = encoding.decode_without_bom_handling(bytes);
+    if malformed {
+        return Err(format!("The file is not valid {}, pick another encoding", encoding.name()));
+    }
+    Ok(text.into_owned())
+}
+
+//
+// Delimiter used the most on the header line, exports from some locales use
+// semicolons and a few tools write tab separated files
+//
+fn detect_delimiter(text: &str) -> u8 {
+    let header = text.lines().next().unwrap_or("");
+    [b',', b';', b'\t']
+        .into_iter()
+        .max_by_key(|delimiter| (header.bytes().filter(|c| c == delimiter).count(), *delimiter == b','))
+        .unwrap_or(b',')
+}
+
+fn read_records(path: &str, encoding: Option<&str>) -> Result<(StringRecord, Vec<StringRecord>), String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(format!("Reading {} failed: {}", path, e)),
+    };
+    let text = decode(&bytes, encoding)?;
+
+    let mut reader = ReaderBuilder::new()
+        .delimiter(detect_delimiter(&text))
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return Err(format!("Invalid CSV file: {}", e)),
+    };
+    if headers.iter().all(|header| header.trim().is_empty()) {
+        return Err("The CSV file has no header line".into());
+    }
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        match record {
+            Ok(record) => records.push(record),
+            Err(e) => return Err(format!("Invalid CSV file: {}", e)),
+        }
+    }
+    Ok((headers, records))
+}
+
+//
+// Column names of the file, the front end maps them to login fields in the generic mode
+//
+pub fn read_columns(path: &str, encoding: Option<&str>) -> Result<Vec<String>, String> {
+    let (headers, _) = read_records(path, encoding)?;
+    Ok(headers.iter().map(|header| header.trim().to_string()).collect())
+}
+
+//
+// Column index of every mapped login field
+//
+fn resolve_columns(headers: &StringRecord, options: &CsvOptions) -> Result<HashMap<&'static str, usize>, String> {
+    let find = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name.trim()));
+
+    let mut columns = HashMap::new();
+    if options.preset == CsvPreset::Generic {
+        for (field, column) in &options.mapping {
+            if column.trim().is_empty() {
+                continue;
+            }
+            let target = match TARGETS.iter().find(|target| *target == field) {
+                Some(target) => *target,
+                None => return Err(format!("Unknown login field {}", field)),
+            };
+            match find(column) {
+                Some(index) => columns.insert(target, index),
+                None => return Err(format!("Column {} not found in the file", column)),
+            };
+        }
+        if columns.is_empty() {
+            return Err("Map at least one column to a login field".into());
+        }
+        return Ok(columns);
+    }
+
+    for (target, names) in options.preset.columns() {
+        if let Some(index) = names.iter().find_map(|name| find(name)) {
+            columns.insert(*target, index);
+        }
+    }
+    if !columns.contains_key("password") {
+        return Err(format!("No password column, this does not look like a {} export", options.preset.name()));
+    }
+    Ok(columns)
+}
+
+//
+// Reads a CSV export with the given preset or column mapping
+//
+pub fn read_file(path: &str, options: &CsvOptions) -> Result<(Vec<ImportItem>, Vec<String>), String> {
+    let (headers, records) = read_records(path, options.encoding.as_deref())?;
+    let columns = resolve_columns(&headers, options)?;
+
+    let mut items = Vec::new();
+    let mut warnings = Vec::new();
+    for record in &records {
+        if let Some(item) = import_row(record, &columns, options.preset, &mut warnings) {
+            items.push(item);
+        }
+    }
+    Ok((items, warnings))
+}
+
+// Unix seconds from seconds, milliseconds ( Firefox ) or an RFC 3339 timestamp
+fn parse_time(value: &str) -> Option<i64> {
+    match value.trim().parse::<i64>() {
+        Ok(time) if time > 100_000_000_000 => Some(time / 1000),
+        Ok(time) => Some(time),
+        Err(_) => parse_timestamp(value).ok().flatten(),
+    }
+}
+
+// Host of a url, the title of logins exported without one
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+    host.rsplit('@').next().unwrap_or(host)
+}
+
+fn import_row(
+    record: &StringRecord,
+    columns: &HashMap<&'static str, usize>,
+    preset: CsvPreset,
+    warnings: &mut Vec<String>,
+) -> Option<ImportItem> {
+    let value = |field: &str| columns.get(field).and_then(|index| record.get(*index)).unwrap_or("");
+    let line = record.position().map(|position| position.line()).unwrap_or(0);
+
+    let url = value("url").trim();
+    let username = value("username").trim();
+    let password = value("password");
+    let notes = value("notes");
+
+    // Firefox keeps its sync account among the logins
+    if preset == CsvPreset::Firefox && url.starts_with("chrome://") {
+        warnings.push(format!("Line {}: Firefox internal login {} not imported", line, url));
+        return None;
+    }
+
+    let mut title = value("title").trim().to_string();
+    if title.is_empty() {
+        title = if !url.is_empty() { url_host(url).to_string() } else { username.to_string() };
+    }
+
+    let mut item = if preset == CsvPreset::LastPass && url == LASTPASS_NOTE_URL {
+        let mut item = ImportItem::new(EntryKind::Note);
+        item.set("content", notes);
+        item
+    } else {
+        if url.is_empty() && username.is_empty() && password.is_empty() {
+            if record.iter().any(|value| !value.trim().is_empty()) {
+                warnings.push(format!("Line {}: no url, username or password, not imported", line));
+            }
+            return None;
+        }
+        let mut item = ImportItem::new(EntryKind::Login);
+        item.set("username", username);
+        item.set("password", password);
+        item.set("url", url);
+        item.set("notes", notes);
+
+        let otp = value("otp").trim();
+        if otp.is_empty() || totp::parse(otp).is_ok() {
+            item.set("otp", otp);
+        } else {
+            item.set("otp", "");
+            let notes = if notes.is_empty() { format!("OTP: {}", otp) } else { format!("{}\n\nOTP: {}", notes, otp) };
+            item.set("notes", notes);
+            warnings.push(format!("Line {}: {}: invalid one-time password kept in the notes", line, title));
+        }
+        item
+    };
+
+    item.set("id", "");
+    item.set("title", title);
+    let favorite = value("favorite").trim().to_ascii_lowercase();
+    item.set("favorite", matches!(favorite.as_str(), "1" | "true" | "yes" | "y" | "x"));
+    let tags: Vec<String> = value("tags")
+        .split([',', ';'])
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect();
+    item.set("tags", tags);
+    item.group = value("group").trim().to_string();
+    item.created = parse_time(value("created"));
+    item.modified = parse_time(value("modified"));
+    item.accessed = parse_time(value("accessed"));
+
+    Some(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn write_csv(name: &str, content: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("keemanager-{}-{}.csv", name, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn options(preset: CsvPreset) -> CsvOptions {
+        CsvOptions { preset, mapping: HashMap::new(), encoding: None }
+    }
+
+    fn text<'a>(item: &'a ImportItem, field: &str) -> &'a str {
+        item.entry.get(field).and_then(Value::as_str).unwrap_or("")
+    }
+
+    #[test]
+    fn decodes_byte_order_marks_and_encodings() {
+        assert_eq!(decode("\u{feff}name,café".as_bytes(), None).unwrap(), "name,café");
+
+        let mut utf16le = vec![0xFF, 0xFE];
+        utf16le.extend("name,café".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(decode(&utf16le, None).unwrap(), "name,café");
+        let mut utf16be = vec![0xFE, 0xFF];
+        utf16be.extend("name,café".encode_utf16().flat_map(u16::to_be_bytes));
+        // The byte order mark wins over the encoding asked for
+        assert_eq!(decode(&utf16be, Some("windows-1252")).unwrap(), "name,café");
+
+        // Not UTF-8 without an encoding, read as Windows-1252
+        assert_eq!(decode(b"name,caf\xe9 \x80", None).unwrap(), "name,café €");
+        assert_eq!(decode(b"name,caf\xe9", Some("latin1")).unwrap(), "name,café");
+        assert_eq!(decode(b"name,caf\xe9", Some("utf-8")).unwrap_err(), "The file is not valid UTF-8, pick another encoding");
+        assert_eq!(decode(b"name", Some("klingon")).unwrap_err(), "Unknown encoding klingon");
+    }
+
+    #[test]
+    fn detects_delimiters() {
+        assert_eq!(detect_delimiter("name,url,username,password\na;b;c;d;e"), b',');
+        assert_eq!(detect_delimiter("name;url;username;password\n"), b';');
+        assert_eq!(detect_delimiter("name\turl\tusername\tpassword"), b'\t');
+        // Commas win a tie, an empty file is read with commas
+        assert_eq!(detect_delimiter("name;url,password"), b',');
+        assert_eq!(detect_delimiter(""), b',');
+    }
+
+    #[test]
+    fn finds_preset_columns() {
+        let headers = StringRecord::from(vec!["Name", " URL ", "username", "Password", "note"]);
+        let columns = resolve_columns(&headers, &options(CsvPreset::Chrome)).unwrap();
+        assert_eq!(columns.len(), 5);
+        assert_eq!((columns["title"], columns["url"], columns["password"], columns["notes"]), (0, 1, 3, 4));
+
+        // 1Password takes the first of its names for a field the file has
+        let headers = StringRecord::from(vec!["Title", "Website", "Username", "Password", "notesPlain"]);
+        let columns = resolve_columns(&headers, &options(CsvPreset::OnePassword)).unwrap();
+        assert_eq!((columns["url"], columns["notes"]), (1, 4));
+
+        let headers = StringRecord::from(vec!["name", "url", "username"]);
+        assert_eq!(
+            resolve_columns(&headers, &options(CsvPreset::LastPass)).unwrap_err(),
+            "No password column, this does not look like a LastPass export"
+        );
+    }
+
+    #[test]
+    fn maps_generic_columns() {
+        let headers = StringRecord::from(vec!["Site", "Login", "Secret"]);
+        let mut generic = options(CsvPreset::Generic);
+        generic.mapping = HashMap::from([("url".to_string(), "site".to_string()), ("password".to_string(), "Secret".to_string())]);
+        let columns = resolve_columns(&headers, &generic).unwrap();
+        assert_eq!((columns["url"], columns["password"], columns.len()), (0, 2, 2));
+
+        generic.mapping = HashMap::from([("pin".to_string(), "Secret".to_string())]);
+        assert_eq!(resolve_columns(&headers, &generic).unwrap_err(), "Unknown login field pin");
+        generic.mapping = HashMap::from([("password".to_string(), "Pass".to_string())]);
+        assert_eq!(resolve_columns(&headers, &generic).unwrap_err(), "Column Pass not found in the file");
+        generic.mapping = HashMap::new();
+        assert_eq!(resolve_columns(&headers, &generic).unwrap_err(), "Map at least one column to a login field");
+    }
+
+    #[test]
+    fn reads_quoted_values_and_embedded_newlines() {
+        let path = write_csv(
+            "chrome",
+            b"name,url,username,password,note\r\n\
+              \"Mail, work\",https://mail.example.com/login,\"al\"\"ice\",\"p,w;\"\"x\",\"first line\r\nsecond line\"\r\n\
+              ,https://user@shop.example.com:8443/cart,bob,secret,\r\n\
+              ,,,,\r\n",
+        );
+        let (items, warnings) = read_file(&path, &options(CsvPreset::Chrome)).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].kind, EntryKind::Login);
+        assert_eq!(text(&items[0], "title"), "Mail, work");
+        assert_eq!(text(&items[0], "username"), "al\"ice");
+        assert_eq!(text(&items[0], "password"), "p,w;\"x");
+        assert_eq!(text(&items[0], "notes"), "first line\r\nsecond line");
+        // Logins without a name are titled by the host of their url
+        assert_eq!(text(&items[1], "title"), "shop.example.com:8443");
+    }
+
+    #[test]
+    fn reads_windows_1252_semicolon_files() {
+        let path = write_csv("lastpass", b"url;username;password;extra;name;grouping;fav\nhttp://sn;;;Caf\xe9 code 1234;Door;Home;1\n");
+        let (items, _) = read_file(&path, &options(CsvPreset::LastPass)).unwrap();
+        assert_eq!(items[0].kind, EntryKind::Note);
+        assert_eq!(text(&items[0], "content"), "Café code 1234");
+        assert_eq!(items[0].group, "Home");
+        assert_eq!(items[0].entry["favorite"], true);
+    }
+}
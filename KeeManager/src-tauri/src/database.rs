@@ -1,18 +1,39 @@
-use chrono::DateTime;
+use uuid::Uuid;
 use once_cell::sync::OnceCell;
-use rusqlite::{params, Connection, Result};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection, Result};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
+    breach::{BreachChecker, BreachSource, BreachedLogin},
     encode, 
     hash,
     encryption, 
+    expiry::{self, ExpiringItem},
+    health::{self, HealthLogin, HealthReport},
+    import::{ImportAttachment, ImportItem, ImportOptions, ImportPreview, ImportResult, PreviewItem},
     key_derivation,
-    entry::{CreditCardEntry, IdentityEntry, LoginEntry, NoteEntry},
+    plain_export::PlainEntry,
+    totp::{self, OtpCode, OtpKind},
+    entry::{
+        format_timestamp, parse_timestamp, with_entry_type, AttachmentInfo, CustomEntry, Entry, EntryKind, FieldDescriptor,
+        FieldType, CreditCardEntry, IdentityEntry, LoginEntry, PasswordHistoryItem, TagCount, TimestampField,
+    },
+    placeholder::{self, PlaceholderContext},
+    reference::{self, ReferencingEntry},
+    strength::{self, LoginStrength},
+    template::{self, Template},
+    validation,
 };
 
+// Largest file that can be attached to an entry
+pub const MAX_ATTACHMENT_SIZE: usize = 32 * 1024 * 1024;
+
 static mut DATABASE_INSTANCE: OnceCell<Arc<Mutex<Database>>> = OnceCell::new();
 
 pub struct Database {
@@ -35,6 +56,13 @@ impl Database {
                     return Err(e);
                 }
             };
+
+            // Adds the tables and columns missing from databases created by older versions
+            if !Self::create_tables(&conn) {
+                eprintln!("DB_get_instance: table upgrade failure");
+                return Err(rusqlite::Error::UnwindingPanic);
+            }
+
             Ok(Database {
                 conn,
                 master_key: master_key.to_string(),
@@ -104,6 +132,13 @@ impl Database {
         }
     }
 
+    //
+    // Whether a vault is open, get_instance returns it whatever path is asked for
+    //
+    pub fn has_instance() -> bool {
+        unsafe { (*std::ptr::addr_of!(DATABASE_INSTANCE)).get().is_some() }
+    }
+
     fn set_master_key_hash(conn: &Connection, master_key: &str) -> bool {
         let decoded_master_key = match encode::decode_base64(master_key)
         {
@@ -192,10 +227,26 @@ impl Database {
             Ok(conn) => conn,
             Err(e) => {
                 eprintln!("Check Master Key: connection failure: {}", e);
+                let _ = encryption::encrypt_database(db_path, master_key);
                 return false;
             }
         };
 
+        let matches = Self::master_key_hash_matches(&conn, master_key);
+        drop(conn);
+        let _ = encryption::encrypt_database(db_path, master_key);
+        matches
+    }
+
+    //
+    // Checks a master key entered again while the vault is open, the database
+    // file is decrypted then so the stored hash is read through the open connection
+    //
+    pub fn confirm_master_key(&self, master_key: &str) -> bool {
+        Self::master_key_hash_matches(&self.conn, master_key)
+    }
+
+    fn master_key_hash_matches(conn: &Connection, master_key: &str) -> bool {
         // Prepare the SQL statement
         let mut stmt = match conn
             .prepare("SELECT hashedMasterKey FROM MasterKey WHERE id = 1;")
@@ -237,13 +288,8 @@ impl Database {
 
             let encoded_hashed_master_key =
             encode::encode_base64(hashed_master_key.as_bytes().to_vec());
-            drop(rows);
-            drop(stmt);
-            drop(conn);
-            let _ = encryption::encrypt_database(db_path, master_key);
             return saved_hashed_master_key == encoded_hashed_master_key;
         } else {
-            let _ = encryption::encrypt_database(db_path, master_key);
             return false;
         }
     }
@@ -268,78 +314,170 @@ impl Database {
             } 
         };
 
+        // Tag links are created first, upgrading an entry table moves them to the entry UUIDs
+        if !Self::create_tag_tables(conn) {
+            return false;
+        }
+
+        for kind in EntryKind::ALL {
+            if !with_entry_type!(kind, T => Self::create_entry_table::<T>(conn)) {
+                return false;
+            }
+        }
+
+        if !Self::create_template_table(conn) {
+            return false;
+        }
+
+        if !Self::create_password_history_table(conn) {
+            return false;
+        }
+
+        Self::create_attachment_table(conn)
+    }
+
+    //
+    // Creates the table of an entry type from its field descriptors,
+    // columns added to a type later on are added to existing tables
+    // ( this also brings databases created by older versions up to date )
+    //
+    fn create_entry_table<T: Entry>(conn: &Connection) -> bool {
+        let columns: String = T::FIELDS
+            .iter()
+            .map(|field| format!("{} {},\n", field.name, Self::column_definition(field)))
+            .collect();
+
         match conn.execute(
-            "CREATE TABLE IF NOT EXISTS LoginEntries (
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (
                     id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    title TEXT NOT NULL,
-                    username TEXT NOT NULL,
-                    password TEXT NOT NULL,
+                    uuid TEXT NOT NULL UNIQUE,
+                    {}
                     salt TEXT NOT NULL UNIQUE,
-                    url TEXT,
-                    notes TEXT,
-                    last_updated DATETIME
+                    favorite INTEGER NOT NULL DEFAULT 0,
+                    created INTEGER,
+                    last_updated DATETIME,
+                    accessed INTEGER,
+                    expires INTEGER
                 )
-            ",
+                ",
+                T::TABLE, columns
+            ),
             [],
         ){
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Create tables: SQL LoginEntries table failure: {}", e);
+                eprintln!("Create tables: SQL {} table failure: {}", T::TABLE, e);
                 return false;
             } 
         };
 
+        for field in T::FIELDS {
+            if !Self::add_column_if_missing(conn, T::TABLE, field.name, Self::column_definition(field)) {
+                return false;
+            }
+        }
+
+        if !Self::add_column_if_missing(conn, T::TABLE, "favorite", "INTEGER NOT NULL DEFAULT 0") {
+            return false;
+        }
+
+        if !Self::add_timestamp_columns(conn, T::TABLE) {
+            return false;
+        }
+
+        Self::add_uuid_column(conn, T::TABLE, Some(T::KIND.as_str()))
+    }
+
+    //
+    // Timestamps are unix seconds, last_updated holds the modification time,
+    // entries from before the other timestamps existed use it for all of them
+    //
+    fn add_timestamp_columns(conn: &Connection, table: &str) -> bool {
+        for column in ["created", "accessed", "expires"] {
+            if !Self::add_column_if_missing(conn, table, column, "INTEGER") {
+                return false;
+            }
+        }
+
+        match conn.execute(
+            &format!(
+                "UPDATE {} SET created = COALESCE(created, last_updated), accessed = COALESCE(accessed, last_updated)
+                 WHERE created IS NULL OR accessed IS NULL",
+                table
+            ),
+            [],
+        ){
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Upgrade tables: {} timestamp upgrade failed: {}", table, e);
+                return false;
+            }
+        };
+
+        true
+    }
+
+    // Encrypted values are always stored as text
+    fn column_definition(field: &FieldDescriptor) -> &'static str {
+        match (field.encrypted, field.field_type) {
+            (false, FieldType::Integer) => "INTEGER NOT NULL DEFAULT 0",
+            _ => "TEXT NOT NULL DEFAULT ''",
+        }
+    }
+
+    //
+    // Tag names are encrypted like any other entry field,
+    // entries are linked to tags through EntryTags ( many-to-many )
+    //
+    fn create_tag_tables(conn: &Connection) -> bool {
         match conn.execute(
-            "CREATE TABLE IF NOT EXISTS CreditCardEntries (
+            "CREATE TABLE IF NOT EXISTS Tags (
                     id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    title TEXT NOT NULL,
-                    card_number TEXT NOT NULL,
-                    expiry_date TEXT NOT NULL,
-                    cardholder_name TEXT NOT NULL,
-                    cvv TEXT NOT NULL,
-                    salt TEXT NOT NULL UNIQUE,
-                    last_updated DATETIME
+                    name TEXT NOT NULL,
+                    salt TEXT NOT NULL UNIQUE
                 )
             ",
             [],
         ){
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Create tables: SQL CreditCardEntries table failure: {}", e);
+                eprintln!("Create tables: SQL Tags table failure: {}", e);
                 return false;
             } 
         };
 
         match conn.execute(
-            "CREATE TABLE IF NOT EXISTS NoteEntries (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    title TEXT NOT NULL,
-                    content TEXT NOT NULL,
-                    salt TEXT NOT NULL UNIQUE,
-                    last_updated DATETIME
+            "CREATE TABLE IF NOT EXISTS EntryTags (
+                    entry_type TEXT NOT NULL,
+                    entry_id TEXT NOT NULL,
+                    tag_id INTEGER NOT NULL,
+                    PRIMARY KEY (entry_type, entry_id, tag_id)
                 )
             ",
             [],
         ){
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Create tables: SQL NoteEntries table failure: {}", e);
+                eprintln!("Create tables: SQL EntryTags table failure: {}", e);
                 return false;
             } 
         };
 
+        true
+    }
+
+    //
+    // Template names and field definitions are encrypted,
+    // the fields are stored as one JSON value
+    //
+    fn create_template_table(conn: &Connection) -> bool {
         match conn.execute(
-            "CREATE TABLE IF NOT EXISTS IdentityEntries (
+            "CREATE TABLE IF NOT EXISTS Templates (
                     id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    title TEXT NOT NULL,
-                    full_name TEXT NOT NULL,
-                    date_of_birth TEXT NOT NULL,
-                    nationality TEXT NOT NULL,
-                    identification_number TEXT NOT NULL UNIQUE,
-                    issue_date TEXT NOT NULL,
-                    expiry_date TEXT NOT NULL,
-                    issuer TEXT NOT NULL,
-                    notes TEXT,
+                    uuid TEXT NOT NULL UNIQUE,
+                    name TEXT NOT NULL,
+                    fields TEXT NOT NULL,
                     salt TEXT NOT NULL UNIQUE,
                     last_updated DATETIME
                 )
@@ -348,80 +486,75 @@ impl Database {
         ){
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Create tables: SQL IdentityEntries table failure: {}", e);
+                eprintln!("Create tables: SQL Templates table failure: {}", e);
                 return false;
             } 
         };
 
-        true
+        Self::add_uuid_column(conn, "Templates", None)
     }
 
-    pub fn add_login(&self, entry: LoginEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt)
-        {
-            Ok(derived_key) => derived_key,
-            Err(e) => {
-                eprintln!("Add login: key derivation failed: {}", e);
-                return false;
-            }
-        };
+    //
+    // Entries are identified by a random UUID so they keep their identity across vaults,
+    // rows of older tables get one and their tag links are moved from the integer id to it
+    //
+    fn add_uuid_column(conn: &Connection, table: &str, entry_type: Option<&str>) -> bool {
+        // SQLite can not add a UNIQUE column, the unique index is created below
+        if !Self::add_column_if_missing(conn, table, "uuid", "TEXT") {
+            return false;
+        }
 
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key)
-        {
-            Ok(encrypted_title) => encrypted_title,
-            Err(e) => {
-                eprintln!("Add login: title encryption failed: {}", e);
-                return false;
-            } 
-        };
+        let ids = {
+            let mut stmt = match conn.prepare(&format!("SELECT id FROM {} WHERE uuid IS NULL OR uuid = ''", table)) {
+                Ok(stmt) => stmt,
+                Err(e) => {
+                    eprintln!("Upgrade tables: SQL statement failure: {}", e);
+                    return false;
+                }
+            };
 
-        let encrypted_username =
-            match encryption::encrypt_gcm(&entry.username, &derived_key){
-            Ok(encrypted_username) => encrypted_username,
-            Err(e) => {
-                eprintln!("Add login: username encryption failed: {}", e);
-                return false;
-            } 
-        };
-        let encrypted_password =
-            match encryption::encrypt_gcm(&entry.password, &derived_key){
-                Ok(encrypted_password) => encrypted_password,
+            let ids = match stmt.query_map([], |row| row.get::<_, i64>(0)) {
+                Ok(ids) => ids.filter_map(|id| id.ok()).collect::<Vec<i64>>(),
                 Err(e) => {
-                    eprintln!("Add login: password encryption failed: {}", e);
+                    eprintln!("Upgrade tables: SQL query failure: {}", e);
                     return false;
-                } 
+                }
             };
-        let encrypted_url = match encryption::encrypt_gcm(&entry.url, &derived_key){
-            Ok(encrypted_url) => encrypted_url,
-            Err(e) => {
-                eprintln!("Add login: url encryption failed: {}", e);
-                return false;
-            } 
-        };
-        let encrypted_notes = match encryption::encrypt_gcm(&entry.notes, &derived_key){
-            Ok(encrypted_notes) => encrypted_notes,
-            Err(e) => {
-                eprintln!("Add login: note encryption failed: {}", e);
-                return false;
-            } 
+            ids
         };
 
-        match self.conn.execute(
-            "INSERT INTO LoginEntries (title, username, password, salt, url, notes, last_updated) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![
-                encrypted_title,
-                encrypted_username,
-                encrypted_password,
-                salt,
-                encrypted_url,
-                encrypted_notes,
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-            ],
+        for id in ids {
+            let uuid = Uuid::new_v4().to_string();
+
+            match conn.execute(&format!("UPDATE {} SET uuid = ?1 WHERE id = ?2", table), params![uuid, id]) {
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("Upgrade tables: setting {} uuid failed: {}", table, e);
+                    return false;
+                }
+            };
+
+            if let Some(entry_type) = entry_type {
+                match conn.execute(
+                    "UPDATE EntryTags SET entry_id = ?1 WHERE entry_type = ?2 AND entry_id = ?3",
+                    params![uuid, entry_type, id.to_string()],
+                ){
+                    Ok(_) => (),
+                    Err(e) => {
+                        eprintln!("Upgrade tables: moving {} tags failed: {}", table, e);
+                        return false;
+                    }
+                };
+            }
+        }
+
+        match conn.execute(
+            &format!("CREATE UNIQUE INDEX IF NOT EXISTS {}_uuid ON {} (uuid)", table, table),
+            [],
         ){
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Add login: insertion failed: {}", e);
+                eprintln!("Upgrade tables: {} uuid index failure: {}", table, e);
                 return false;
             }
         };
@@ -429,72 +562,109 @@ impl Database {
         true
     }
 
-    pub fn update_login(&self, entry: LoginEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
-            Ok(derived_key) => derived_key,
-            Err(e) => {
-                eprintln!("Update login: key derivation failed: {}", e);
-                return false;
+    //
+    // Keeps a valid UUID that was given ( imported entries ) unless
+    // it is already used in the table, otherwise creates a new one
+    //
+    fn entry_uuid(&self, table: &str, id: &str) -> String {
+        if let Ok(uuid) = Uuid::parse_str(id.trim()) {
+            let uuid = uuid.to_string();
+            let used = self.conn.query_row(
+                &format!("SELECT COUNT(*) FROM {} WHERE uuid = ?1", table),
+                params![uuid],
+                |row| row.get::<_, i64>(0),
+            );
+            if let Ok(0) = used {
+                return uuid;
             }
-        };
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key){
-            Ok(encrypted_title) => encrypted_title,
+        }
+
+        Uuid::new_v4().to_string()
+    }
+
+    //
+    // Passwords replaced on login entries, each one encrypted with its own salt
+    //
+    fn create_password_history_table(conn: &Connection) -> bool {
+        match conn.execute(
+            "CREATE TABLE IF NOT EXISTS PasswordHistory (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    entry_id TEXT NOT NULL,
+                    password TEXT NOT NULL,
+                    salt TEXT NOT NULL UNIQUE,
+                    replaced INTEGER NOT NULL
+                )
+            ",
+            [],
+        ){
+            Ok(_) => (),
             Err(e) => {
-                eprintln!("Update login: title encryption failed: {}", e);
+                eprintln!("Create tables: SQL PasswordHistory table failure: {}", e);
                 return false;
             } 
         };
 
-        let encrypted_username =
-            match encryption::encrypt_gcm(&entry.username, &derived_key){
-            Ok(encrypted_username) => encrypted_username,
+        true
+    }
+
+    //
+    // Files attached to entries, name and data are encrypted with the row's salt
+    // and the data is stored base64 encoded
+    //
+    fn create_attachment_table(conn: &Connection) -> bool {
+        match conn.execute(
+            "CREATE TABLE IF NOT EXISTS Attachments (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    uuid TEXT NOT NULL UNIQUE,
+                    entry_type TEXT NOT NULL,
+                    entry_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    data TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    salt TEXT NOT NULL UNIQUE,
+                    created INTEGER NOT NULL
+                )
+            ",
+            [],
+        ){
+            Ok(_) => (),
             Err(e) => {
-                eprintln!("Update login: username encryption failed: {}", e);
+                eprintln!("Create tables: SQL Attachments table failure: {}", e);
                 return false;
             } 
         };
-        let encrypted_password =
-            match encryption::encrypt_gcm(&entry.password, &derived_key){
-                Ok(encrypted_password) => encrypted_password,
-                Err(e) => {
-                    eprintln!("Update login: password encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_url = match encryption::encrypt_gcm(&entry.url, &derived_key){
-            Ok(encrypted_url) => encrypted_url,
+
+        true
+    }
+
+    fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> bool {
+        let mut stmt = match conn.prepare(&format!("PRAGMA table_info({})", table)) {
+            Ok(stmt) => stmt,
             Err(e) => {
-                eprintln!("Update login: url encryption failed: {}", e);
+                eprintln!("Upgrade tables: SQL statement failure: {}", e);
                 return false;
-            } 
+            }
         };
-        let encrypted_notes = match encryption::encrypt_gcm(&entry.notes, &derived_key){
-            Ok(encrypted_notes) => encrypted_notes,
+
+        let columns = match stmt.query_map([], |row| row.get::<_, String>(1)) {
+            Ok(columns) => columns.filter_map(|column| column.ok()).collect::<Vec<String>>(),
             Err(e) => {
-                eprintln!("Update login: note encryption failed: {}", e);
+                eprintln!("Upgrade tables: SQL query failure: {}", e);
                 return false;
-            } 
+            }
         };
 
-        match self.conn.execute(
-            "UPDATE LoginEntries 
-             SET title = ?1, username = ?2, password = ?3, salt = ?4, url = ?5, notes = ?6, last_updated = ?7 
-             WHERE id = ?8",
-            params![
-                encrypted_title,
-                encrypted_username,
-                encrypted_password,
-                salt,
-                encrypted_url,
-                encrypted_notes,
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                entry.id
-            ],
+        if columns.iter().any(|existing| existing == column) {
+            return true;
+        }
+
+        match conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
         ){
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Update login: update failed: {}", e);
+                eprintln!("Upgrade tables: adding {}.{} failed: {}", table, column, e);
                 return false;
             }
         };
@@ -502,883 +672,2137 @@ impl Database {
         true
     }
 
-    pub fn delete_login(&self, id: &str) -> bool {
-        match self.conn
-            .execute("DELETE FROM LoginEntries WHERE id = ?1", params![id])
-            {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("Delete Login: delete failed: {}", e);
-                    return false;
+    //
+    // Serializes an entry into a JSON object so its fields can be read by column name
+    //
+    fn entry_object<T: Entry>(entry: &T) -> Result<Map<String, Value>, bool> {
+        match serde_json::to_value(entry) {
+            Ok(Value::Object(object)) => Ok(object),
+            Ok(_) => {
+                eprintln!("Entry object: {} is not serialized as an object", T::TABLE);
+                Err(false)
             }
-            };
+            Err(e) => {
+                eprintln!("Entry object: serialization failed: {}", e);
+                Err(false)
+            }
+        }
+    }
 
-        true
+    fn entry_tags(object: &Map<String, Value>) -> Vec<String> {
+        match object.get("tags") {
+            Some(Value::Array(tags)) => tags
+                .iter()
+                .filter_map(|tag| tag.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        }
     }
 
-    pub fn get_logins(&self) -> Result<Vec<LoginEntry>, bool> {
-        let mut stmt = match self.conn.prepare(
-            "SELECT id, title, username, password, salt, url, notes, last_updated FROM LoginEntries",
-        ){
-            Ok(stmt) => stmt,
+    //
+    // Validates the entry and returns its column values in the order of
+    // the field descriptors followed by salt, favorite, last_updated and expires.
+    // References of logins are checked against the given logins, imports pass them
+    // so they are not loaded again for every login, otherwise they are loaded
+    //
+    fn entry_row<T: Entry>(
+        &self,
+        entry: &mut T,
+        logins: Option<&mut HashMap<String, Map<String, Value>>>,
+        context: &str,
+    ) -> Result<(Map<String, Value>, Vec<SqlValue>), bool> {
+        if let Err(e) = entry.validate() {
+            eprintln!("{}: invalid {}: {}", context, T::KIND.as_str(), e);
+            return Err(false);
+        }
+
+        let mut object = Self::entry_object(entry)?;
+
+        if T::KIND == EntryKind::Custom {
+            self.validate_template_values(&mut object, context)?;
+        }
+        if T::KIND == EntryKind::Login {
+            match logins {
+                Some(logins) => Self::check_login_references(logins, &mut object, context)?,
+                None => Self::check_login_references(&mut self.login_objects()?, &mut object, context)?,
+            }
+        }
+
+        let salt = encryption::generate_salt();
+        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
+            Ok(derived_key) => derived_key,
             Err(e) => {
-                eprintln!("Get login: connection statement failed: {}", e);
+                eprintln!("{}: key derivation failed: {}", context, e);
                 return Err(false);
-            } 
+            }
         };
 
-        let entry_iter = match stmt.query_map([], |row| {
-            let last_updated_datetime = row.get::<_, i64>(7).expect("Get Logins: getting last update time failed");
-            let datetime = DateTime::from_timestamp(last_updated_datetime, 0).unwrap();
-            // Format as "day-month-year"
-            let last_updated_datetime_string = datetime.format("%d.%m.%Y %H:%M:%S").to_string();
+        let mut values = Vec::new();
+        for field in T::FIELDS {
+            let value = match object.get(field.name) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(value) => value.to_string(),
+            };
 
-            let encoded_salt: String = row.get::<_, String>(4).expect("Get Logins: getting salt failed");
-            let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt)
-            {
-                Ok(derived_key) => derived_key,
-                Err(_) => {
-                    eprintln!("Get logins: key derivation failure");
-                    return Err(rusqlite::Error::UnwindingPanic);
+            if !field.encrypted {
+                values.push(match field.field_type {
+                    FieldType::Integer => SqlValue::Integer(value.parse().unwrap_or(0)),
+                    FieldType::Text | FieldType::Json => SqlValue::Text(value),
+                });
+                continue;
+            }
+
+            match encryption::encrypt_gcm(&value, &derived_key) {
+                Ok(encrypted_value) => values.push(SqlValue::Text(encrypted_value)),
+                Err(e) => {
+                    eprintln!("{}: {} encryption failed: {}", context, field.name, e);
+                    return Err(false);
                 }
             };
-    
-            let decrypted_title =
-                match encryption::decrypt_gcm(&row.get::<_, String>(1).expect("Get Logins: getting title failed"), &derived_key)
-                {
-                    Ok(decrypted_title) => decrypted_title,
-                    Err(_) => {
-                        eprintln!("Get logins: title decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_username =
-                match encryption::decrypt_gcm(&row.get::<_, String>(2).expect("Get Logins: getting username failed"), &derived_key)
-                {
-                    Ok(decrypted_username) => decrypted_username,
-                    Err(_) => {
-                        eprintln!("Get logins: username decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_password =
-                match encryption::decrypt_gcm(&row.get::<_, String>(3).expect("Get Logins: getting password failed"), &derived_key)
-                {
-                    Ok(decrypted_password) => decrypted_password,
-                    Err(_) => {
-                        eprintln!("Get logins: password decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_url =
-                match encryption::decrypt_gcm(&row.get::<_, String>(5).expect("Get Logins: getting url failed"), &derived_key)
-                {
-                    Ok(decrypted_url) => decrypted_url,
-                    Err(_) => {
-                        eprintln!("Get Logins: url decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_notes =
-                match encryption::decrypt_gcm(&row.get::<_, String>(6).expect("Get Logins: getting note failed"), &derived_key)
-                {
-                    Ok(decrypted_notes) => decrypted_notes,
-                    Err(_) => {
-                        eprintln!("Get Logins: note decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            Ok(LoginEntry {
-                id: row.get::<_, i64>(0).expect("Get Logins: getting id failed").to_string(),
-                title: decrypted_title,
-                username: decrypted_username,
-                password: decrypted_password,
-                url: decrypted_url,
-                notes: decrypted_notes,
-                last_updated: last_updated_datetime_string,
-            })
-        }){
-            Ok(entry_iter) => entry_iter,
+        }
+
+        let expires = match parse_timestamp(object.get("expires").and_then(Value::as_str).unwrap_or("")) {
+            Ok(expires) => expires,
             Err(e) => {
-                eprintln!("Get Logins: query failed: {}", e);
+                eprintln!("{}: invalid expiry time: {}", context, e);
                 return Err(false);
-            } 
+            }
         };
 
-        let mut entries = Vec::new();
-        for entry in entry_iter {
-            entries.push(entry.expect("Get Logins: pushing entries error"));
+        let favorite = object.get("favorite").and_then(Value::as_bool).unwrap_or(false);
+        values.push(SqlValue::Text(salt));
+        values.push(SqlValue::Integer(favorite as i64));
+        values.push(SqlValue::Integer(Self::now()));
+        values.push(match expires {
+            Some(expires) => SqlValue::Integer(expires),
+            None => SqlValue::Null,
+        });
+
+        Ok((object, values))
+    }
+
+    fn row_columns<T: Entry>() -> Vec<&'static str> {
+        let mut columns: Vec<&'static str> = T::FIELDS.iter().map(|field| field.name).collect();
+        columns.extend(["salt", "favorite", "last_updated", "expires"]);
+        columns
+    }
+
+    fn now() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+    }
+
+    //
+    // Stores a new entry and returns its UUID
+    //
+    pub fn add_entry<T: Entry>(&self, mut entry: T) -> Result<String, bool> {
+        if let Err(e) = entry.validate_new() {
+            eprintln!("Add Entry: invalid {}: {}", T::KIND.as_str(), e);
+            return Err(false);
         }
-        Ok(entries)
+        let (object, values) = self.entry_row(&mut entry, None, "Add Entry")?;
+        let now = Self::now();
+        self.insert_entry::<T>(&object, values, now, now, now, "Add Entry")
     }
 
-    pub fn add_credit_card(&self, entry: CreditCardEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
-            Ok(derived_key) => derived_key,
+    //
+    // Stores the row entry_row made of an entry with the given created, modified
+    // and accessed times, imports keep the times of their source this way
+    //
+    fn insert_entry<T: Entry>(
+        &self,
+        object: &Map<String, Value>,
+        mut values: Vec<SqlValue>,
+        created: i64,
+        modified: i64,
+        accessed: i64,
+        context: &str,
+    ) -> Result<String, bool> {
+        values[T::FIELDS.len() + 2] = SqlValue::Integer(modified);
+
+        let uuid = self.entry_uuid(T::TABLE, object.get("id").and_then(Value::as_str).unwrap_or(""));
+        let mut columns = Self::row_columns::<T>();
+        columns.extend(["uuid", "created", "accessed"]);
+        values.push(SqlValue::Text(uuid.clone()));
+        values.push(SqlValue::Integer(created));
+        values.push(SqlValue::Integer(accessed));
+
+        let placeholders: Vec<String> = (1..=columns.len()).map(|index| format!("?{}", index)).collect();
+
+        match self.conn.execute(
+            &format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                T::TABLE,
+                columns.join(", "),
+                placeholders.join(", ")
+            ),
+            params_from_iter(values),
+        ){
+            Ok(_) => (),
             Err(e) => {
-                eprintln!("Add Credit Card: key derivation failed: {}", e);
-                return false;
+                eprintln!("{}: {} insertion failed: {}", context, T::TABLE, e);
+                return Err(false);
             }
         };
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key)
-        {
-            Ok(encrypted_title) => encrypted_title,
-            Err(e) => {
-                eprintln!("Add Credit Card: title encryption failed: {}", e);
-                return false;
-            } 
-        };
-        let encrypted_card_number =
-           match encryption::encrypt_gcm(&entry.card_number, &derived_key)
-            {
-                Ok(encrypted_card_number) => encrypted_card_number,
-                Err(e) => {
-                    eprintln!("Add Credit Card: card number encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_expiry_date =
-            match encryption::encrypt_gcm(&entry.expiry_date, &derived_key)
-            {
-                Ok(encrypted_expiry_date) => encrypted_expiry_date,
-                Err(e) => {
-                    eprintln!("Add Credit Card: expiry date encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_cardholder_name =
-            match encryption::encrypt_gcm(&entry.cardholder_name, &derived_key)
-            {
-                Ok(encrypted_cardholder_name) => encrypted_cardholder_name,
-                Err(e) => {
-                    eprintln!("Add Credit Card: cardholder name encryption failed: {}", e);
-                    return false;
-                } 
+
+        if !self.set_entry_tags(T::KIND.as_str(), &uuid, &Self::entry_tags(object)) {
+            return Err(false);
+        }
+        Ok(uuid)
+    }
+
+    pub fn update_entry<T: Entry>(&self, mut entry: T) -> bool {
+        if T::KIND == EntryKind::Login {
+            entry = match self.keep_otp_counter(entry) {
+                Ok(entry) => entry,
+                Err(_) => return false,
             };
-        let encrypted_cvv = match encryption::encrypt_gcm(&entry.cvv, &derived_key)
-        {
-            Ok(encrypted_cvv) => encrypted_cvv,
-            Err(e) => {
-                eprintln!("Add Credit Card: CVV encryption failed: {}", e);
+        }
+
+        let (object, mut values) = match self.entry_row(&mut entry, None, "Update Entry") {
+            Ok(row) => row,
+            Err(_) => return false,
+        };
+
+        let id = match object.get("id").and_then(Value::as_str) {
+            Some(id) => id.to_string(),
+            None => {
+                eprintln!("Update Entry: {} entry has no id", T::TABLE);
                 return false;
-            } 
+            }
+        };
+
+        let assignments: Vec<String> = Self::row_columns::<T>()
+            .iter()
+            .enumerate()
+            .map(|(index, column)| format!("{} = ?{}", column, index + 1))
+            .collect();
+        values.push(SqlValue::Text(id.clone()));
+
+        // The password being replaced goes into the history of the login
+        let previous_password = if T::KIND == EntryKind::Login {
+            match self.current_login_value(&id, "password") {
+                Ok(previous_password) => previous_password,
+                Err(_) => return false,
+            }
+        } else {
+            None
         };
 
         match self.conn.execute(
-            "INSERT INTO CreditCardEntries (title, card_number, expiry_date, cardholder_name, cvv, salt, last_updated) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
-            params![
-                encrypted_title,
-                encrypted_card_number,
-                encrypted_expiry_date,
-                encrypted_cardholder_name,
-                encrypted_cvv,
-                salt,
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-            ],
+            &format!(
+                "UPDATE {} SET {} WHERE uuid = ?{}",
+                T::TABLE,
+                assignments.join(", "),
+                values.len()
+            ),
+            params_from_iter(values),
         ){
+            Ok(0) => {
+                eprintln!("Update Entry: {} entry {} not found", T::TABLE, id);
+                return false;
+            }
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Add Credit Card: insertion failed: {}", e);
+                eprintln!("Update Entry: {} update failed: {}", T::TABLE, e);
                 return false;
             }
         };
-        
-        true
-    }
 
-    pub fn update_credit_card(&self, entry: CreditCardEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
-            Ok(derived_key) => derived_key,
-            Err(e) => {
-                eprintln!("Update Credit Card: key derivation failed: {}", e);
+        if let Some(previous_password) = previous_password {
+            let password = object.get("password").and_then(Value::as_str).unwrap_or("");
+            if !previous_password.is_empty()
+                && previous_password != password
+                && !self.add_password_history(&id, &previous_password, Self::now())
+            {
                 return false;
             }
-        };
+        }
 
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key)
-        {
-            Ok(encrypted_title) => encrypted_title,
-            Err(e) => {
-                eprintln!("Update Credit Card: title encryption failed: {}", e);
-                return false;
-            } 
-        };
-        let encrypted_card_number =
-           match encryption::encrypt_gcm(&entry.card_number, &derived_key)
-            {
-                Ok(encrypted_card_number) => encrypted_card_number,
-                Err(e) => {
-                    eprintln!("Update Credit Card: card number encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_expiry_date =
-            match encryption::encrypt_gcm(&entry.expiry_date, &derived_key)
+        self.set_entry_tags(T::KIND.as_str(), &id, &Self::entry_tags(&object))
+    }
+
+    pub fn delete_entry(&self, kind: EntryKind, id: &str) -> bool {
+        let table = with_entry_type!(kind, T => T::TABLE);
+
+        match self.conn
+            .execute(&format!("DELETE FROM {} WHERE uuid = ?1", table), params![id])
             {
-                Ok(encrypted_expiry_date) => encrypted_expiry_date,
+                Ok(_) => (),
                 Err(e) => {
-                    eprintln!("Update Credit Card: expiry date encryption failed: {}", e);
+                    eprintln!("Delete Entry: {} delete failed: {}", table, e);
                     return false;
-                } 
+            }
             };
-        let encrypted_cardholder_name =
-            match encryption::encrypt_gcm(&entry.cardholder_name, &derived_key)
-            {
-                Ok(encrypted_cardholder_name) => encrypted_cardholder_name,
+
+        if kind == EntryKind::Login {
+            match self.conn.execute("DELETE FROM PasswordHistory WHERE entry_id = ?1", params![id]) {
+                Ok(_) => (),
                 Err(e) => {
-                    eprintln!("Update Credit Card: cardholder name encryption failed: {}", e);
+                    eprintln!("Delete Entry: password history delete failed: {}", e);
                     return false;
-                } 
+                }
             };
-        let encrypted_cvv = match encryption::encrypt_gcm(&entry.cvv, &derived_key)
-        {
-            Ok(encrypted_cvv) => encrypted_cvv,
-            Err(e) => {
-                eprintln!("Update Credit Card: CVV encryption failed: {}", e);
-                return false;
-            } 
-        };
-        match self.conn.execute(
-            "UPDATE CreditCardEntries 
-             SET title = ?1, card_number = ?2, expiry_date = ?3, cardholder_name = ?4, cvv = ?5, salt = ?6, last_updated = ?7 
-             WHERE id = ?8",
-            params![
-                encrypted_title,
-                encrypted_card_number,
-                encrypted_expiry_date,
-                encrypted_cardholder_name,
-                encrypted_cvv,
-                salt,
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                entry.id
-            ],
-        ){
+        }
+
+        match self.conn.execute("DELETE FROM Attachments WHERE entry_id = ?1", params![id]) {
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Update Credit Card: update failed: {}", e);
+                eprintln!("Delete Entry: attachment delete failed: {}", e);
                 return false;
             }
         };
 
-        true
+        self.remove_entry_tags(kind.as_str(), id)
     }
 
-    pub fn delete_credit_card(&self, id: &str) -> bool {
-        match self.conn
-            .execute("DELETE FROM CreditCardEntries WHERE id = ?1", params![id])
-            {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("Delete Credit Card: delete failed: {}", e);
-                    return false;
+    //
+    // Entries with their references resolved, logins that reference other logins
+    // get the referenced values and keep the stored ones in "references"
+    //
+    pub fn get_entries<T: Entry>(&self) -> Result<Vec<T>, bool> {
+        let entries = self.get_raw_entries::<T>()?;
+        if T::KIND != EntryKind::Login {
+            return Ok(entries);
+        }
+
+        let mut objects = Vec::new();
+        for entry in &entries {
+            objects.push(Self::entry_object(entry)?);
+        }
+        let logins = Self::objects_by_id(&objects);
+
+        let mut resolved_entries = Vec::new();
+        for mut object in objects {
+            for e in reference::resolve_references(&logins, &mut object) {
+                eprintln!("Get Entries: {}", e);
             }
+            match serde_json::from_value::<T>(Value::Object(object)) {
+                Ok(entry) => resolved_entries.push(entry),
+                Err(e) => {
+                    eprintln!("Get Entries: {} deserialization failed: {}", T::TABLE, e);
+                    return Err(false);
+                }
             };
-
-        true
+        }
+        Ok(resolved_entries)
     }
 
-    pub fn get_credit_cards(&self) -> Result<Vec<CreditCardEntry>, bool> {
-        let mut stmt = match self.conn.prepare(
-            "SELECT id, title, card_number, expiry_date, cardholder_name, cvv, salt, last_updated FROM CreditCardEntries",
-        ){
+    //
+    // Entries as they are stored, references are not resolved
+    //
+    pub fn get_raw_entries<T: Entry>(&self) -> Result<Vec<T>, bool> {
+        let columns: Vec<&'static str> = T::FIELDS.iter().map(|field| field.name).collect();
+        let mut stmt = match self.conn.prepare(&format!(
+            "SELECT uuid, salt, favorite, created, last_updated, accessed, expires, {} FROM {}",
+            columns.join(", "),
+            T::TABLE
+        )){
             Ok(stmt) => stmt,
             Err(e) => {
-                eprintln!("Get Credit Cards: connection statement failed: {}", e);
+                eprintln!("Get Entries: connection statement failed: {}", e);
                 return Err(false);
             } 
         };
+
+        let entry_tags = self.load_entry_tags(T::KIND.as_str())?;
+
         let entry_iter = match stmt.query_map([], |row| {
-            let last_updated_datetime = row.get::<_, i64>(7).expect("Get Credit Cards: getting last update time failed");
-            let datetime = DateTime::from_timestamp(last_updated_datetime, 0).unwrap();
-            // Format as "day-month-year"
-            let last_updated_datetime_string = datetime.format("%d.%m.%Y %H:%M:%S").to_string();
+            let id = row.get::<_, String>(0)?;
 
-            let encoded_salt: String = row.get::<_, String>(6)?;
-            let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
+            let encoded_salt: String = row.get::<_, String>(1)?;
+            let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt)
+            {
                 Ok(derived_key) => derived_key,
                 Err(_) => {
-                    eprintln!("Get login: key derivation failed");
-                    return Err(rusqlite::Error::InvalidQuery);
+                    eprintln!("Get Entries: key derivation failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
                 }
             };
-            
-            let decrypted_title =
-                match encryption::decrypt_gcm(&row.get::<_, String>(1).expect("Get Credit Cards: getting title failed"), &derived_key)
-                {
-                    Ok(decrypted_title) => decrypted_title,
-                    Err(_) => {
-                        eprintln!("Get Credit Cards: title decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_card_number =
-                match encryption::decrypt_gcm(&row.get::<_, String>(2).expect("Get Credit Cards: getting card number failed"), &derived_key)
-                {
-                    Ok(decrypted_card_number) => decrypted_card_number,
-                    Err(_) => {
-                        eprintln!("Get Credit Cards: card number decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_expiry_date =
-                match encryption::decrypt_gcm(&row.get::<_, String>(3).expect("Get Credit Cards: getting expiry date failed"), &derived_key)
-                {
-                    Ok(decrypted_expiry_date) => decrypted_expiry_date,
-                    Err(_) => {
-                        eprintln!("Get Credit Cards: expiry date decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_cardholder_name =
-                match encryption::decrypt_gcm(&row.get::<_, String>(4).expect("Get Credit Cards: getting cardholder name failed"), &derived_key)
-                {
-                    Ok(decrypted_cardholder_name) => decrypted_cardholder_name,
-                    Err(_) => {
-                        eprintln!("Get Credit Cards: cardholder name decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
+
+            let mut object = Map::new();
+            for (index, field) in T::FIELDS.iter().enumerate() {
+                // Columns added by an upgrade hold NULL or an empty string
+                let value = match row.get::<_, SqlValue>(index + 7)? {
+                    SqlValue::Text(value) if field.encrypted && !value.is_empty() => {
+                        match encryption::decrypt_gcm(&value, &derived_key) {
+                            Ok(decrypted_value) => decrypted_value,
+                            Err(_) => {
+                                eprintln!("Get Entries: {} decryption failure", field.name);
+                                return Err(rusqlite::Error::UnwindingPanic);
+                            }
+                        }
                     }
+                    SqlValue::Text(value) => value,
+                    SqlValue::Integer(value) => value.to_string(),
+                    _ => String::new(),
                 };
-            let decrypted_cvv =
-                match encryption::decrypt_gcm(&row.get::<_, String>(5).expect("Get Credit Cards: getting CVV failed"), &derived_key)
-                {
-                    Ok(decrypted_cvv) => decrypted_cvv,
-                    Err(_) => {
-                        eprintln!("Get Credit Cards: CVV decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
+
+                let value = match field.field_type {
+                    FieldType::Integer => Value::from(value.parse::<i64>().unwrap_or(0)),
+                    FieldType::Text => Value::String(value),
+                    FieldType::Json if value.is_empty() => Value::Object(Map::new()),
+                    FieldType::Json => match serde_json::from_str(&value) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            eprintln!("Get Entries: {} parsing failed: {}", field.name, e);
+                            return Err(rusqlite::Error::UnwindingPanic);
+                        }
+                    },
                 };
-                
-            Ok(CreditCardEntry {
-                id: row.get::<_, i64>(0)?.to_string(),
-                title: decrypted_title,
-                card_number: decrypted_card_number,
-                expiry_date: decrypted_expiry_date,
-                cardholder_name: decrypted_cardholder_name,
-                cvv: decrypted_cvv,
-                last_updated: last_updated_datetime_string,
-            })
+                object.insert(field.name.to_string(), value);
+            }
+
+            object.insert("tags".into(), Value::from(entry_tags.get(&id).cloned().unwrap_or_default()));
+            object.insert("favorite".into(), Value::Bool(row.get::<_, bool>(2)?));
+            let modified = row.get::<_, Option<i64>>(4)?.unwrap_or(0);
+            let created = row.get::<_, Option<i64>>(3)?.unwrap_or(modified);
+            let accessed = row.get::<_, Option<i64>>(5)?.unwrap_or(modified);
+            object.insert("created".into(), Value::String(format_timestamp(created)));
+            object.insert("modified".into(), Value::String(format_timestamp(modified)));
+            object.insert("accessed".into(), Value::String(format_timestamp(accessed)));
+            object.insert(
+                "expires".into(),
+                match row.get::<_, Option<i64>>(6)? {
+                    Some(expires) => Value::String(format_timestamp(expires)),
+                    None => Value::Null,
+                },
+            );
+            object.insert("id".into(), Value::String(id));
+
+            match serde_json::from_value::<T>(Value::Object(object)) {
+                Ok(mut entry) => {
+                    entry.complete();
+                    Ok(entry)
+                }
+                Err(e) => {
+                    eprintln!("Get Entries: {} deserialization failed: {}", T::TABLE, e);
+                    Err(rusqlite::Error::UnwindingPanic)
+                }
+            }
         }){
             Ok(entry_iter) => entry_iter,
             Err(e) => {
-                eprintln!("Get Credit Cards: query failed: {}", e);
+                eprintln!("Get Entries: query failed: {}", e);
                 return Err(false);
             } 
         };
 
         let mut entries = Vec::new();
         for entry in entry_iter {
-            entries.push(entry.expect("Get Credit Cards: pushing entries error"));
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    eprintln!("Get Entries: reading {} failed: {}", T::TABLE, e);
+                    return Err(false);
+                }
+            }
         }
         Ok(entries)
     }
 
-    pub fn add_note(&self, entry: NoteEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
-            Ok(derived_key) => derived_key,
-            Err(e) => {
-                eprintln!("Add Note: key derivation failed: {}", e);
-                return false;
-            }
-        };
+    fn objects_by_id(objects: &[Map<String, Value>]) -> HashMap<String, Map<String, Value>> {
+        objects
+            .iter()
+            .filter_map(|object| {
+                object
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .map(|id| (id.to_string(), object.clone()))
+            })
+            .collect()
+    }
 
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key)
-        {
-            Ok(encrypted_title) => encrypted_title,
-            Err(e) => {
-                eprintln!("Add Note: title encryption failed: {}", e);
-                return false;
-            } 
+    //
+    // Stored login entries as JSON objects by UUID, used to resolve references
+    //
+    fn login_objects(&self) -> Result<HashMap<String, Map<String, Value>>, bool> {
+        let mut objects = Vec::new();
+        for login in self.get_raw_entries::<LoginEntry>()? {
+            objects.push(Self::entry_object(&login)?);
+        }
+        Ok(Self::objects_by_id(&objects))
+    }
+
+    //
+    // Keeps the references of fields the front end only received resolved
+    // and rejects references that are unresolved or lead to a cycle,
+    // the logins are left as they were
+    //
+    fn check_login_references(
+        logins: &mut HashMap<String, Map<String, Value>>,
+        object: &mut Map<String, Value>,
+        context: &str,
+    ) -> Result<(), bool> {
+        reference::restore_references(logins, object);
+
+        // The login takes the place of its stored version while it is checked
+        let id = object.get("id").and_then(Value::as_str).unwrap_or("").to_string();
+        let stored = logins.insert(id.clone(), object.clone());
+        let checked = reference::check_references(logins, object);
+        match stored {
+            Some(stored) => logins.insert(id, stored),
+            None => logins.remove(&id),
         };
-        let encrypted_content =
-            match encryption::encrypt_gcm(&entry.content, &derived_key)
-            {
-                Ok(encrypted_content) => encrypted_content,
-                Err(e) => {
-                    eprintln!("Add Note: content encryption failed: {}", e);
-                    return false;
-                } 
-            };
 
-        match self.conn.execute(
-            "INSERT INTO NoteEntries (title, salt, content, last_updated) VALUES (?1, ?2, ?3, ?4)",
-            params![
-                encrypted_title,
-                salt,
-                encrypted_content,
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs()
-            ],
-        ){
-            Ok(_) => (),
+        match checked {
+            Ok(_) => Ok(()),
             Err(e) => {
-                eprintln!("Add Note: insertion failed: {}", e);
-                return false;
+                eprintln!("{}: {}", context, e);
+                Err(false)
             }
+        }
+    }
+
+    //
+    // Value of a login field with its references resolved, used when the value
+    // is copied so it is never stale, errors are returned to the front end
+    //
+    pub fn resolve_login_field(&self, id: &str, field: &str) -> Result<String, String> {
+        let logins = match self.login_objects() {
+            Ok(logins) => logins,
+            Err(_) => return Err("Loading login entries failed".into()),
+        };
+
+        let value = match reference::resolve_field(&logins, id, field) {
+            Ok(value) => value,
+            Err(e) => return Err(e.to_string()),
         };
 
-         true
+        let _ = self.mark_accessed(EntryKind::Login, id);
+        Ok(value)
     }
 
-    pub fn update_note(&self, entry: NoteEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
-            Ok(derived_key) => derived_key,
-            Err(e) => {
-                eprintln!("Update Note: key derivation failed: {}", e);
-                return false;
-            }
+    //
+    // Expands placeholders ( "{USERNAME}", "{URL:HOST}", ... ) in the text with the values
+    // of an entry, used for dynamic URLs, command lines and auto-type sequences
+    //
+    pub fn expand_placeholders(&self, kind: EntryKind, id: &str, text: &str) -> Result<String, String> {
+        let entries = match self.get_entry_values(kind) {
+            Ok(entries) => entries,
+            Err(_) => return Err(format!("Loading {} entries failed", kind.as_str())),
         };
-
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key)
+        let entry = match entries
+            .iter()
+            .filter_map(Value::as_object)
+            .find(|entry| entry.get("id").and_then(Value::as_str) == Some(id))
         {
-            Ok(encrypted_title) => encrypted_title,
-            Err(e) => {
-                eprintln!("Update Note: title encryption failed: {}", e);
-                return false;
-            } 
+            Some(entry) => entry,
+            None => return Err(format!("Entry {} not found", id)),
         };
-        let encrypted_content =
-            match encryption::encrypt_gcm(&entry.content, &derived_key)
-            {
-                Ok(encrypted_content) => encrypted_content,
-                Err(e) => {
-                    eprintln!("Update Note: content encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        match self.conn.execute(
-            "UPDATE NoteEntries 
-             SET title = ?1, salt = ?2, content = ?3, last_updated = ?4 
-             WHERE id = ?5",
-            params![
-                encrypted_title,
-                salt,
-                encrypted_content,
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                entry.id
-            ],
-        ){
-            Ok(_) => (),
-            Err(e) => {
-                eprintln!("Update Note: update failed: {}", e);
-                return false;
-            }
+
+        // Only asked for when used since it increments HOTP counters
+        let totp = if kind == EntryKind::Login && placeholder::uses_placeholder(text, "TOTP") {
+            self.get_totp(id).ok().map(|otp| otp.code)
+        } else {
+            None
         };
-        
-        true
+
+        placeholder::expand(
+            text,
+            &PlaceholderContext {
+                entry,
+                totp: totp.as_deref(),
+                now: chrono::Local::now(),
+            },
+        )
     }
 
-    pub fn delete_note(&self, id: &str) -> bool {
-        match self.conn
-            .execute("DELETE FROM NoteEntries WHERE id = ?1", params![id])
-            {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("Delete Note: delete failed: {}", e);
-                    return false;
+    //
+    // Login entries with fields that reference the given entry,
+    // shown before the entry is deleted since their references would break
+    //
+    pub fn get_referencing_entries(&self, id: &str) -> Result<Vec<ReferencingEntry>, bool> {
+        let logins = self.login_objects()?;
+
+        let mut referencing_entries = Vec::new();
+        for (login_id, login) in logins {
+            if login_id == id {
+                continue;
             }
-            };
+            let fields = reference::referencing_fields(&login, id);
+            if fields.is_empty() {
+                continue;
+            }
+            referencing_entries.push(ReferencingEntry {
+                id: login_id,
+                title: login.get("title").and_then(Value::as_str).unwrap_or("").to_string(),
+                fields,
+            });
+        }
 
-        true
+        referencing_entries.sort_by_key(|entry| entry.title.to_lowercase());
+        Ok(referencing_entries)
     }
 
-    pub fn get_notes(&self) -> Result<Vec<NoteEntry>, bool> {
-        let mut stmt = match self
-            .conn
-            .prepare("SELECT id, title, salt, content, last_updated FROM NoteEntries")
-            {
-                Ok(stmt) => stmt,
-                Err(e) => {
-                    eprintln!("Get Notes: connection statement failed: {}", e);
-                    return Err(false);
-                } 
-            };
+    //
+    // Strength of every login password, the title, username and URL
+    // count as guessable inputs since passwords often contain them
+    //
+    pub fn score_logins(&self) -> Result<Vec<LoginStrength>, bool> {
+        let mut scores = Vec::new();
+        for login in self.get_entries::<LoginEntry>()? {
+            let strength = strength::estimate(&login.password, &[&login.title, &login.username, &login.url]);
+            scores.push(LoginStrength {
+                id: login.id,
+                title: login.title,
+                strength,
+            });
+        }
 
+        // Weakest first
+        scores.sort_by_key(|score| score.strength.score);
+        Ok(scores)
+    }
 
-        let entry_iter = match stmt.query_map([], |row| {
-            let last_updated_datetime = row.get::<_, i64>(4).expect("Get Notes: getting last update time failed");
-            let datetime = DateTime::from_timestamp(last_updated_datetime, 0).unwrap();
-            // Format as "day-month-year"
-            let last_updated_datetime_string = datetime.format("%d.%m.%Y %H:%M:%S").to_string();
+    //
+    // Checks every login for reused, weak and old passwords, duplicate accounts
+    // and missing two factor authentication
+    //
+    pub fn vault_health_report(&self, max_age_days: u32, min_score: u8) -> Result<HealthReport, bool> {
+        // Passwords are compared by a keyed hash with a key that only lives for this report
+        let hash_key = match key_derivation::derive_key(&self.master_key, &encryption::generate_salt()) {
+            Ok(hash_key) => hash_key,
+            Err(e) => {
+                eprintln!("Vault Health Report: key derivation failed: {}", e);
+                return Err(false);
+            }
+        };
+        let hash_key = match encode::decode_base64(&hash_key) {
+            Ok(hash_key) => hash_key,
+            Err(e) => {
+                eprintln!("Vault Health Report: key decoding failed: {}", e);
+                return Err(false);
+            }
+        };
 
-            let encoded_salt: String = row.get::<_, String>(2).expect("Get Notes: getting salt failed");
-            let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
-                Ok(derived_key) => derived_key,
-                Err(_) => {
-                    eprintln!("Get Notes: key derivation failed");
-                    return Err(rusqlite::Error::InvalidQuery);
+        let mut logins = Vec::new();
+        for login in self.get_entries::<LoginEntry>()? {
+            let password_hash = if login.password.is_empty() {
+                None
+            } else {
+                match hash::hmac_sha256(&hash_key, login.password.as_bytes()) {
+                    Ok(password_hash) => Some(password_hash),
+                    Err(e) => {
+                        eprintln!("Vault Health Report: password hashing failed: {}", e);
+                        return Err(false);
+                    }
                 }
             };
-            let decrypted_title =
-                match encryption::decrypt_gcm(&row.get::<_, String>(1)?, &derived_key)
-                {
-                    Ok(decrypted_title) => decrypted_title,
-                    Err(_) => {
-                        eprintln!("Get Notes: title decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            let decrypted_content =
-                match encryption::decrypt_gcm(&row.get::<_, String>(3)?, &derived_key)
-                {
-                    Ok(decrypted_content) => decrypted_content,
-                    Err(_) => {
-                        eprintln!("Get Notes: content decryption failure");
-                        return Err(rusqlite::Error::UnwindingPanic);
-                    }
-                };
-            Ok(NoteEntry {
-                id: row.get::<_, i64>(0)?.to_string(),
-                title: decrypted_title,
-                content: decrypted_content,
-                last_updated: last_updated_datetime_string,
-            })
-        }){
-            Ok(entry_iter) => entry_iter,
-            Err(e) => {
-                eprintln!("Get Logins: query failed: {}", e);
-                return Err(false);
-            } 
+
+            // Age of the password is counted from the last modification of the login
+            let history = self.get_password_history(&login.id)?;
+            let password_changed = match parse_timestamp(&login.modified) {
+                Ok(Some(modified)) => Ok(Some(modified)),
+                _ => parse_timestamp(&login.created),
+            };
+
+            logins.push(HealthLogin {
+                score: strength::estimate(&login.password, &[&login.title, &login.username, &login.url]).score,
+                password_changed: password_changed.ok().flatten().unwrap_or(0),
+                has_two_factor: !login.otp.trim().is_empty(),
+                reuses_previous_password: !login.password.is_empty()
+                    && history.iter().any(|item| item.password == login.password),
+                password_hash,
+                id: login.id,
+                username: login.username,
+                url: login.url,
+            });
+        }
+
+        Ok(health::build_report(&logins, max_age_days, min_score, Self::now()))
+    }
+
+    //
+    // Logins whose password appears in the breached password source,
+    // most often breached first
+    //
+    pub fn check_breached_passwords(&self, source: BreachSource) -> Result<Vec<BreachedLogin>, String> {
+        let mut checker = BreachChecker::new(source)?;
+
+        let logins = match self.get_entries::<LoginEntry>() {
+            Ok(logins) => logins,
+            Err(_) => return Err("Loading login entries failed".into()),
         };
 
-        let mut entries = Vec::new();
-        for entry in entry_iter {
-            entries.push(entry.expect("Get Notes: pushing entries error"));
+        let mut breached = Vec::new();
+        for login in logins {
+            if login.password.is_empty() {
+                continue;
+            }
+            let count = checker.count(&login.password)?;
+            if count > 0 {
+                breached.push(BreachedLogin {
+                    id: login.id,
+                    title: login.title,
+                    count,
+                });
+            }
         }
-        Ok(entries)
+
+        breached.sort_by_key(|login| std::cmp::Reverse(login.count));
+        Ok(breached)
     }
 
-    pub fn add_identity(&self, entry: IdentityEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
-            Ok(derived_key) => derived_key,
-            Err(e) => {
-                eprintln!("Add Identities: key derivation failed: {}", e);
+    //
+    // Credit cards and identity documents that are expired or expire within
+    // the given number of days, soonest first. Stored dates that cannot be parsed are skipped
+    //
+    pub fn expiring_items(&self, within_days: u32) -> Result<Vec<ExpiringItem>, bool> {
+        let today = chrono::Local::now().date_naive();
+        let mut items = Vec::new();
+
+        for card in self.get_entries::<CreditCardEntry>()? {
+            match validation::parse_card_expiry(&card.expiry_date) {
+                Ok(Some(expires_on)) => items.extend(expiry::check(
+                    &card.id,
+                    EntryKind::CreditCard,
+                    &card.title,
+                    expires_on,
+                    today,
+                    within_days,
+                )),
+                Ok(None) => (),
+                Err(e) => eprintln!("Expiring Items: credit card {}: {}", card.id, e),
+            }
+        }
+
+        for identity in self.get_entries::<IdentityEntry>()? {
+            match validation::validate_iso_date(&identity.expiry_date) {
+                Ok(Some(expires_on)) => items.extend(expiry::check(
+                    &identity.id,
+                    EntryKind::Identity,
+                    &identity.title,
+                    expires_on,
+                    today,
+                    within_days,
+                )),
+                Ok(None) => (),
+                Err(e) => eprintln!("Expiring Items: identity {}: {}", identity.id, e),
+            }
+        }
+
+        items.sort_by_key(|item| item.days_left);
+        Ok(items)
+    }
+
+    //
+    // Records that an entry was viewed or a value was copied from it
+    //
+    pub fn mark_accessed(&self, kind: EntryKind, id: &str) -> bool {
+        let table = with_entry_type!(kind, T => T::TABLE);
+
+        match self.conn.execute(
+            &format!("UPDATE {} SET accessed = ?1 WHERE uuid = ?2", table),
+            params![Self::now(), id],
+        ){
+            Ok(0) => {
+                eprintln!("Mark Accessed: {} entry {} not found", table, id);
                 return false;
             }
-        };
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key)
-        {
-            Ok(encrypted_title) => encrypted_title,
+            Ok(_) => (),
             Err(e) => {
-                eprintln!("Add Identity: title encryption failed: {}", e);
+                eprintln!("Mark Accessed: update failed: {}", e);
                 return false;
-            } 
+            }
         };
-        let encrypted_full_name =
-            match encryption::encrypt_gcm(&entry.full_name, &derived_key)
-            {
-                Ok(encrypted_full_name) => encrypted_full_name,
-                Err(e) => {
-                    eprintln!("Add Identity: full name encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_date_of_birth =
-            match encryption::encrypt_gcm(&entry.date_of_birth, &derived_key)
-            {
-                Ok(encrypted_date_of_birth) => encrypted_date_of_birth,
-                Err(e) => {
-                    eprintln!("Add Identity: date of birth encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_nationality =
-            match encryption::encrypt_gcm(&entry.nationality, &derived_key)
-            {
-                Ok(encrypted_nationality) => encrypted_nationality,
-                Err(e) => {
-                    eprintln!("Add Identity: nationality encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_identification_number =
-            match encryption::encrypt_gcm(&entry.identification_number, &derived_key)
-            {
-                Ok(encrypted_identification_number) => encrypted_identification_number,
-                Err(e) => {
-                    eprintln!("Add Identity: identification number encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_issue_date =
-            match encryption::encrypt_gcm(&entry.issue_date, &derived_key)
-            {
-                Ok(encrypted_issue_date) => encrypted_issue_date,
-                Err(e) => {
-                    eprintln!("Add Identity: issue date encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_expiry_date =
-            match encryption::encrypt_gcm(&entry.expiry_date, &derived_key)
-            {
-                Ok(encrypted_expiry_date) => encrypted_expiry_date,
-                Err(e) => {
-                    eprintln!("Add Identity: expiry date encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_issuer = match encryption::encrypt_gcm(&entry.issuer, &derived_key)
-        {
-            Ok(encrypted_issuer) => encrypted_issuer,
+
+        true
+    }
+
+    //
+    // Sorts entries by one of their timestamps,
+    // entries without the timestamp ( no expiry time ) come last
+    //
+    pub fn sort_entry_values(entries: &mut [Value], field: TimestampField, descending: bool) {
+        entries.sort_by(|a, b| {
+            let a = a.get(field.as_str()).and_then(Value::as_str);
+            let b = b.get(field.as_str()).and_then(Value::as_str);
+            match (a, b) {
+                (Some(a), Some(b)) if descending => b.cmp(a),
+                (Some(a), Some(b)) => a.cmp(b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        });
+    }
+
+    //
+    // Entry points for the front end, entries are passed as JSON
+    // and the kind selects the entry type
+    //
+    pub fn add_entry_value(&self, kind: EntryKind, entry: Value) -> Result<String, bool> {
+        with_entry_type!(kind, T => match serde_json::from_value::<T>(entry) {
+            Ok(entry) => self.add_entry(entry),
             Err(e) => {
-                eprintln!("Add Identity: issuer encryption failed: {}", e);
-                return false;
-            } 
-        };
-        let encrypted_notes = match encryption::encrypt_gcm(&entry.notes, &derived_key)
-        {
-            Ok(encrypted_notes) => encrypted_notes,
+                eprintln!("Add Entry: invalid {} entry: {}", kind.as_str(), e);
+                Err(false)
+            }
+        })
+    }
+
+    pub fn update_entry_value(&self, kind: EntryKind, entry: Value) -> bool {
+        with_entry_type!(kind, T => match serde_json::from_value::<T>(entry) {
+            Ok(entry) => self.update_entry(entry),
             Err(e) => {
-                eprintln!("Add Identity: note encryption failed: {}", e);
-                return false;
-            } 
-        };
+                eprintln!("Update Entry: invalid {} entry: {}", kind.as_str(), e);
+                false
+            }
+        })
+    }
 
-        match self.conn.execute(
-            "INSERT INTO IdentityEntries (title, full_name, date_of_birth, nationality, identification_number, issue_date, expiry_date, issuer, notes, salt, last_updated)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11);",
-            params![
-                encrypted_title,
-                encrypted_full_name,
-                encrypted_date_of_birth,
-                encrypted_nationality,
-                encrypted_identification_number,
-                encrypted_issue_date,
-                encrypted_expiry_date,
-                encrypted_issuer,
-                encrypted_notes,
-                salt,
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
-            ],
+    //
+    // Entries of the given kind, each with a "type" field
+    // so the front end can tell them apart
+    //
+    pub fn get_entry_values(&self, kind: EntryKind) -> Result<Vec<Value>, bool> {
+        with_entry_type!(kind, T => {
+            let mut values = Vec::new();
+            for entry in self.get_entries::<T>()? {
+                let mut object = Self::entry_object(&entry)?;
+                object.insert("type".into(), Value::String(kind.as_str().to_string()));
+                values.push(Value::Object(object));
+            }
+            Ok(values)
+        })
+    }
+
+    //
+    // Current one time password of a login entry,
+    // HOTP entries get their stored counter incremented
+    //
+    pub fn get_totp(&self, id: &str) -> Result<OtpCode, bool> {
+        let (encrypted_otp, encoded_salt) = match self.conn.query_row(
+            "SELECT otp, salt FROM LoginEntries WHERE uuid = ?1",
+            params![id],
+            |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?)),
         ){
-            Ok(_) => (),
+            Ok(row) => row,
             Err(e) => {
-                eprintln!("Add Identity: insertion failed: {}", e);
-                return false;
+                eprintln!("Get TOTP: query failed: {}", e);
+                return Err(false);
             }
         };
 
-        true
-    }
+        let encrypted_otp = match encrypted_otp {
+            Some(encrypted_otp) => encrypted_otp,
+            None => {
+                eprintln!("Get TOTP: entry has no otp secret");
+                return Err(false);
+            }
+        };
 
-    pub fn update_identity(&self, entry: IdentityEntry) -> bool {
-        let salt = encryption::generate_salt();
-        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
+        let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
             Ok(derived_key) => derived_key,
-            Err(e) => {
-                eprintln!("Update Identities: key derivation failed: {}", e);
-                return false;
+            Err(_) => {
+                eprintln!("Get TOTP: key derivation failed");
+                return Err(false);
             }
         };
 
-        let encrypted_title = match encryption::encrypt_gcm(&entry.title, &derived_key)
-        {
-            Ok(encrypted_title) => encrypted_title,
-            Err(e) => {
-                eprintln!("Update Identity: title encryption failed: {}", e);
-                return false;
-            } 
+        let otp_uri = match encryption::decrypt_gcm(&encrypted_otp, &derived_key) {
+            Ok(otp_uri) => otp_uri,
+            Err(_) => {
+                eprintln!("Get TOTP: otp decryption failure");
+                return Err(false);
+            }
         };
-        let encrypted_full_name =
-            match encryption::encrypt_gcm(&entry.full_name, &derived_key)
-            {
-                Ok(encrypted_full_name) => encrypted_full_name,
-                Err(e) => {
-                    eprintln!("Update Identity: full name encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_date_of_birth =
-            match encryption::encrypt_gcm(&entry.date_of_birth, &derived_key)
-            {
-                Ok(encrypted_date_of_birth) => encrypted_date_of_birth,
-                Err(e) => {
-                    eprintln!("Update Identity: date of birth encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_nationality =
-            match encryption::encrypt_gcm(&entry.nationality, &derived_key)
-            {
-                Ok(encrypted_nationality) => encrypted_nationality,
-                Err(e) => {
-                    eprintln!("Update Identity: nationality encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_identification_number =
-            match encryption::encrypt_gcm(&entry.identification_number, &derived_key)
-            {
-                Ok(encrypted_identification_number) => encrypted_identification_number,
-                Err(e) => {
-                    eprintln!("Update Identity: identification number encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_issue_date =
-            match encryption::encrypt_gcm(&entry.issue_date, &derived_key)
-            {
-                Ok(encrypted_issue_date) => encrypted_issue_date,
-                Err(e) => {
-                    eprintln!("Update Identity: issue date encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_expiry_date =
-            match encryption::encrypt_gcm(&entry.expiry_date, &derived_key)
-            {
-                Ok(encrypted_expiry_date) => encrypted_expiry_date,
-                Err(e) => {
-                    eprintln!("Update Identity: expiry date encryption failed: {}", e);
-                    return false;
-                } 
-            };
-        let encrypted_issuer = match encryption::encrypt_gcm(&entry.issuer, &derived_key)
-        {
-            Ok(encrypted_issuer) => encrypted_issuer,
+
+        if otp_uri.is_empty() {
+            eprintln!("Get TOTP: entry has no otp secret");
+            return Err(false);
+        }
+
+        let otp_params = match totp::parse(&otp_uri) {
+            Ok(otp_params) => otp_params,
             Err(e) => {
-                eprintln!("Update Identity: issuer encryption failed: {}", e);
-                return false;
-            } 
+                eprintln!("Get TOTP: invalid otp secret: {}", e);
+                return Err(false);
+            }
         };
-        let encrypted_notes = match encryption::encrypt_gcm(&entry.notes, &derived_key)
-        {
-            Ok(encrypted_notes) => encrypted_notes,
+
+        // Generating a code counts as using the entry
+        let _ = self.mark_accessed(EntryKind::Login, id);
+
+        if otp_params.kind == OtpKind::Totp {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            return Ok(totp::totp(&otp_params, now));
+        }
+
+        let code = totp::hotp(
+            &otp_params.secret,
+            otp_params.counter,
+            otp_params.digits,
+            otp_params.algorithm,
+        );
+
+        // Store the next counter so the same code is never handed out twice
+        let encrypted_next_otp = match encryption::encrypt_gcm(
+            &totp::set_counter(&otp_uri, otp_params.counter + 1),
+            &derived_key,
+        ){
+            Ok(encrypted_next_otp) => encrypted_next_otp,
             Err(e) => {
-                eprintln!("Update Identity: note encryption failed: {}", e);
-                return false;
-            } 
+                eprintln!("Get TOTP: otp encryption failed: {}", e);
+                return Err(false);
+            }
         };
 
         match self.conn.execute(
-            "UPDATE IdentityEntries 
-                SET title = ?1, full_name = ?2, date_of_birth = ?3, nationality = ?4, identification_number = ?5, issue_date = ?6, 
-                expiry_date = ?7, issuer = ?8, notes = ?9, salt = ?10, last_updated = ?11 
-                WHERE id = ?12",
-            params![
-                encrypted_title,
-                encrypted_full_name,
-                encrypted_date_of_birth,
-                encrypted_nationality,
-                encrypted_identification_number,
-                encrypted_issue_date,
-                encrypted_expiry_date,
-                encrypted_issuer,
-                encrypted_notes,
-                salt,
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                entry.id
-            ],
+            "UPDATE LoginEntries SET otp = ?1 WHERE uuid = ?2",
+            params![encrypted_next_otp, id],
         ){
             Ok(_) => (),
             Err(e) => {
-                eprintln!("Update Identity: update failed: {}", e);
-                return false;
+                eprintln!("Get TOTP: counter update failed: {}", e);
+                return Err(false);
             }
         };
-        
-        true
-    }
 
-    pub fn delete_identity(&self, id: &str) -> bool {
-        match self.conn
-            .execute("DELETE FROM IdentityEntries WHERE id = ?1", params![id])
-            {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("Delete Identity: delete failed: {}", e);
-                    return false;
-                }
-            };
-        
-        true
+        Ok(OtpCode {
+            code,
+            remaining: 0,
+            period: 0,
+        })
     }
 
-    pub fn get_identities(&self) -> Result<Vec<IdentityEntry>, bool> {
-        let mut stmt = match self.conn.prepare(
-            "SELECT id, title, full_name, date_of_birth, nationality, identification_number,
-                issue_date, expiry_date, issuer, notes, salt, last_updated 
-                FROM IdentityEntries",
-        )
-        {
+    //
+    // Decrypts every tag name ( tag id -> name )
+    //
+    fn load_tag_names(&self) -> Result<HashMap<i64, String>, bool> {
+        let mut stmt = match self.conn.prepare("SELECT id, name, salt FROM Tags") {
             Ok(stmt) => stmt,
             Err(e) => {
-                eprintln!("Get Identities: connection statement failed: {}", e);
+                eprintln!("Load Tags: connection statement failed: {}", e);
                 return Err(false);
-            } 
+            }
         };
 
-        let entry_iter = match stmt.query_map([], |row| {
-            let last_updated_datetime = row.get::<_, i64>(11).expect("Get Identities: getting last update time failed");
-            let datetime = DateTime::from_timestamp(last_updated_datetime, 0).unwrap();
-            // Format as "day-month-year"
-            let last_updated_datetime_string = datetime.format("%d.%m.%Y %H:%M:%S").to_string();
-
-            let encoded_salt: String = row.get::<_, String>(10).expect("Get Identities: getting salt failed");
+        let tag_iter = match stmt.query_map([], |row| {
+            let encoded_salt: String = row.get::<_, String>(2)?;
             let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
                 Ok(derived_key) => derived_key,
                 Err(_) => {
-                    eprintln!("Get Identities: key derivation failed");
+                    eprintln!("Load Tags: key derivation failed");
                     return Err(rusqlite::Error::InvalidQuery);
                 }
             };
-
-            let decrypted_title =
-                encryption::decrypt_gcm(&row.get::<_, String>(1).expect("Get Identities: getting title failed"), &derived_key).expect("Error");
-            let decrypted_full_name =
-                encryption::decrypt_gcm(&row.get::<_, String>(2).expect("Get Identities: getting full name failed"), &derived_key).expect("Error");
-            let decrypted_date_of_birth =
-                encryption::decrypt_gcm(&row.get::<_, String>(3).expect("Get Identities: getting date of birth failed"), &derived_key).expect("Error");
-            let decrypted_nationality =
-                encryption::decrypt_gcm(&row.get::<_, String>(4).expect("Get Identities: getting nationality failed"), &derived_key).expect("Error");
-            let decrypted_identification_number =
-                encryption::decrypt_gcm(&row.get::<_, String>(5).expect("Get Identities: getting identification number failed"), &derived_key).expect("Error");
-            let decrypted_issue_date =
-                encryption::decrypt_gcm(&row.get::<_, String>(6).expect("Get Identities: getting issue date failed"), &derived_key).expect("Error");
-            let decrypted_expiry_date =
-                encryption::decrypt_gcm(&row.get::<_, String>(7).expect("Get Identities: getting expiry date failed"), &derived_key).expect("Error");
-            let decrypted_issuer =
-                encryption::decrypt_gcm(&row.get::<_, String>(8).expect("Get Identities: getting issuer failed"), &derived_key).expect("Error");
-            let decrypted_notes =
-                encryption::decrypt_gcm(&row.get::<_, String>(9).expect("Get Identities: getting note failed"), &derived_key).expect("Error");
-
-            Ok(IdentityEntry {
-                id: row.get::<_, i64>(0)?.to_string(),
-                title: decrypted_title,
-                full_name: decrypted_full_name,
-                date_of_birth: decrypted_date_of_birth,
-                nationality: decrypted_nationality,
-                identification_number: decrypted_identification_number,
-                issue_date: decrypted_issue_date,
-                expiry_date: decrypted_expiry_date,
-                issuer: decrypted_issuer,
-                notes: decrypted_notes,
-                last_updated: last_updated_datetime_string,
-            })
-        })
-        {
-            Ok(entry_iter) => entry_iter,
+            let decrypted_name =
+                match encryption::decrypt_gcm(&row.get::<_, String>(1)?, &derived_key)
+                {
+                    Ok(decrypted_name) => decrypted_name,
+                    Err(_) => {
+                        eprintln!("Load Tags: name decryption failure");
+                        return Err(rusqlite::Error::UnwindingPanic);
+                    }
+                };
+            Ok((row.get::<_, i64>(0)?, decrypted_name))
+        }){
+            Ok(tag_iter) => tag_iter,
             Err(e) => {
-                eprintln!("Get Identities: query failed: {}", e);
+                eprintln!("Load Tags: query failed: {}", e);
                 return Err(false);
-            } 
+            }
         };
 
-        let mut entries = Vec::new();
-        for entry in entry_iter {
-            entries.push(entry.expect("Get Identities: pushing entries error"));
+        let mut tags = HashMap::new();
+        for tag in tag_iter {
+            match tag {
+                Ok((id, name)) => {
+                    tags.insert(id, name);
+                }
+                Err(e) => {
+                    eprintln!("Load Tags: reading tag failed: {}", e);
+                    return Err(false);
+                }
+            }
+        }
+        Ok(tags)
+    }
+
+    //
+    // Tag names of every entry of the given type ( entry id -> tag names )
+    //
+    fn load_entry_tags(&self, entry_type: &str) -> Result<HashMap<String, Vec<String>>, bool> {
+        let tag_names = self.load_tag_names()?;
+
+        let mut stmt = match self
+            .conn
+            .prepare("SELECT entry_id, tag_id FROM EntryTags WHERE entry_type = ?1")
+            {
+                Ok(stmt) => stmt,
+                Err(e) => {
+                    eprintln!("Load Entry Tags: connection statement failed: {}", e);
+                    return Err(false);
+                }
+            };
+
+        let link_iter = match stmt.query_map(params![entry_type], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        }){
+            Ok(link_iter) => link_iter,
+            Err(e) => {
+                eprintln!("Load Entry Tags: query failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let mut entry_tags: HashMap<String, Vec<String>> = HashMap::new();
+        for link in link_iter {
+            let (entry_id, tag_id) = match link {
+                Ok(link) => link,
+                Err(e) => {
+                    eprintln!("Load Entry Tags: reading link failed: {}", e);
+                    return Err(false);
+                }
+            };
+            if let Some(name) = tag_names.get(&tag_id) {
+                entry_tags.entry(entry_id).or_default().push(name.clone());
+            }
+        }
+
+        for tags in entry_tags.values_mut() {
+            tags.sort();
+        }
+        Ok(entry_tags)
+    }
+
+    //
+    // Replaces the tags of an entry, creating the tags that don't exist yet
+    //
+    fn set_entry_tags(&self, entry_type: &str, entry_id: &str, tags: &[String]) -> bool {
+        let mut tag_names = match self.load_tag_names() {
+            Ok(tag_names) => tag_names,
+            Err(_) => {
+                eprintln!("Set Entry Tags: loading tags failed");
+                return false;
+            }
+        };
+
+        match self.conn.execute(
+            "DELETE FROM EntryTags WHERE entry_type = ?1 AND entry_id = ?2",
+            params![entry_type, entry_id],
+        ){
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Set Entry Tags: clearing tags failed: {}", e);
+                return false;
+            }
+        };
+
+        for tag in tags {
+            let tag = tag.trim();
+            if tag.is_empty() {
+                continue;
+            }
+
+            let existing_id = tag_names
+                .iter()
+                .find(|(_, name)| name.as_str() == tag)
+                .map(|(id, _)| *id);
+
+            let tag_id = match existing_id {
+                Some(tag_id) => tag_id,
+                None => {
+                    let salt = encryption::generate_salt();
+                    let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
+                        Ok(derived_key) => derived_key,
+                        Err(e) => {
+                            eprintln!("Set Entry Tags: key derivation failed: {}", e);
+                            return false;
+                        }
+                    };
+                    let encrypted_name = match encryption::encrypt_gcm(tag, &derived_key) {
+                        Ok(encrypted_name) => encrypted_name,
+                        Err(e) => {
+                            eprintln!("Set Entry Tags: name encryption failed: {}", e);
+                            return false;
+                        }
+                    };
+
+                    match self.conn.execute(
+                        "INSERT INTO Tags (name, salt) VALUES (?1, ?2)",
+                        params![encrypted_name, salt],
+                    ){
+                        Ok(_) => (),
+                        Err(e) => {
+                            eprintln!("Set Entry Tags: tag insertion failed: {}", e);
+                            return false;
+                        }
+                    };
+
+                    let tag_id = self.conn.last_insert_rowid();
+                    tag_names.insert(tag_id, tag.to_string());
+                    tag_id
+                }
+            };
+
+            match self.conn.execute(
+                "INSERT OR IGNORE INTO EntryTags (entry_type, entry_id, tag_id) VALUES (?1, ?2, ?3)",
+                params![entry_type, entry_id, tag_id],
+            ){
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("Set Entry Tags: link insertion failed: {}", e);
+                    return false;
+                }
+            };
+        }
+
+        self.remove_unused_tags()
+    }
+
+    fn remove_entry_tags(&self, entry_type: &str, entry_id: &str) -> bool {
+        match self.conn.execute(
+            "DELETE FROM EntryTags WHERE entry_type = ?1 AND entry_id = ?2",
+            params![entry_type, entry_id],
+        ){
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Remove Entry Tags: delete failed: {}", e);
+                return false;
+            }
+        };
+
+        self.remove_unused_tags()
+    }
+
+    fn remove_unused_tags(&self) -> bool {
+        match self.conn.execute(
+            "DELETE FROM Tags WHERE id NOT IN (SELECT tag_id FROM EntryTags)",
+            [],
+        ){
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Remove Unused Tags: delete failed: {}", e);
+                return false;
+            }
+        };
+
+        true
+    }
+
+    pub fn get_tags(&self) -> Result<Vec<TagCount>, bool> {
+        let tag_names = self.load_tag_names()?;
+
+        let mut stmt = match self
+            .conn
+            .prepare("SELECT tag_id, COUNT(*) FROM EntryTags GROUP BY tag_id")
+            {
+                Ok(stmt) => stmt,
+                Err(e) => {
+                    eprintln!("Get Tags: connection statement failed: {}", e);
+                    return Err(false);
+                }
+            };
+
+        let count_iter = match stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+        }){
+            Ok(count_iter) => count_iter,
+            Err(e) => {
+                eprintln!("Get Tags: query failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let mut tags = Vec::new();
+        for count in count_iter {
+            let (tag_id, count) = match count {
+                Ok(count) => count,
+                Err(e) => {
+                    eprintln!("Get Tags: reading tag count failed: {}", e);
+                    return Err(false);
+                }
+            };
+            if let Some(name) = tag_names.get(&tag_id) {
+                tags.push(TagCount {
+                    name: name.clone(),
+                    count,
+                });
+            }
+        }
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    pub fn get_entries_by_tag(&self, tag: &str) -> Result<Vec<Value>, bool> {
+        let mut entries = Vec::new();
+        for kind in EntryKind::ALL {
+            for entry in self.get_entry_values(kind)? {
+                let has_tag = match entry.get("tags") {
+                    Some(Value::Array(tags)) => tags.iter().any(|name| name.as_str() == Some(tag)),
+                    _ => false,
+                };
+                if has_tag {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn get_favorites(&self) -> Result<Vec<Value>, bool> {
+        let mut entries = Vec::new();
+        for kind in EntryKind::ALL {
+            for entry in self.get_entry_values(kind)? {
+                if entry.get("favorite").and_then(Value::as_bool).unwrap_or(false) {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    //
+    // Checks the values of a custom entry against its template
+    // and replaces them with the normalized values
+    //
+    fn validate_template_values(&self, object: &mut Map<String, Value>, context: &str) -> Result<(), bool> {
+        let template_id = object.get("template_id").and_then(Value::as_str).unwrap_or("").to_string();
+        let template = match self.get_template(&template_id) {
+            Ok(template) => template,
+            Err(_) => {
+                eprintln!("{}: template {} not found", context, template_id);
+                return Err(false);
+            }
+        };
+
+        let values: HashMap<String, String> = match object.get("fields") {
+            Some(fields) => match serde_json::from_value(fields.clone()) {
+                Ok(values) => values,
+                Err(e) => {
+                    eprintln!("{}: invalid template values: {}", context, e);
+                    return Err(false);
+                }
+            },
+            None => HashMap::new(),
+        };
+
+        let values = match template::validate_values(&template, &values) {
+            Ok(values) => values,
+            Err(e) => {
+                eprintln!("{}: invalid {} entry: {}", context, template.name, e);
+                return Err(false);
+            }
+        };
+
+        object.insert(
+            "fields".into(),
+            Value::Object(values.into_iter().map(|(name, value)| (name, Value::String(value))).collect()),
+        );
+        Ok(())
+    }
+
+    //
+    // Encrypts the template name and field definitions ( as JSON )
+    //
+    fn encrypt_template(&self, template: &mut Template, context: &str) -> Result<(String, String, String), bool> {
+        if let Err(e) = template::validate_template(template) {
+            eprintln!("{}: invalid template: {}", context, e);
+            return Err(false);
+        }
+
+        let fields = match serde_json::to_string(&template.fields) {
+            Ok(fields) => fields,
+            Err(e) => {
+                eprintln!("{}: field serialization failed: {}", context, e);
+                return Err(false);
+            }
+        };
+
+        let salt = encryption::generate_salt();
+        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
+            Ok(derived_key) => derived_key,
+            Err(e) => {
+                eprintln!("{}: key derivation failed: {}", context, e);
+                return Err(false);
+            }
+        };
+
+        let encrypted_name = match encryption::encrypt_gcm(&template.name, &derived_key) {
+            Ok(encrypted_name) => encrypted_name,
+            Err(e) => {
+                eprintln!("{}: name encryption failed: {}", context, e);
+                return Err(false);
+            }
+        };
+        let encrypted_fields = match encryption::encrypt_gcm(&fields, &derived_key) {
+            Ok(encrypted_fields) => encrypted_fields,
+            Err(e) => {
+                eprintln!("{}: fields encryption failed: {}", context, e);
+                return Err(false);
+            }
+        };
+
+        Ok((encrypted_name, encrypted_fields, salt))
+    }
+
+    pub fn add_template(&self, mut template: Template) -> bool {
+        let (encrypted_name, encrypted_fields, salt) = match self.encrypt_template(&mut template, "Add Template") {
+            Ok(row) => row,
+            Err(_) => return false,
+        };
+
+        match self.conn.execute(
+            "INSERT INTO Templates (uuid, name, fields, salt, last_updated) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                self.entry_uuid("Templates", &template.id),
+                encrypted_name,
+                encrypted_fields,
+                salt,
+                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+            ],
+        ){
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Add Template: insertion failed: {}", e);
+                return false;
+            }
+        };
+
+        true
+    }
+
+    //
+    // Existing entries keep their values, they are checked
+    // against the new definition the next time they are saved
+    //
+    pub fn update_template(&self, mut template: Template) -> bool {
+        let (encrypted_name, encrypted_fields, salt) = match self.encrypt_template(&mut template, "Update Template") {
+            Ok(row) => row,
+            Err(_) => return false,
+        };
+
+        match self.conn.execute(
+            "UPDATE Templates SET name = ?1, fields = ?2, salt = ?3, last_updated = ?4 WHERE uuid = ?5",
+            params![
+                encrypted_name,
+                encrypted_fields,
+                salt,
+                SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                template.id
+            ],
+        ){
+            Ok(0) => {
+                eprintln!("Update Template: template {} not found", template.id);
+                return false;
+            }
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Update Template: update failed: {}", e);
+                return false;
+            }
+        };
+
+        true
+    }
+
+    //
+    // Templates still used by entries can not be deleted
+    //
+    pub fn delete_template(&self, id: &str) -> bool {
+        let in_use = match self.get_entries::<CustomEntry>() {
+            Ok(entries) => entries.iter().any(|entry| entry.template_id == id),
+            Err(_) => {
+                eprintln!("Delete Template: loading entries failed");
+                return false;
+            }
+        };
+        if in_use {
+            eprintln!("Delete Template: template {} is still used by entries", id);
+            return false;
+        }
+
+        match self.conn.execute("DELETE FROM Templates WHERE uuid = ?1", params![id]) {
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Delete Template: delete failed: {}", e);
+                return false;
+            }
+        };
+
+        true
+    }
+
+    pub fn get_templates(&self) -> Result<Vec<Template>, bool> {
+        let mut stmt = match self.conn.prepare("SELECT uuid, name, fields, salt, last_updated FROM Templates") {
+            Ok(stmt) => stmt,
+            Err(e) => {
+                eprintln!("Get Templates: connection statement failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let template_iter = match stmt.query_map([], |row| {
+            let encoded_salt: String = row.get::<_, String>(3)?;
+            let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
+                Ok(derived_key) => derived_key,
+                Err(_) => {
+                    eprintln!("Get Templates: key derivation failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+
+            let decrypted_name = match encryption::decrypt_gcm(&row.get::<_, String>(1)?, &derived_key) {
+                Ok(decrypted_name) => decrypted_name,
+                Err(_) => {
+                    eprintln!("Get Templates: name decryption failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+            let decrypted_fields = match encryption::decrypt_gcm(&row.get::<_, String>(2)?, &derived_key) {
+                Ok(decrypted_fields) => decrypted_fields,
+                Err(_) => {
+                    eprintln!("Get Templates: fields decryption failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+            let fields = match serde_json::from_str(&decrypted_fields) {
+                Ok(fields) => fields,
+                Err(e) => {
+                    eprintln!("Get Templates: fields parsing failed: {}", e);
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+
+            Ok(Template {
+                id: row.get::<_, String>(0)?,
+                name: decrypted_name,
+                fields,
+                modified: format_timestamp(row.get::<_, Option<i64>>(4)?.unwrap_or(0)),
+            })
+        }){
+            Ok(template_iter) => template_iter,
+            Err(e) => {
+                eprintln!("Get Templates: query failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let mut templates = Vec::new();
+        for template in template_iter {
+            match template {
+                Ok(template) => templates.push(template),
+                Err(e) => {
+                    eprintln!("Get Templates: reading template failed: {}", e);
+                    return Err(false);
+                }
+            }
+        }
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(templates)
+    }
+
+    pub fn get_template(&self, id: &str) -> Result<Template, bool> {
+        match self.get_templates()?.into_iter().find(|template| template.id == id) {
+            Some(template) => Ok(template),
+            None => Err(false),
+        }
+    }
+
+    //
+    // Creates a custom entry from a template,
+    // the values are validated like any other custom entry
+    //
+    pub fn add_entry_from_template(
+        &self,
+        template_id: &str,
+        title: &str,
+        fields: HashMap<String, String>,
+        tags: Vec<String>,
+    ) -> Result<String, bool> {
+        self.add_entry(CustomEntry {
+            id: String::new(),
+            template_id: template_id.to_string(),
+            title: title.to_string(),
+            fields,
+            tags,
+            favorite: false,
+            created: String::new(),
+            modified: String::new(),
+            accessed: String::new(),
+            expires: None,
+        })
+    }
+
+    //
+    // Decrypted value of a stored login column, None when the login does not exist
+    //
+    fn current_login_value(&self, id: &str, column: &str) -> Result<Option<String>, bool> {
+        let row = self.conn.query_row(
+            &format!("SELECT {}, salt FROM LoginEntries WHERE uuid = ?1", column),
+            params![id],
+            |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?)),
+        );
+        let (encrypted_value, encoded_salt) = match row {
+            Ok((Some(encrypted_value), encoded_salt)) => (encrypted_value, encoded_salt),
+            Ok((None, _)) => return Ok(Some(String::new())),
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => {
+                eprintln!("Current Login Value: query failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
+            Ok(derived_key) => derived_key,
+            Err(_) => {
+                eprintln!("Current Login Value: key derivation failed");
+                return Err(false);
+            }
+        };
+
+        match encryption::decrypt_gcm(&encrypted_value, &derived_key) {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => {
+                eprintln!("Current Login Value: {} decryption failure", column);
+                Err(false)
+            }
+        }
+    }
+
+    //
+    // The front end sends back the login as it loaded it, HOTP codes generated since then
+    // moved the stored counter on, so the stored counter is kept when it is further
+    //
+    fn keep_otp_counter<T: Entry>(&self, entry: T) -> Result<T, bool> {
+        let mut object = Self::entry_object(&entry)?;
+        let id = object.get("id").and_then(Value::as_str).unwrap_or("").to_string();
+        let otp = object.get("otp").and_then(Value::as_str).unwrap_or("").to_string();
+
+        let stored_otp = match self.current_login_value(&id, "otp")? {
+            Some(stored_otp) => stored_otp,
+            None => return Ok(entry),
+        };
+        let kept_otp = totp::keep_counter(&stored_otp, &otp);
+        if kept_otp == otp {
+            return Ok(entry);
+        }
+
+        object.insert("otp".into(), Value::String(kept_otp));
+        match serde_json::from_value::<T>(Value::Object(object)) {
+            Ok(entry) => Ok(entry),
+            Err(e) => {
+                eprintln!("Update Entry: invalid {} entry: {}", T::KIND.as_str(), e);
+                Err(false)
+            }
+        }
+    }
+
+    fn add_password_history(&self, entry_id: &str, password: &str, replaced: i64) -> bool {
+        let salt = encryption::generate_salt();
+        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
+            Ok(derived_key) => derived_key,
+            Err(e) => {
+                eprintln!("Add Password History: key derivation failed: {}", e);
+                return false;
+            }
+        };
+        let encrypted_password = match encryption::encrypt_gcm(password, &derived_key) {
+            Ok(encrypted_password) => encrypted_password,
+            Err(e) => {
+                eprintln!("Add Password History: password encryption failed: {}", e);
+                return false;
+            }
+        };
+
+        match self.conn.execute(
+            "INSERT INTO PasswordHistory (entry_id, password, salt, replaced) VALUES (?1, ?2, ?3, ?4)",
+            params![entry_id, encrypted_password, salt, replaced],
+        ){
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Add Password History: insertion failed: {}", e);
+                return false;
+            }
+        };
+
+        true
+    }
+
+    //
+    // Previous passwords of a login, the most recently replaced first
+    //
+    pub fn get_password_history(&self, entry_id: &str) -> Result<Vec<PasswordHistoryItem>, bool> {
+        let mut stmt = match self.conn.prepare(
+            "SELECT password, salt, replaced FROM PasswordHistory WHERE entry_id = ?1 ORDER BY replaced DESC, id DESC",
+        ){
+            Ok(stmt) => stmt,
+            Err(e) => {
+                eprintln!("Get Password History: connection statement failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let history_iter = match stmt.query_map(params![entry_id], |row| {
+            let encoded_salt: String = row.get::<_, String>(1)?;
+            let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
+                Ok(derived_key) => derived_key,
+                Err(_) => {
+                    eprintln!("Get Password History: key derivation failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+            let decrypted_password = match encryption::decrypt_gcm(&row.get::<_, String>(0)?, &derived_key) {
+                Ok(decrypted_password) => decrypted_password,
+                Err(_) => {
+                    eprintln!("Get Password History: password decryption failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+            Ok(PasswordHistoryItem {
+                password: decrypted_password,
+                replaced: format_timestamp(row.get::<_, i64>(2)?),
+            })
+        }){
+            Ok(history_iter) => history_iter,
+            Err(e) => {
+                eprintln!("Get Password History: query failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let mut history = Vec::new();
+        for item in history_iter {
+            match item {
+                Ok(item) => history.push(item),
+                Err(e) => {
+                    eprintln!("Get Password History: reading history failed: {}", e);
+                    return Err(false);
+                }
+            }
+        }
+        Ok(history)
+    }
+
+    //
+    // Whether the password was used by the login before,
+    // the generator and the audit use this to reject reuse
+    //
+    pub fn is_previous_password(&self, entry_id: &str, password: &str) -> Result<bool, bool> {
+        Ok(self
+            .get_password_history(entry_id)?
+            .iter()
+            .any(|item| item.password == password))
+    }
+
+    //
+    // Attaches a file to an entry and returns the attachment's UUID
+    //
+    pub fn add_attachment(&self, kind: EntryKind, entry_id: &str, name: &str, data: &[u8]) -> Result<String, bool> {
+        if data.len() > MAX_ATTACHMENT_SIZE {
+            eprintln!("Add Attachment: {} is larger than {} bytes", name, MAX_ATTACHMENT_SIZE);
+            return Err(false);
+        }
+
+        let salt = encryption::generate_salt();
+        let derived_key = match key_derivation::derive_key(&self.master_key, &salt) {
+            Ok(derived_key) => derived_key,
+            Err(e) => {
+                eprintln!("Add Attachment: key derivation failed: {}", e);
+                return Err(false);
+            }
+        };
+        let encrypted_name = match encryption::encrypt_gcm(name, &derived_key) {
+            Ok(encrypted_name) => encrypted_name,
+            Err(e) => {
+                eprintln!("Add Attachment: name encryption failed: {}", e);
+                return Err(false);
+            }
+        };
+        let encrypted_data = match encryption::encrypt_gcm(&encode::encode_base64(data.to_vec()), &derived_key) {
+            Ok(encrypted_data) => encrypted_data,
+            Err(e) => {
+                eprintln!("Add Attachment: data encryption failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let uuid = Uuid::new_v4().to_string();
+        match self.conn.execute(
+            "INSERT INTO Attachments (uuid, entry_type, entry_id, name, data, size, salt, created)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![uuid, kind.as_str(), entry_id, encrypted_name, encrypted_data, data.len() as i64, salt, Self::now()],
+        ){
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Add Attachment: insertion failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        Ok(uuid)
+    }
+
+    //
+    // Attachments of an entry without their data, oldest first
+    //
+    pub fn get_attachments(&self, entry_id: &str) -> Result<Vec<AttachmentInfo>, bool> {
+        let mut stmt = match self.conn.prepare(
+            "SELECT uuid, entry_id, name, size, salt, created FROM Attachments WHERE entry_id = ?1 ORDER BY created, id",
+        ){
+            Ok(stmt) => stmt,
+            Err(e) => {
+                eprintln!("Get Attachments: connection statement failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let attachment_iter = match stmt.query_map(params![entry_id], |row| {
+            let encoded_salt: String = row.get::<_, String>(4)?;
+            let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
+                Ok(derived_key) => derived_key,
+                Err(_) => {
+                    eprintln!("Get Attachments: key derivation failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+            let decrypted_name = match encryption::decrypt_gcm(&row.get::<_, String>(2)?, &derived_key) {
+                Ok(decrypted_name) => decrypted_name,
+                Err(_) => {
+                    eprintln!("Get Attachments: name decryption failure");
+                    return Err(rusqlite::Error::UnwindingPanic);
+                }
+            };
+            Ok(AttachmentInfo {
+                id: row.get::<_, String>(0)?,
+                entry_id: row.get::<_, String>(1)?,
+                name: decrypted_name,
+                size: row.get::<_, i64>(3)?,
+                created: format_timestamp(row.get::<_, i64>(5)?),
+            })
+        }){
+            Ok(attachment_iter) => attachment_iter,
+            Err(e) => {
+                eprintln!("Get Attachments: query failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let mut attachments = Vec::new();
+        for attachment in attachment_iter {
+            match attachment {
+                Ok(attachment) => attachments.push(attachment),
+                Err(e) => {
+                    eprintln!("Get Attachments: reading attachment failed: {}", e);
+                    return Err(false);
+                }
+            }
+        }
+        Ok(attachments)
+    }
+
+    //
+    // Name and content of an attachment
+    //
+    pub fn get_attachment_data(&self, id: &str) -> Result<(String, Vec<u8>), bool> {
+        let row = self.conn.query_row(
+            "SELECT name, data, salt FROM Attachments WHERE uuid = ?1",
+            params![id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)),
+        );
+        let (encrypted_name, encrypted_data, encoded_salt) = match row {
+            Ok(row) => row,
+            Err(e) => {
+                eprintln!("Get Attachment: query failed: {}", e);
+                return Err(false);
+            }
+        };
+
+        let derived_key = match key_derivation::derive_key(&self.master_key, &encoded_salt) {
+            Ok(derived_key) => derived_key,
+            Err(_) => {
+                eprintln!("Get Attachment: key derivation failed");
+                return Err(false);
+            }
+        };
+        let name = match encryption::decrypt_gcm(&encrypted_name, &derived_key) {
+            Ok(name) => name,
+            Err(_) => {
+                eprintln!("Get Attachment: name decryption failure");
+                return Err(false);
+            }
+        };
+        let data = match encryption::decrypt_gcm(&encrypted_data, &derived_key) {
+            Ok(data) => data,
+            Err(_) => {
+                eprintln!("Get Attachment: data decryption failure");
+                return Err(false);
+            }
+        };
+
+        match encode::decode_base64(&data) {
+            Ok(data) => Ok((name, data)),
+            Err(e) => {
+                eprintln!("Get Attachment: data decoding failed: {}", e);
+                Err(false)
+            }
+        }
+    }
+
+    pub fn delete_attachment(&self, id: &str) -> bool {
+        match self.conn.execute("DELETE FROM Attachments WHERE uuid = ?1", params![id]) {
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Delete Attachment: delete failed: {}", e);
+                return false;
+            }
+        };
+
+        true
+    }
+
+    //
+    // Entry object of an import item after validation, the way it would be stored
+    //
+    fn import_object<T: Entry>(item: &ImportItem) -> Result<Map<String, Value>, String> {
+        let mut entry: T = match serde_json::from_value(Value::Object(item.entry.clone())) {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("invalid {}: {}", T::KIND.as_str(), e)),
+        };
+        entry.validate()?;
+        Self::entry_object(&entry).map_err(|_| format!("invalid {}", T::KIND.as_str()))
+    }
+
+    // Keys an entry is found by when looking for duplicates, its id and its field values
+    fn duplicate_keys<T: Entry>(object: &Map<String, Value>) -> Vec<String> {
+        let values: Vec<String> = T::FIELDS
+            .iter()
+            .map(|field| match object.get(field.name) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(value) => value.to_string(),
+            })
+            .collect();
+
+        let mut keys = vec![format!("{}:values:{}", T::KIND.as_str(), values.join("\u{1f}"))];
+        if let Some(id) = object.get("id").and_then(Value::as_str) {
+            if let Ok(uuid) = Uuid::parse_str(id.trim()) {
+                keys.push(format!("{}:id:{}", T::KIND.as_str(), uuid));
+            }
+        }
+        keys
+    }
+
+    //
+    // Duplicate keys of the stored entries of the kinds being imported ( key -> entry id )
+    //
+    fn import_duplicate_index(&self, items: &[ImportItem]) -> Result<HashMap<String, String>, bool> {
+        let mut index = HashMap::new();
+        for kind in EntryKind::ALL {
+            if !items.iter().any(|item| item.kind == kind) {
+                continue;
+            }
+            with_entry_type!(kind, T => {
+                for entry in self.get_raw_entries::<T>()? {
+                    let object = Self::entry_object(&entry)?;
+                    let id = object.get("id").and_then(Value::as_str).unwrap_or("").to_string();
+                    for key in Self::duplicate_keys::<T>(&object) {
+                        index.insert(key, id.clone());
+                    }
+                }
+            });
+        }
+        Ok(index)
+    }
+
+    fn find_duplicate(index: &HashMap<String, String>, kind: EntryKind, object: &Map<String, Value>) -> Option<String> {
+        let keys = with_entry_type!(kind, T => Self::duplicate_keys::<T>(object));
+        keys.iter().find_map(|key| index.get(key).cloned())
+    }
+
+    // Adds an item of the import to the index so later items with the same id or values are duplicates
+    fn index_duplicate(index: &mut HashMap<String, String>, kind: EntryKind, object: &Map<String, Value>, id: &str) {
+        for key in with_entry_type!(kind, T => Self::duplicate_keys::<T>(object)) {
+            index.entry(key).or_insert_with(|| id.to_string());
+        }
+    }
+
+    //
+    // What importing the items would do, nothing is stored. Items repeating an earlier
+    // item of the import are duplicates of "import:<index of the earlier item>"
+    //
+    pub fn preview_import(&self, items: &[ImportItem], warnings: Vec<String>) -> Result<ImportPreview, bool> {
+        let mut duplicates = self.import_duplicate_index(items)?;
+
+        let mut preview = ImportPreview { items: Vec::new(), warnings };
+        for (index, item) in items.iter().enumerate() {
+            let (duplicate_of, error) = match with_entry_type!(item.kind, T => Self::import_object::<T>(item)) {
+                Ok(object) => {
+                    let duplicate_of = Self::find_duplicate(&duplicates, item.kind, &object);
+                    Self::index_duplicate(&mut duplicates, item.kind, &object, &format!("import:{}", index));
+                    (duplicate_of, None)
+                }
+                Err(e) => (None, Some(e)),
+            };
+            preview.items.push(PreviewItem {
+                index,
+                kind: item.kind,
+                title: item.title().to_string(),
+                group: item.group.clone(),
+                attachments: item.attachments.len(),
+                history: item.password_history.len(),
+                duplicate_of,
+                error,
+            });
+        }
+        Ok(preview)
+    }
+
+    //
+    // Stores the items in one transaction, items that do not validate are reported
+    // and left out while a database error rolls the whole import back. Items are
+    // duplicates of stored entries and of the items imported before them
+    //
+    pub fn import_items(&self, items: Vec<ImportItem>, options: &ImportOptions, warnings: Vec<String>) -> Result<ImportResult, String> {
+        let mut duplicates = match self.import_duplicate_index(&items) {
+            Ok(duplicates) => duplicates,
+            Err(_) => return Err("Reading the existing entries failed".into()),
+        };
+        // Stored logins for the references of imported logins, loaded once for the whole import
+        let mut logins = if items.iter().any(|item| item.kind == EntryKind::Login) {
+            match self.login_objects() {
+                Ok(logins) => logins,
+                Err(_) => return Err("Reading the existing entries failed".into()),
+            }
+        } else {
+            HashMap::new()
+        };
+
+        let mut selected: Vec<(usize, ImportItem)> = items
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| options.selected.as_ref().is_none_or(|selected| selected.contains(index)))
+            .collect();
+        // Logins referencing other entries go last so the entries they reference exist already
+        selected.sort_by_key(|(_, item)| item.entry.values().any(|value| value.as_str().is_some_and(|value| value.contains("{REF:"))));
+
+        if let Err(e) = self.conn.execute_batch("BEGIN") {
+            return Err(format!("Starting the import failed: {}", e));
+        }
+
+        let mut result = ImportResult { warnings, ..Default::default() };
+        for (_, mut item) in selected {
+            let mut object = match with_entry_type!(item.kind, T => Self::import_object::<T>(&item)) {
+                Ok(object) => object,
+                Err(e) => {
+                    result.failed.push(format!("{}: {}", item.title(), e));
+                    continue;
+                }
+            };
+
+            if let Some(existing) = Self::find_duplicate(&duplicates, item.kind, &object) {
+                if options.skip_duplicates {
+                    result.duplicates += 1;
+                    continue;
+                }
+                // The copy gets an id of its own
+                if object.get("id").and_then(Value::as_str) == Some(existing.as_str()) {
+                    item.set("id", "");
+                }
+            }
+
+            match with_entry_type!(item.kind, T => self.import_entry::<T>(&item, &mut logins)) {
+                Ok(Some(id)) => {
+                    result.imported += 1;
+                    // A free id is kept, so the stored id covers the one of the item
+                    object.insert("id".into(), Value::String(id.clone()));
+                    Self::index_duplicate(&mut duplicates, item.kind, &object, &id);
+                }
+                Ok(None) => result.failed.push(format!("{}: the entry could not be stored", item.title())),
+                Err(e) => {
+                    let _ = self.conn.execute_batch("ROLLBACK");
+                    return Err(e);
+                }
+            }
+        }
+
+        if let Err(e) = self.conn.execute_batch("COMMIT") {
+            let _ = self.conn.execute_batch("ROLLBACK");
+            return Err(format!("Saving the import failed: {}", e));
+        }
+        Ok(result)
+    }
+
+    //
+    // Stores an imported entry the way add_entry does, with its timestamps, tags, attachments
+    // and password history. The checks of new entries are skipped so expired cards of a backup
+    // are kept. Returns its id, None when the entry is refused ( like a reference to a missing
+    // login ) and an error when the database fails
+    //
+    fn import_entry<T: Entry>(
+        &self,
+        item: &ImportItem,
+        logins: &mut HashMap<String, Map<String, Value>>,
+    ) -> Result<Option<String>, String> {
+        let mut object = item.entry.clone();
+        object.insert("tags".into(), Value::from(item.tags()));
+        let mut entry: T = match serde_json::from_value(Value::Object(object)) {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("Invalid {}: {}", T::KIND.as_str(), e)),
+        };
+
+        let (mut object, values) = match self.entry_row(&mut entry, Some(logins), "Import Entry") {
+            Ok(row) => row,
+            Err(_) => return Ok(None),
+        };
+
+        let now = Self::now();
+        let uuid = match self.insert_entry::<T>(
+            &object,
+            values,
+            item.created.unwrap_or(now),
+            item.modified.unwrap_or(now),
+            item.accessed.unwrap_or(now),
+            "Import Entry",
+        ) {
+            Ok(uuid) => uuid,
+            Err(_) => return Err(format!("Storing {} failed", item.title())),
+        };
+
+        // Later logins of the import may reference this one
+        if T::KIND == EntryKind::Login {
+            object.insert("id".into(), Value::String(uuid.clone()));
+            logins.insert(uuid.clone(), object);
+        }
+
+        for attachment in &item.attachments {
+            if self.add_attachment(T::KIND, &uuid, &attachment.name, &attachment.data).is_err() {
+                return Err(format!("Storing attachment {} of {} failed", attachment.name, item.title()));
+            }
+        }
+        if T::KIND == EntryKind::Login {
+            for (password, replaced) in &item.password_history {
+                if !self.add_password_history(&uuid, password, *replaced) {
+                    return Err(format!("Storing the password history of {} failed", item.title()));
+                }
+            }
+        }
+
+        Ok(Some(uuid))
+    }
+
+    //
+    // Adds the templates of a backup the vault does not have yet, templates with
+    // the same id are kept as they are, returns the number of added templates
+    //
+    pub fn import_templates(&self, templates: Vec<Template>) -> Result<usize, bool> {
+        let existing = self.get_templates()?;
+
+        let mut added = 0;
+        for template in templates {
+            if existing.iter().any(|existing| existing.id == template.id) {
+                continue;
+            }
+            if !self.add_template(template) {
+                return Err(false);
+            }
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    //
+    // Every entry of the vault as the front end shows it ( references resolved ) for the
+    // plaintext exports, custom entries name their template and list its fields
+    //
+    pub fn plain_entries(&self) -> Result<Vec<PlainEntry>, bool> {
+        let templates = self.get_templates()?;
+
+        let mut entries = Vec::new();
+        for kind in EntryKind::ALL {
+            with_entry_type!(kind, T => {
+                for entry in self.get_entries::<T>()? {
+                    let object = Self::entry_object(&entry)?;
+                    let mut fields = Vec::new();
+                    for field in T::FIELDS {
+                        let value = object.get(field.name).cloned().unwrap_or(Value::Null);
+                        match (field.name, value) {
+                            ("template_id", Value::String(id)) => {
+                                let name = templates.iter().find(|template| template.id == id).map(|template| template.name.clone());
+                                fields.push(("template".to_string(), Value::String(name.unwrap_or(id))));
+                            }
+                            ("fields", Value::Object(values)) => {
+                                let template = object
+                                    .get("template_id")
+                                    .and_then(Value::as_str)
+                                    .and_then(|id| templates.iter().find(|template| template.id == id));
+                                let mut names: Vec<String> = match template {
+                                    Some(template) => template.fields.iter().map(|field| field.name.clone()).collect(),
+                                    None => Vec::new(),
+                                };
+                                names.extend(values.keys().filter(|name| !names.contains(name)).cloned().collect::<Vec<String>>());
+                                for name in names {
+                                    if let Some(value) = values.get(&name) {
+                                        fields.push((name, value.clone()));
+                                    }
+                                }
+                            }
+                            (name, value) => fields.push((name.to_string(), value)),
+                        }
+                    }
+                    for name in ["tags", "favorite", "created", "modified", "accessed", "expires"] {
+                        fields.push((name.to_string(), object.get(name).cloned().unwrap_or(Value::Null)));
+                    }
+                    entries.push(PlainEntry { kind, fields });
+                }
+            });
         }
         Ok(entries)
     }
-    
-}
\ No newline at end of file
+
+    //
+    // Every entry of the vault with its attachments and password history,
+    // the exporters write these ( logins keep their references unresolved )
+    //
+    pub fn export_items(&self) -> Result<Vec<ImportItem>, bool> {
+        self.collect_export_items(false)
+    }
+
+    //
+    // Every entry with the references of logins resolved, for formats that have no references
+    //
+    pub fn export_resolved_items(&self) -> Result<Vec<ImportItem>, bool> {
+        self.collect_export_items(true)
+    }
+
+    fn collect_export_items(&self, resolved: bool) -> Result<Vec<ImportItem>, bool> {
+        let mut items = Vec::new();
+        for kind in EntryKind::ALL {
+            with_entry_type!(kind, T => {
+                let entries = if resolved { self.get_entries::<T>()? } else { self.get_raw_entries::<T>()? };
+                for entry in entries {
+                    items.push(self.export_item(kind, Self::entry_object(&entry)?)?);
+                }
+            });
+        }
+        Ok(items)
+    }
+
+    fn export_item(&self, kind: EntryKind, object: Map<String, Value>) -> Result<ImportItem, bool> {
+        let id = object.get("id").and_then(Value::as_str).unwrap_or("").to_string();
+        let time = |field: &str| parse_timestamp(object.get(field).and_then(Value::as_str).unwrap_or("")).unwrap_or(None);
+
+        let mut item = ImportItem::new(kind);
+        item.created = time("created");
+        item.modified = time("modified");
+        item.accessed = time("accessed");
+
+        for attachment in self.get_attachments(&id)? {
+            let (name, data) = self.get_attachment_data(&attachment.id)?;
+            item.attachments.push(ImportAttachment { name, data });
+        }
+
+        if kind == EntryKind::Login {
+            for previous in self.get_password_history(&id)?.into_iter().rev() {
+                let replaced = parse_timestamp(&previous.replaced).unwrap_or(None).unwrap_or(0);
+                item.password_history.push((previous.password, replaced));
+            }
+        }
+
+        item.entry = object;
+        Ok(item)
+    }
+}
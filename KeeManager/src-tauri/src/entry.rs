@@ -1,10 +1,19 @@
+use chrono::{DateTime, NaiveDate, SecondsFormat};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::card::{self, CardNetwork};
+use crate::{ssh, totp, validation};
 
 //
 // Serializing and deserializing
 // the entry structs to be able
 // to send them to the front end
 //
+// created, modified, accessed and expires are RFC 3339 timestamps ( UTC ),
+// they are set by the database except for the optional expiry time
+//
 
 #[derive(Serialize, Deserialize)]
 pub struct LoginEntry {
@@ -14,9 +23,26 @@ pub struct LoginEntry {
     pub password: String,
     pub url: String,
     pub notes: String,
-    pub last_updated: String,
+    #[serde(default)]
+    pub otp: String,
+    // Stored values of fields holding "{REF:...}" references, the fields themselves are resolved
+    #[serde(default)]
+    pub references: HashMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
 }
 
+// card_number is stored without separators and expiry_date as MM/YY
 #[derive(Serialize, Deserialize)]
 pub struct CreditCardEntry {
     pub id: String,
@@ -25,7 +51,23 @@ pub struct CreditCardEntry {
     pub expiry_date: String,
     pub cardholder_name: String,
     pub cvv: String,
-    pub last_updated: String,
+    // Network and masked number are computed from the card number, they are not stored
+    #[serde(default)]
+    pub network: CardNetwork,
+    #[serde(default)]
+    pub masked_number: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,9 +75,21 @@ pub struct NoteEntry {
     pub id: String,
     pub title: String,
     pub content: String,
-    pub last_updated: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
 }
 
+// expiry_date is an optional ISO 8601 date ( YYYY-MM-DD )
 #[derive(Serialize, Deserialize)]
 pub struct IdentityEntry {
     pub id: String,
@@ -48,5 +102,632 @@ pub struct IdentityEntry {
     pub expiry_date: String,
     pub issuer: String,
     pub notes: String,
-    pub last_updated: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+// Public key, fingerprint and key type are computed
+// by the backend from the private ( or public ) key
+#[derive(Serialize, Deserialize)]
+pub struct SshKeyEntry {
+    pub id: String,
+    pub title: String,
+    pub private_key: String,
+    pub passphrase: String,
+    pub public_key: String,
+    pub comment: String,
+    #[serde(default)]
+    pub fingerprint: String,
+    #[serde(default)]
+    pub key_type: String,
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+// IBAN and BIC are validated and normalized before they are stored
+#[derive(Serialize, Deserialize)]
+pub struct BankAccountEntry {
+    pub id: String,
+    pub title: String,
+    pub account_holder: String,
+    pub bank_name: String,
+    pub iban: String,
+    pub bic: String,
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+// security_type is one of validation::WIFI_SECURITY_TYPES
+#[derive(Serialize, Deserialize)]
+pub struct WifiEntry {
+    pub id: String,
+    pub title: String,
+    pub ssid: String,
+    pub security_type: String,
+    pub password: String,
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+// expiry_date is an optional ISO 8601 date ( YYYY-MM-DD )
+#[derive(Serialize, Deserialize)]
+pub struct ApiCredentialEntry {
+    pub id: String,
+    pub title: String,
+    pub api_key: String,
+    pub secret: String,
+    pub endpoint: String,
+    pub expiry_date: String,
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+// seats set to 0 means the number of seats is not limited
+#[derive(Serialize, Deserialize)]
+pub struct SoftwareLicenseEntry {
+    pub id: String,
+    pub title: String,
+    pub product: String,
+    pub license_key: String,
+    pub licensee: String,
+    pub seats: u32,
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+// Entry created from a user-defined template, the field values
+// are checked against the template before they are stored
+#[derive(Serialize, Deserialize)]
+pub struct CustomEntry {
+    pub id: String,
+    pub template_id: String,
+    pub title: String,
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub modified: String,
+    #[serde(default)]
+    pub accessed: String,
+    #[serde(default)]
+    pub expires: Option<String>,
+}
+
+//
+// Timestamps entries can be sorted by
+//
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampField {
+    Created,
+    Modified,
+    Accessed,
+    Expires,
+}
+
+impl TimestampField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimestampField::Created => "created",
+            TimestampField::Modified => "modified",
+            TimestampField::Accessed => "accessed",
+            TimestampField::Expires => "expires",
+        }
+    }
+}
+
+//
+// Unix seconds as RFC 3339 in UTC ( "2024-05-01T12:30:00Z" ),
+// timestamps in this format also sort correctly as strings
+//
+pub fn format_timestamp(seconds: i64) -> String {
+    DateTime::from_timestamp(seconds, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+//
+// Parses an RFC 3339 timestamp or a plain date ( start of the day in UTC )
+// into unix seconds, empty means no timestamp
+//
+pub fn parse_timestamp(value: &str) -> Result<Option<i64>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(Some(datetime.timestamp()));
+    }
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => Ok(Some(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())),
+        Err(_) => Err(format!("Invalid timestamp, expected RFC 3339 or YYYY-MM-DD: {}", value)),
+    }
+}
+
+// A password that was replaced on a login, replaced is an RFC 3339 timestamp
+#[derive(Serialize, Deserialize)]
+pub struct PasswordHistoryItem {
+    pub password: String,
+    pub replaced: String,
+}
+
+// File attached to an entry, the data itself is only loaded when it is saved
+#[derive(Serialize, Deserialize)]
+pub struct AttachmentInfo {
+    pub id: String,
+    pub entry_id: String,
+    pub name: String,
+    pub size: i64,
+    pub created: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TagCount {
+    pub name: String,
+    pub count: i64,
+}
+
+//
+// Every entry type stored in the vault, the serialized names
+// match the "type" the front end gives each entry
+//
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Login,
+    CreditCard,
+    Note,
+    Identity,
+    SshKey,
+    BankAccount,
+    Wifi,
+    ApiCredential,
+    SoftwareLicense,
+    Custom,
+}
+
+impl EntryKind {
+    pub const ALL: [EntryKind; 10] = [
+        EntryKind::Login,
+        EntryKind::CreditCard,
+        EntryKind::Note,
+        EntryKind::Identity,
+        EntryKind::SshKey,
+        EntryKind::BankAccount,
+        EntryKind::Wifi,
+        EntryKind::ApiCredential,
+        EntryKind::SoftwareLicense,
+        EntryKind::Custom,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::Login => "login",
+            EntryKind::CreditCard => "credit_card",
+            EntryKind::Note => "note",
+            EntryKind::Identity => "identity",
+            EntryKind::SshKey => "ssh_key",
+            EntryKind::BankAccount => "bank_account",
+            EntryKind::Wifi => "wifi",
+            EntryKind::ApiCredential => "api_credential",
+            EntryKind::SoftwareLicense => "software_license",
+            EntryKind::Custom => "custom",
+        }
+    }
+}
+
+//
+// Runs the given expression with $entry_type set to
+// the struct that belongs to the entry kind
+//
+macro_rules! with_entry_type {
+    ($kind:expr, $entry_type:ident => $body:expr) => {
+        match $kind {
+            $crate::entry::EntryKind::Login => {
+                type $entry_type = $crate::entry::LoginEntry;
+                $body
+            }
+            $crate::entry::EntryKind::CreditCard => {
+                type $entry_type = $crate::entry::CreditCardEntry;
+                $body
+            }
+            $crate::entry::EntryKind::Note => {
+                type $entry_type = $crate::entry::NoteEntry;
+                $body
+            }
+            $crate::entry::EntryKind::Identity => {
+                type $entry_type = $crate::entry::IdentityEntry;
+                $body
+            }
+            $crate::entry::EntryKind::SshKey => {
+                type $entry_type = $crate::entry::SshKeyEntry;
+                $body
+            }
+            $crate::entry::EntryKind::BankAccount => {
+                type $entry_type = $crate::entry::BankAccountEntry;
+                $body
+            }
+            $crate::entry::EntryKind::Wifi => {
+                type $entry_type = $crate::entry::WifiEntry;
+                $body
+            }
+            $crate::entry::EntryKind::ApiCredential => {
+                type $entry_type = $crate::entry::ApiCredentialEntry;
+                $body
+            }
+            $crate::entry::EntryKind::SoftwareLicense => {
+                type $entry_type = $crate::entry::SoftwareLicenseEntry;
+                $body
+            }
+            $crate::entry::EntryKind::Custom => {
+                type $entry_type = $crate::entry::CustomEntry;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_entry_type;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FieldType {
+    Text,
+    Integer,
+    // Stored as JSON text ( maps and lists )
+    Json,
+}
+
+//
+// Describes one column of an entry table, the column name
+// is the same as the struct field name
+//
+pub struct FieldDescriptor {
+    pub name: &'static str,
+    pub field_type: FieldType,
+    pub encrypted: bool,
+}
+
+impl FieldDescriptor {
+    pub const fn encrypted(name: &'static str) -> Self {
+        FieldDescriptor {
+            name,
+            field_type: FieldType::Text,
+            encrypted: true,
+        }
+    }
+
+    pub const fn encrypted_integer(name: &'static str) -> Self {
+        FieldDescriptor {
+            name,
+            field_type: FieldType::Integer,
+            encrypted: true,
+        }
+    }
+
+    pub const fn encrypted_json(name: &'static str) -> Self {
+        FieldDescriptor {
+            name,
+            field_type: FieldType::Json,
+            encrypted: true,
+        }
+    }
+}
+
+//
+// Storage schema of an entry type, the database stores any type
+// implementing this trait ( id, tags, favorite and the timestamps are
+// common to every entry and handled by the database itself, the id
+// is a random UUID that stays the same across vaults )
+//
+pub trait Entry: Serialize + DeserializeOwned {
+    const KIND: EntryKind;
+    const TABLE: &'static str;
+    const FIELDS: &'static [FieldDescriptor];
+
+    //
+    // Normalizes and validates the entry before it is stored
+    //
+    fn validate(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    //
+    // Checks that only apply to entries being added, not to stored entries being edited
+    //
+    fn validate_new(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    //
+    // Fills in the values computed from the stored fields after the entry is loaded
+    //
+    fn complete(&mut self) {}
+}
+
+impl Entry for LoginEntry {
+    const KIND: EntryKind = EntryKind::Login;
+    const TABLE: &'static str = "LoginEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("username"),
+        FieldDescriptor::encrypted("password"),
+        FieldDescriptor::encrypted("url"),
+        FieldDescriptor::encrypted("notes"),
+        FieldDescriptor::encrypted("otp"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        self.otp = self.otp.trim().to_string();
+        if !self.otp.is_empty() {
+            totp::parse(&self.otp)?;
+        }
+        Ok(())
+    }
+}
+
+impl Entry for CreditCardEntry {
+    const KIND: EntryKind = EntryKind::CreditCard;
+    const TABLE: &'static str = "CreditCardEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("card_number"),
+        FieldDescriptor::encrypted("expiry_date"),
+        FieldDescriptor::encrypted("cardholder_name"),
+        FieldDescriptor::encrypted("cvv"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        let (card_number, network) = card::validate_number(&self.card_number)?;
+        card::validate_cvv(&self.cvv, network)?;
+        self.card_number = card_number;
+        self.cvv = self.cvv.trim().to_string();
+        if let Some(expiry) = validation::parse_card_expiry(&self.expiry_date)? {
+            self.expiry_date = expiry.format("%m/%y").to_string();
+        }
+        self.complete();
+        Ok(())
+    }
+
+    fn validate_new(&mut self) -> Result<(), String> {
+        card::check_not_expired(&self.expiry_date, chrono::Local::now().date_naive())
+    }
+
+    fn complete(&mut self) {
+        self.network = card::detect_network(&self.card_number);
+        self.masked_number = card::mask_number(&self.card_number);
+    }
+}
+
+impl Entry for NoteEntry {
+    const KIND: EntryKind = EntryKind::Note;
+    const TABLE: &'static str = "NoteEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("content"),
+    ];
+}
+
+impl Entry for IdentityEntry {
+    const KIND: EntryKind = EntryKind::Identity;
+    const TABLE: &'static str = "IdentityEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("full_name"),
+        FieldDescriptor::encrypted("date_of_birth"),
+        FieldDescriptor::encrypted("nationality"),
+        FieldDescriptor::encrypted("identification_number"),
+        FieldDescriptor::encrypted("issue_date"),
+        FieldDescriptor::encrypted("expiry_date"),
+        FieldDescriptor::encrypted("issuer"),
+        FieldDescriptor::encrypted("notes"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        self.expiry_date = match validation::validate_iso_date(&self.expiry_date)? {
+            Some(expiry) => expiry.format("%Y-%m-%d").to_string(),
+            None => String::new(),
+        };
+        Ok(())
+    }
+}
+
+impl Entry for SshKeyEntry {
+    const KIND: EntryKind = EntryKind::SshKey;
+    const TABLE: &'static str = "SshKeyEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("private_key"),
+        FieldDescriptor::encrypted("passphrase"),
+        FieldDescriptor::encrypted("public_key"),
+        FieldDescriptor::encrypted("comment"),
+        FieldDescriptor::encrypted("fingerprint"),
+        FieldDescriptor::encrypted("key_type"),
+        FieldDescriptor::encrypted("notes"),
+    ];
+
+    //
+    // Computes public key, fingerprint and key type from the private key
+    // ( or from the public key alone when no private key is stored )
+    //
+    fn validate(&mut self) -> Result<(), String> {
+        let key_info = if !self.private_key.trim().is_empty() {
+            ssh::inspect_private_key(&self.private_key, &self.passphrase)?
+        } else {
+            ssh::inspect_public_key(&self.public_key)?
+        };
+
+        self.public_key = key_info.public_key;
+        self.fingerprint = key_info.fingerprint;
+        self.key_type = key_info.key_type;
+        if self.comment.trim().is_empty() {
+            self.comment = key_info.comment;
+        }
+        Ok(())
+    }
+}
+
+impl Entry for BankAccountEntry {
+    const KIND: EntryKind = EntryKind::BankAccount;
+    const TABLE: &'static str = "BankAccountEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("account_holder"),
+        FieldDescriptor::encrypted("bank_name"),
+        FieldDescriptor::encrypted("iban"),
+        FieldDescriptor::encrypted("bic"),
+        FieldDescriptor::encrypted("notes"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        self.iban = validation::validate_iban(&self.iban)?;
+        self.bic = validation::validate_bic(&self.bic)?;
+        Ok(())
+    }
+}
+
+impl Entry for WifiEntry {
+    const KIND: EntryKind = EntryKind::Wifi;
+    const TABLE: &'static str = "WifiEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("ssid"),
+        FieldDescriptor::encrypted("security_type"),
+        FieldDescriptor::encrypted("password"),
+        FieldDescriptor::encrypted("notes"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        validation::validate_wifi(&self.ssid, &self.security_type, &self.password)
+    }
+}
+
+impl Entry for ApiCredentialEntry {
+    const KIND: EntryKind = EntryKind::ApiCredential;
+    const TABLE: &'static str = "ApiCredentialEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("api_key"),
+        FieldDescriptor::encrypted("secret"),
+        FieldDescriptor::encrypted("endpoint"),
+        FieldDescriptor::encrypted("expiry_date"),
+        FieldDescriptor::encrypted("notes"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        validation::validate_endpoint(&self.endpoint)?;
+        self.expiry_date = match validation::validate_iso_date(&self.expiry_date)? {
+            Some(expiry) => expiry.format("%Y-%m-%d").to_string(),
+            None => String::new(),
+        };
+        Ok(())
+    }
+}
+
+impl Entry for SoftwareLicenseEntry {
+    const KIND: EntryKind = EntryKind::SoftwareLicense;
+    const TABLE: &'static str = "SoftwareLicenseEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("product"),
+        FieldDescriptor::encrypted("license_key"),
+        FieldDescriptor::encrypted("licensee"),
+        FieldDescriptor::encrypted_integer("seats"),
+        FieldDescriptor::encrypted("notes"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        if self.license_key.trim().is_empty() {
+            return Err("License key is empty".into());
+        }
+        Ok(())
+    }
+}
+
+// The values are validated against the template by the database,
+// which is the only place the template can be loaded from
+impl Entry for CustomEntry {
+    const KIND: EntryKind = EntryKind::Custom;
+    const TABLE: &'static str = "CustomEntries";
+    const FIELDS: &'static [FieldDescriptor] = &[
+        FieldDescriptor::encrypted("title"),
+        FieldDescriptor::encrypted("template_id"),
+        FieldDescriptor::encrypted_json("fields"),
+    ];
+
+    fn validate(&mut self) -> Result<(), String> {
+        if self.template_id.trim().is_empty() {
+            return Err("Custom entry has no template".into());
+        }
+        Ok(())
+    }
 }
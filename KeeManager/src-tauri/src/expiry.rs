@@ -0,0 +1,51 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::entry::EntryKind;
+
+// Items expiring within this many days are reported unless the configuration sets another number
+pub const DEFAULT_WARNING_DAYS: u32 = 30;
+
+// Name of the event sent to the front end when the vault is opened
+pub const EXPIRING_ITEMS_EVENT: &str = "expiring-items";
+
+//
+// Credit card or identity document that is expired or about to expire
+//
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExpiringItem {
+    pub id: String,
+    pub kind: EntryKind,
+    pub title: String,
+    // Last day the item is valid ( YYYY-MM-DD ), cards are valid until the end of their expiry month
+    pub expires_on: String,
+    // Negative once the item is expired
+    pub days_left: i64,
+    pub expired: bool,
+}
+
+//
+// The item if it expires within the given number of days from today or is already expired
+//
+pub fn check(
+    id: &str,
+    kind: EntryKind,
+    title: &str,
+    expires_on: NaiveDate,
+    today: NaiveDate,
+    within_days: u32,
+) -> Option<ExpiringItem> {
+    let days_left = (expires_on - today).num_days();
+    if days_left > within_days as i64 {
+        return None;
+    }
+
+    Some(ExpiringItem {
+        id: id.to_string(),
+        kind,
+        title: title.to_string(),
+        expires_on: expires_on.format("%Y-%m-%d").to_string(),
+        days_left,
+        expired: days_left < 0,
+    })
+}
@@ -0,0 +1,34 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+//
+// Creates or replaces the file readable and writable by its owner only. On Windows
+// the file gets the permissions of its folder, the user's own folders by default
+//
+pub fn write_private(path: &str, data: &[u8]) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = match options.open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Writing {} failed: {}", path, e)),
+    };
+    // The mode only applies to new files, an existing one keeps its permissions otherwise
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = file.set_permissions(fs::Permissions::from_mode(0o600)) {
+            return Err(format!("Restricting the permissions of {} failed: {}", path, e));
+        }
+    }
+
+    match file.write_all(data).and_then(|_| file.sync_all()) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("Writing {} failed: {}", path, e)),
+    }
+}
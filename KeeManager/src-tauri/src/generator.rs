@@ -0,0 +1,471 @@
+use aes_gcm::aead::OsRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!@#$%^&*()-_=+[]{};:,.<>/?~";
+const LOOK_ALIKES: &str = "0OoIl1|`'\"";
+
+const MIN_LENGTH: usize = 4;
+const MAX_LENGTH: usize = 256;
+
+//
+// Settings a password is generated with, policies with a name
+// are saved in the configuration so they can be chosen again
+//
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PasswordPolicy {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_length")]
+    pub length: usize,
+    #[serde(default = "enabled")]
+    pub lowercase: bool,
+    #[serde(default = "enabled")]
+    pub uppercase: bool,
+    #[serde(default = "enabled")]
+    pub digits: bool,
+    #[serde(default = "enabled")]
+    pub symbols: bool,
+    #[serde(default)]
+    pub min_lowercase: usize,
+    #[serde(default)]
+    pub min_uppercase: usize,
+    #[serde(default)]
+    pub min_digits: usize,
+    #[serde(default)]
+    pub min_symbols: usize,
+    // Leave out characters that are easily confused ( 0 O o I l 1 | ... )
+    #[serde(default)]
+    pub exclude_look_alikes: bool,
+    // Further characters to leave out
+    #[serde(default)]
+    pub exclude: String,
+    // Extra characters added to the enabled classes
+    #[serde(default)]
+    pub custom_characters: String,
+    // When set the password follows the pattern instead of length and classes
+    #[serde(default)]
+    pub pattern: String,
+}
+
+fn default_length() -> usize {
+    20
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            name: String::new(),
+            length: default_length(),
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            min_lowercase: 0,
+            min_uppercase: 0,
+            min_digits: 0,
+            min_symbols: 0,
+            exclude_look_alikes: false,
+            exclude: String::new(),
+            custom_characters: String::new(),
+            pattern: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GeneratedPassword {
+    pub password: String,
+    // Entropy in bits, an upper bound when minimum counts are set
+    // since those rule out some of the passwords the pool could form
+    pub entropy: f64,
+}
+
+//
+// Policies available without any configuration
+//
+pub fn default_policies() -> Vec<PasswordPolicy> {
+    vec![
+        PasswordPolicy {
+            name: "Strong".into(),
+            min_lowercase: 1,
+            min_uppercase: 1,
+            min_digits: 1,
+            min_symbols: 1,
+            ..Default::default()
+        },
+        PasswordPolicy {
+            name: "Easy to read".into(),
+            length: 16,
+            symbols: false,
+            exclude_look_alikes: true,
+            ..Default::default()
+        },
+        PasswordPolicy {
+            name: "PIN".into(),
+            pattern: "d{6}".into(),
+            ..Default::default()
+        },
+    ]
+}
+
+//
+// Built-in policies followed by the saved ones,
+// a saved policy replaces a built-in one with the same name
+//
+pub fn all_policies(saved: &[PasswordPolicy]) -> Vec<PasswordPolicy> {
+    let mut policies: Vec<PasswordPolicy> = default_policies()
+        .into_iter()
+        .filter(|policy| !saved.iter().any(|saved| saved.name.eq_ignore_ascii_case(&policy.name)))
+        .collect();
+    policies.extend(saved.iter().cloned());
+    policies
+}
+
+pub fn find_policy(saved: &[PasswordPolicy], name: &str) -> Option<PasswordPolicy> {
+    all_policies(saved)
+        .into_iter()
+        .find(|policy| policy.name.eq_ignore_ascii_case(name))
+}
+
+//
+// Checks a policy without generating a password
+//
+pub fn validate_policy(policy: &PasswordPolicy) -> Result<(), String> {
+    if policy.pattern.is_empty() {
+        character_classes(policy).map(|_| ())
+    } else {
+        parse_pattern(policy).map(|_| ())
+    }
+}
+
+//
+// Generates a password with the operating system's random number generator.
+// The entropy is length * log2( pool size ), which overstates the real entropy
+// once minimum counts are enforced, so it is only an upper bound for such policies
+//
+pub fn generate_password(policy: &PasswordPolicy) -> Result<GeneratedPassword, String> {
+    if !policy.pattern.is_empty() {
+        return generate_from_pattern(policy);
+    }
+
+    let classes = character_classes(policy)?;
+
+    // Characters of every enabled class, each only once
+    let mut pool: Vec<char> = Vec::new();
+    for (characters, _) in &classes {
+        for c in characters {
+            if !pool.contains(c) {
+                pool.push(*c);
+            }
+        }
+    }
+
+    let mut password: Vec<char> = Vec::with_capacity(policy.length);
+    for (characters, minimum) in &classes {
+        for _ in 0..*minimum {
+            password.push(characters[OsRng.gen_range(0..characters.len())]);
+        }
+    }
+    while password.len() < policy.length {
+        password.push(pool[OsRng.gen_range(0..pool.len())]);
+    }
+    // The minimum characters must not always come first
+    password.shuffle(&mut OsRng);
+
+    Ok(GeneratedPassword {
+        password: password.into_iter().collect(),
+        entropy: policy.length as f64 * (pool.len() as f64).log2(),
+    })
+}
+
+//
+// Remaining characters of a set after the excluded ones are removed
+//
+fn allowed_characters(characters: &str, policy: &PasswordPolicy) -> Vec<char> {
+    let mut allowed: Vec<char> = Vec::new();
+    for c in characters.chars() {
+        if (policy.exclude_look_alikes && LOOK_ALIKES.contains(c))
+            || policy.exclude.contains(c)
+            || c.is_control()
+            || allowed.contains(&c)
+        {
+            continue;
+        }
+        allowed.push(c);
+    }
+    allowed
+}
+
+//
+// Enabled character classes with their minimum counts
+//
+fn character_classes(policy: &PasswordPolicy) -> Result<Vec<(Vec<char>, usize)>, String> {
+    if policy.length < MIN_LENGTH || policy.length > MAX_LENGTH {
+        return Err(format!(
+            "Password length must be between {} and {}",
+            MIN_LENGTH, MAX_LENGTH
+        ));
+    }
+
+    let mut classes = Vec::new();
+    for (name, characters, enabled, minimum) in [
+        ("lowercase letters", LOWERCASE, policy.lowercase, policy.min_lowercase),
+        ("uppercase letters", UPPERCASE, policy.uppercase, policy.min_uppercase),
+        ("digits", DIGITS, policy.digits, policy.min_digits),
+        ("symbols", SYMBOLS, policy.symbols, policy.min_symbols),
+    ] {
+        if !enabled {
+            if minimum > 0 {
+                return Err(format!("A minimum is set for {} but they are disabled", name));
+            }
+            continue;
+        }
+        let characters = allowed_characters(characters, policy);
+        if characters.is_empty() {
+            return Err(format!("All {} are excluded", name));
+        }
+        classes.push((characters, minimum));
+    }
+
+    let custom_characters = allowed_characters(&policy.custom_characters, policy);
+    if !custom_characters.is_empty() {
+        classes.push((custom_characters, 0));
+    }
+
+    if classes.is_empty() {
+        return Err("No characters to generate the password from".into());
+    }
+
+    let minimum: usize = classes.iter().map(|(_, minimum)| minimum).sum();
+    if minimum > policy.length {
+        return Err(format!(
+            "The minimum counts add up to {} which is more than the length of {}",
+            minimum, policy.length
+        ));
+    }
+
+    Ok(classes)
+}
+
+//
+// Patterns choose a set for every position:
+// l lowercase, u uppercase, d digit, s symbol, a lowercase or digit, U uppercase or digit,
+// A letter or digit, x any, h / H lower / upper case hex, [abc] one of the characters,
+// \c the character c, {n} repeats the previous element, anything else is kept as it is
+//
+fn parse_pattern(policy: &PasswordPolicy) -> Result<Vec<Vec<char>>, String> {
+    let mut positions: Vec<Vec<char>> = Vec::new();
+    let mut chars = policy.pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let set = match c {
+            'l' => allowed_characters(LOWERCASE, policy),
+            'u' => allowed_characters(UPPERCASE, policy),
+            'd' => allowed_characters(DIGITS, policy),
+            's' => allowed_characters(SYMBOLS, policy),
+            'a' => allowed_characters(&format!("{}{}", LOWERCASE, DIGITS), policy),
+            'U' => allowed_characters(&format!("{}{}", UPPERCASE, DIGITS), policy),
+            'A' => allowed_characters(&format!("{}{}{}", LOWERCASE, UPPERCASE, DIGITS), policy),
+            'x' => allowed_characters(&format!("{}{}{}{}", LOWERCASE, UPPERCASE, DIGITS, SYMBOLS), policy),
+            'h' => allowed_characters("0123456789abcdef", policy),
+            'H' => allowed_characters("0123456789ABCDEF", policy),
+            '\\' => match chars.next() {
+                Some(literal) => vec![literal],
+                None => return Err("Pattern ends with an escape character".into()),
+            },
+            '[' => {
+                let mut characters = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('\\') => match chars.next() {
+                            Some(literal) => characters.push(literal),
+                            None => return Err("Pattern ends with an escape character".into()),
+                        },
+                        Some(c) => characters.push(c),
+                        None => return Err("Pattern has an unclosed [".into()),
+                    }
+                }
+                allowed_characters(&characters, policy)
+            }
+            '{' => {
+                let mut count = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => count.push(c),
+                        None => return Err("Pattern has an unclosed {".into()),
+                    }
+                }
+                let count: usize = match count.trim().parse() {
+                    Ok(count) => count,
+                    Err(_) => return Err(format!("Invalid repeat count in pattern: {}", count)),
+                };
+                let previous = match positions.pop() {
+                    Some(previous) => previous,
+                    None => return Err("Pattern starts with a repeat count".into()),
+                };
+                // A huge count would overflow the sum
+                if count > MAX_LENGTH || positions.len() + count > MAX_LENGTH {
+                    return Err(format!("Patterns may generate at most {} characters", MAX_LENGTH));
+                }
+                for _ in 0..count {
+                    positions.push(previous.clone());
+                }
+                continue;
+            }
+            literal => vec![literal],
+        };
+
+        if set.is_empty() {
+            return Err(format!("All characters for '{}' in the pattern are excluded", c));
+        }
+        positions.push(set);
+    }
+
+    if positions.is_empty() {
+        return Err("Pattern generates an empty password".into());
+    }
+    if positions.len() > MAX_LENGTH {
+        return Err(format!("Patterns may generate at most {} characters", MAX_LENGTH));
+    }
+
+    Ok(positions)
+}
+
+fn generate_from_pattern(policy: &PasswordPolicy) -> Result<GeneratedPassword, String> {
+    let positions = parse_pattern(policy)?;
+
+    let password = positions
+        .iter()
+        .map(|set| set[OsRng.gen_range(0..set.len())])
+        .collect();
+    let entropy = positions.iter().map(|set| (set.len() as f64).log2()).sum();
+
+    Ok(GeneratedPassword { password, entropy })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(password: &str, characters: &str) -> usize {
+        password.chars().filter(|c| characters.contains(*c)).count()
+    }
+
+    #[test]
+    fn minimum_counts_are_met() {
+        let policy = PasswordPolicy {
+            length: 8,
+            min_lowercase: 2,
+            min_uppercase: 2,
+            min_digits: 2,
+            min_symbols: 2,
+            ..Default::default()
+        };
+        for _ in 0..50 {
+            let password = generate_password(&policy).unwrap().password;
+            assert_eq!(password.chars().count(), 8);
+            assert_eq!(count(&password, LOWERCASE), 2);
+            assert_eq!(count(&password, UPPERCASE), 2);
+            assert_eq!(count(&password, DIGITS), 2);
+            assert_eq!(count(&password, SYMBOLS), 2);
+        }
+    }
+
+    #[test]
+    fn invalid_minimums_are_refused() {
+        let too_many = PasswordPolicy { length: 4, min_digits: 3, min_symbols: 2, ..Default::default() };
+        assert!(validate_policy(&too_many).unwrap_err().contains("add up to 5"));
+
+        let disabled = PasswordPolicy { digits: false, min_digits: 1, ..Default::default() };
+        assert!(validate_policy(&disabled).unwrap_err().contains("digits"));
+
+        let short = PasswordPolicy { length: 3, ..Default::default() };
+        assert!(validate_policy(&short).is_err());
+    }
+
+    #[test]
+    fn excluded_characters_are_left_out() {
+        let policy = PasswordPolicy {
+            length: 256,
+            exclude: "aeiouAEIOU!".into(),
+            exclude_look_alikes: true,
+            ..Default::default()
+        };
+        for _ in 0..10 {
+            let password = generate_password(&policy).unwrap().password;
+            assert_eq!(count(&password, "aeiouAEIOU!"), 0);
+            assert_eq!(count(&password, LOOK_ALIKES), 0);
+        }
+
+        let no_digits = PasswordPolicy { exclude: DIGITS.into(), ..Default::default() };
+        assert_eq!(validate_policy(&no_digits).unwrap_err(), "All digits are excluded");
+    }
+
+    #[test]
+    fn entropy_is_length_times_pool_bits() {
+        let policy = PasswordPolicy { length: 10, uppercase: false, symbols: false, ..Default::default() };
+        let generated = generate_password(&policy).unwrap();
+        assert_eq!(count(&generated.password, &format!("{}{}", LOWERCASE, DIGITS)), 10);
+        assert!((generated.entropy - 10.0 * 36f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn patterns_choose_a_set_per_position() {
+        let policy = PasswordPolicy { pattern: "ll\\d-[xyz]{3}H".into(), ..Default::default() };
+        let positions = parse_pattern(&policy).unwrap();
+        assert_eq!(positions.len(), 8);
+        assert_eq!(positions[0].len(), 26);
+        assert_eq!(positions[2], vec!['d']);
+        assert_eq!(positions[3], vec!['-']);
+        assert_eq!(positions[4], vec!['x', 'y', 'z']);
+        assert_eq!(positions[6], vec!['x', 'y', 'z']);
+        assert_eq!(positions[7].len(), 16);
+
+        let generated = generate_password(&policy).unwrap();
+        assert!(generated.password.starts_with(|c: char| c.is_ascii_lowercase()));
+        assert_eq!(&generated.password[2..4], "d-");
+        let expected = 2.0 * 26f64.log2() + 3.0 * 3f64.log2() + 16f64.log2();
+        assert!((generated.entropy - expected).abs() < 1e-9);
+
+        let pin = PasswordPolicy { pattern: "d{6}".into(), ..Default::default() };
+        let pin = generate_password(&pin).unwrap().password;
+        assert!(pin.len() == 6 && pin.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn patterns_apply_exclusions() {
+        let policy = PasswordPolicy { pattern: "[0Oab]".into(), exclude_look_alikes: true, ..Default::default() };
+        assert_eq!(parse_pattern(&policy).unwrap(), vec![vec!['a', 'b']]);
+
+        let policy = PasswordPolicy { pattern: "d".into(), exclude: DIGITS.into(), ..Default::default() };
+        assert!(parse_pattern(&policy).is_err());
+    }
+
+    #[test]
+    fn invalid_patterns_are_refused() {
+        for (pattern, error) in [
+            ("l\\", "Pattern ends with an escape character"),
+            ("[abc", "Pattern has an unclosed ["),
+            ("l{3", "Pattern has an unclosed {"),
+            ("{3}l", "Pattern starts with a repeat count"),
+            ("l{x}", "Invalid repeat count in pattern: x"),
+            ("l{0}", "Pattern generates an empty password"),
+            ("l{300}", "Patterns may generate at most 256 characters"),
+            ("ll{18446744073709551615}", "Patterns may generate at most 256 characters"),
+        ] {
+            let policy = PasswordPolicy { pattern: pattern.into(), ..Default::default() };
+            assert_eq!(parse_pattern(&policy).unwrap_err(), error, "{}", pattern);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::encode;
+use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
 
 //
@@ -25,3 +26,17 @@ pub fn hash_sha256_as_bytes(input: Vec<u8>) -> Result<[u8; 32], String> {
     key.copy_from_slice(&result[..32]);
     Ok(key)
 }
+
+//
+// Keyed hash ( HMAC-SHA-256 ) of given input bytes encoded to Base64,
+// lets values be compared without keeping them in plain text
+//
+pub fn hmac_sha256(key: &[u8], input: &[u8]) -> Result<String, String> {
+    let mut mac = match Hmac::<Sha256>::new_from_slice(key) {
+        Ok(mac) => mac,
+        Err(e) => return Err(e.to_string()),
+    };
+    mac.update(input);
+
+    Ok(encode::encode_base64(mac.finalize().into_bytes().to_vec()))
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DEFAULT_MAX_AGE_DAYS: u32 = 365;
+// Passwords scoring below this are reported as weak
+pub const DEFAULT_MIN_SCORE: u8 = 3;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+//
+// What the report needs to know about a login, the password itself
+// is only present as a keyed hash
+//
+pub struct HealthLogin {
+    pub id: String,
+    pub username: String,
+    pub url: String,
+    pub password_hash: Option<String>,
+    pub score: u8,
+    // Last modification of the login ( unix seconds ), the password is
+    // counted as changed then
+    pub password_changed: i64,
+    pub has_two_factor: bool,
+    pub reuses_previous_password: bool,
+}
+
+//
+// Findings of the vault health check, every finding lists the ids of the
+// logins concerned, groups hold logins sharing a password or username and URL
+//
+#[derive(Serialize, Deserialize, Default)]
+pub struct HealthReport {
+    pub total_logins: usize,
+    pub max_age_days: u32,
+    pub min_score: u8,
+    pub reused: Vec<Vec<String>>,
+    pub reused_previous: Vec<String>,
+    pub weak: Vec<String>,
+    // Logins not modified in more than max_age_days
+    pub old: Vec<String>,
+    pub duplicates: Vec<Vec<String>>,
+    pub missing_two_factor: Vec<String>,
+}
+
+pub fn build_report(logins: &[HealthLogin], max_age_days: u32, min_score: u8, now: i64) -> HealthReport {
+    let mut report = HealthReport {
+        total_logins: logins.len(),
+        max_age_days,
+        min_score,
+        ..Default::default()
+    };
+
+    let mut by_password: HashMap<&str, Vec<String>> = HashMap::new();
+    let mut by_account: HashMap<(String, String), Vec<String>> = HashMap::new();
+    let max_age = max_age_days as i64 * SECONDS_PER_DAY;
+
+    for login in logins {
+        if let Some(password_hash) = &login.password_hash {
+            by_password.entry(password_hash).or_default().push(login.id.clone());
+        }
+
+        if login.reuses_previous_password {
+            report.reused_previous.push(login.id.clone());
+        }
+        if login.score < min_score {
+            report.weak.push(login.id.clone());
+        }
+        if now - login.password_changed > max_age {
+            report.old.push(login.id.clone());
+        }
+        if !login.has_two_factor {
+            report.missing_two_factor.push(login.id.clone());
+        }
+
+        let username = login.username.trim().to_lowercase();
+        let url = normalize_url(&login.url);
+        if !username.is_empty() || !url.is_empty() {
+            by_account.entry((username, url)).or_default().push(login.id.clone());
+        }
+    }
+
+    report.reused = groups(by_password.into_values());
+    report.duplicates = groups(by_account.into_values());
+    report
+}
+
+// Groups with more than one login, in a stable order
+fn groups(values: impl Iterator<Item = Vec<String>>) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = values.filter(|ids| ids.len() > 1).collect();
+    for group in groups.iter_mut() {
+        group.sort();
+    }
+    groups.sort();
+    groups
+}
+
+//
+// URL without scheme, "www." and trailing slashes so
+// "https://www.example.com/" and "example.com" are the same site
+//
+fn normalize_url(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let url = match url.find("://") {
+        Some(index) => &url[index + 3..],
+        None => &url,
+    };
+    let url = url.strip_prefix("www.").unwrap_or(url);
+    url.trim_end_matches('/').to_string()
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::entry::EntryKind;
+
+//
+// Entry read from another password manager's export or taken from the vault
+// to be exported, the entry object looks like the ones the front end sends
+// ( id, fields, tags, favorite and expires ), the other timestamps are unix seconds
+//
+pub struct ImportItem {
+    pub kind: EntryKind,
+    pub entry: Map<String, Value>,
+    // Folder or group the entry was in, it becomes a tag
+    pub group: String,
+    pub created: Option<i64>,
+    pub modified: Option<i64>,
+    pub accessed: Option<i64>,
+    pub attachments: Vec<ImportAttachment>,
+    // Previous passwords of a login and when they were replaced, oldest first
+    pub password_history: Vec<(String, i64)>,
+}
+
+pub struct ImportAttachment {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl ImportItem {
+    pub fn new(kind: EntryKind) -> Self {
+        ImportItem {
+            kind,
+            entry: Map::new(),
+            group: String::new(),
+            created: None,
+            modified: None,
+            accessed: None,
+            attachments: Vec::new(),
+            password_history: Vec::new(),
+        }
+    }
+
+    pub fn set(&mut self, field: &str, value: impl Into<Value>) {
+        self.entry.insert(field.to_string(), value.into());
+    }
+
+    pub fn title(&self) -> &str {
+        self.entry.get("title").and_then(Value::as_str).unwrap_or("")
+    }
+
+    //
+    // Tags of the entry with the group added
+    //
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = match self.entry.get("tags") {
+            Some(Value::Array(tags)) => tags.iter().filter_map(|tag| tag.as_str().map(str::to_string)).collect(),
+            _ => Vec::new(),
+        };
+        let group = self.group.trim();
+        if !group.is_empty() && !tags.iter().any(|tag| tag == group) {
+            tags.push(group.to_string());
+        }
+        tags
+    }
+}
+
+//
+// Values KeeManager has no field for, they are kept in the notes
+// when the entry type has notes and reported otherwise
+//
+#[derive(Default)]
+pub struct Unmapped {
+    values: Vec<(String, String)>,
+}
+
+impl Unmapped {
+    pub fn add(&mut self, name: &str, value: &str) {
+        if !value.trim().is_empty() {
+            self.values.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    pub fn finish(self, item: &mut ImportItem, notes_field: Option<&str>, warnings: &mut Vec<String>) {
+        if self.values.is_empty() {
+            return;
+        }
+        let names: Vec<&str> = self.values.iter().map(|(name, _)| name.as_str()).collect();
+
+        match notes_field {
+            Some(notes_field) => {
+                let lines: Vec<String> = self.values.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+                let notes = item.entry.get(notes_field).and_then(Value::as_str).unwrap_or("").to_string();
+                let notes = if notes.is_empty() {
+                    lines.join("\n")
+                } else {
+                    format!("{}\n\n{}", notes, lines.join("\n"))
+                };
+                item.set(notes_field, notes);
+                warnings.push(format!("{}: kept in the notes: {}", item.title(), names.join(", ")));
+            }
+            None => warnings.push(format!("{}: not imported: {}", item.title(), names.join(", "))),
+        }
+    }
+}
+
+//
+// What an import would do with an item, shown before anything is stored
+//
+#[derive(Serialize, Deserialize)]
+pub struct PreviewItem {
+    pub index: usize,
+    pub kind: EntryKind,
+    pub title: String,
+    pub group: String,
+    pub attachments: usize,
+    pub history: usize,
+    // Existing entry with the same id or the same values,
+    // "import:<index>" for an earlier item of the same import
+    pub duplicate_of: Option<String>,
+    // Why the item cannot be imported
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ImportPreview {
+    pub items: Vec<PreviewItem>,
+    // Data the importer could not map, like unsupported fields
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ImportOptions {
+    #[serde(default = "default_skip_duplicates")]
+    pub skip_duplicates: bool,
+    // Indexes of the preview items to import, all of them when not set
+    #[serde(default)]
+    pub selected: Option<Vec<usize>>,
+}
+
+fn default_skip_duplicates() -> bool {
+    true
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            skip_duplicates: default_skip_duplicates(),
+            selected: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ImportResult {
+    pub imported: usize,
+    pub duplicates: usize,
+    // Titles of the items that were not imported and why
+    pub failed: Vec<String>,
+    pub warnings: Vec<String>,
+}
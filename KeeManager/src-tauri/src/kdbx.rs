@@ -0,0 +1,1112 @@
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncrypt, KeyInit, KeyIvInit, StreamCipher};
+use aes::Aes256;
+use aes_gcm::aead::OsRng;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20::ChaCha20;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use salsa20::Salsa20;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use uuid::Uuid;
+
+use crate::encode;
+
+//
+// KeePass KDBX 4 files: an unencrypted outer header, the payload in HMAC
+// protected blocks, encrypted and usually gzip compressed. The payload starts
+// with the inner header ( inner random stream and attachments ) followed by
+// the XML document, protected values in it are XORed with the inner random stream
+//
+
+const SIGNATURE_1: u32 = 0x9AA2_D903;
+const SIGNATURE_2: u32 = 0xB54B_FB67;
+const MAJOR_VERSION: u16 = 4;
+
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xC1, 0xF2, 0xE6, 0xBF, 0x71, 0x43, 0x50, 0xBE, 0x58, 0x05, 0x21, 0x6A, 0xFC, 0x5A, 0xFF,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xD6, 0x03, 0x8A, 0x2B, 0x8B, 0x6F, 0x4C, 0xB5, 0xA5, 0x24, 0x33, 0x9A, 0x31, 0xDB, 0xB5, 0x9A,
+];
+const KDF_AES: [u8; 16] = [
+    0xC9, 0xD9, 0xF3, 0x9A, 0x62, 0x8A, 0x44, 0x60, 0xBF, 0x74, 0x0D, 0x08, 0xC1, 0x8A, 0x4F, 0xEA,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xEF, 0x63, 0x6D, 0xDF, 0x8C, 0x29, 0x44, 0x4B, 0x91, 0xF7, 0xA9, 0xA4, 0x03, 0xE3, 0x0A, 0x0C,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9E, 0x29, 0x8B, 0x19, 0x56, 0xDB, 0x47, 0x73, 0xB2, 0x3D, 0xFC, 0x3E, 0xC6, 0xF0, 0xA1, 0xE6,
+];
+
+// Outer header fields
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER_ID: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_ENCRYPTION_IV: u8 = 7;
+const HEADER_KDF_PARAMETERS: u8 = 11;
+
+// Inner header fields
+const INNER_HEADER_END: u8 = 0;
+const INNER_RANDOM_STREAM_ID: u8 = 1;
+const INNER_RANDOM_STREAM_KEY: u8 = 2;
+const INNER_BINARY: u8 = 3;
+
+const STREAM_SALSA20: u32 = 2;
+const STREAM_CHACHA20: u32 = 3;
+const SALSA20_NONCE: [u8; 8] = [0xE8, 0x30, 0x09, 0x4B, 0x97, 0x20, 0x5D, 0x2A];
+
+// Variant dictionary ( KDF parameters ) value types
+const VARIANT_VERSION: u16 = 0x0100;
+const VARIANT_END: u8 = 0x00;
+const VARIANT_UINT32: u8 = 0x04;
+const VARIANT_UINT64: u8 = 0x05;
+const VARIANT_BOOL: u8 = 0x08;
+const VARIANT_INT32: u8 = 0x0C;
+const VARIANT_INT64: u8 = 0x0D;
+const VARIANT_STRING: u8 = 0x18;
+const VARIANT_BYTES: u8 = 0x42;
+
+// Limits for reading, crafted KDF parameters must not make the import
+// run out of memory or keep transforming the key for hours
+const MAX_AES_ROUNDS: u64 = 100_000_000;
+const MAX_ARGON2_MEMORY: u64 = 1024 * 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u64 = 100;
+const MAX_ARGON2_PARALLELISM: u64 = 64;
+
+const BLOCK_SIZE: usize = 1024 * 1024;
+// Seconds between 0001-01-01 and the unix epoch, KDBX 4 times count from the former
+const EPOCH_OFFSET: i64 = 62_135_596_800;
+
+// Standard entry fields, every other string is a custom field
+pub const TITLE: &str = "Title";
+pub const USERNAME: &str = "UserName";
+pub const PASSWORD: &str = "Password";
+pub const URL: &str = "URL";
+pub const NOTES: &str = "Notes";
+
+pub struct KdbxField {
+    pub key: String,
+    pub value: String,
+    pub protected: bool,
+}
+
+// Unix seconds, expires is only set when the entry expires
+#[derive(Default, Clone, Copy)]
+pub struct KdbxTimes {
+    pub created: Option<i64>,
+    pub modified: Option<i64>,
+    pub accessed: Option<i64>,
+    pub expires: Option<i64>,
+}
+
+pub struct KdbxAttachment {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+//
+// Entry with the names of the groups it is in ( below the root group ),
+// history holds the previous versions of the entry, oldest first
+//
+#[derive(Default)]
+pub struct KdbxEntry {
+    pub uuid: String,
+    pub group: Vec<String>,
+    pub fields: Vec<KdbxField>,
+    pub tags: Vec<String>,
+    pub times: KdbxTimes,
+    pub attachments: Vec<KdbxAttachment>,
+    pub history: Vec<KdbxEntry>,
+}
+
+impl KdbxEntry {
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.key == key)
+            .map(|field| field.value.as_str())
+    }
+
+    pub fn set_field(&mut self, key: &str, value: &str, protected: bool) {
+        self.fields.push(KdbxField {
+            key: key.to_string(),
+            value: value.to_string(),
+            protected,
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct KdbxDatabase {
+    pub name: String,
+    pub entries: Vec<KdbxEntry>,
+}
+
+//
+// Argon2id parameters of written files
+//
+pub struct KdbxSettings {
+    // Memory in bytes
+    pub memory: u64,
+    pub iterations: u64,
+    pub parallelism: u32,
+}
+
+impl Default for KdbxSettings {
+    fn default() -> Self {
+        KdbxSettings {
+            memory: 64 * 1024 * 1024,
+            iterations: 10,
+            parallelism: 2,
+        }
+    }
+}
+
+//
+// Composite key of a password and / or a key file
+//
+pub struct KdbxKey {
+    components: Vec<[u8; 32]>,
+}
+
+impl KdbxKey {
+    pub fn new(password: &str, key_file: Option<&[u8]>) -> Result<Self, String> {
+        let mut components = Vec::new();
+        // With a key file an empty password means the file is the only component
+        if !password.is_empty() || key_file.is_none() {
+            components.push(Sha256::digest(password.as_bytes()).into());
+        }
+        if let Some(key_file) = key_file {
+            components.push(key_file_key(key_file)?);
+        }
+        Ok(KdbxKey { components })
+    }
+
+    fn composite(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for component in &self.components {
+            hasher.update(component);
+        }
+        hasher.finalize().into()
+    }
+}
+
+//
+// Key of a key file: XML key files ( version 1.0 and 2.0 ), 32 raw bytes,
+// 64 hex digits or the SHA-256 hash of any other file
+//
+fn key_file_key(data: &[u8]) -> Result<[u8; 32], String> {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let text = text.trim_start_matches('\u{feff}').trim();
+        if text.starts_with("<?xml") || text.starts_with("<KeyFile") {
+            return xml_key_file_key(text);
+        }
+        if text.len() == 64 && text.chars().all(|c| c.is_ascii_hexdigit()) {
+            let mut key = [0u8; 32];
+            for (index, byte) in key.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).unwrap_or(0);
+            }
+            return Ok(key);
+        }
+    }
+    if data.len() == 32 {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(data);
+        return Ok(key);
+    }
+    Ok(Sha256::digest(data).into())
+}
+
+fn xml_key_file_key(text: &str) -> Result<[u8; 32], String> {
+    let document = match roxmltree::Document::parse(text) {
+        Ok(document) => document,
+        Err(e) => return Err(format!("Key file is not valid XML: {}", e)),
+    };
+    let root = document.root_element();
+    let version = child(root, "Meta")
+        .and_then(|meta| child(meta, "Version"))
+        .map(text_of)
+        .unwrap_or_default();
+    let data = match child(root, "Key").and_then(|key| child(key, "Data")) {
+        Some(data) => data,
+        None => return Err("Key file has no key data".into()),
+    };
+
+    let key: Vec<u8> = if version.starts_with("2.") {
+        let hex: String = text_of(data).chars().filter(|c| !c.is_whitespace()).collect();
+        let bytes = match parse_hex(&hex) {
+            Some(bytes) => bytes,
+            None => return Err("Key file data is not hexadecimal".into()),
+        };
+        // The hash attribute holds the first 4 bytes of the SHA-256 hash of the key
+        if let Some(hash) = data.attribute("Hash") {
+            if parse_hex(hash).as_deref() != Some(&Sha256::digest(&bytes)[..4]) {
+                return Err("Key file is damaged: the key does not match its hash".into());
+            }
+        }
+        bytes
+    } else {
+        match encode::decode_base64(text_of(data).trim()) {
+            Ok(bytes) => bytes,
+            Err(_) => return Err("Key file data is not base64".into()),
+        }
+    };
+
+    if key.len() != 32 {
+        return Err("Key file key must be 32 bytes long".into());
+    }
+    let mut result = [0u8; 32];
+    result.copy_from_slice(&key);
+    Ok(result)
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+//
+// Little endian reader over the file, running past the end is an error
+//
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, position: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.position < length {
+            return Err("KDBX file is truncated".into());
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+}
+
+//
+// Values of a variant dictionary ( the KDF parameters )
+//
+enum Variant {
+    Integer(u64),
+    Bytes(Vec<u8>),
+    // Booleans and strings, no supported KDF uses them
+    Other,
+}
+
+fn read_variant_dictionary(data: &[u8]) -> Result<HashMap<String, Variant>, String> {
+    let mut reader = ByteReader::new(data);
+    if reader.u16()? >> 8 != VARIANT_VERSION >> 8 {
+        return Err("Unsupported KDF parameter version".into());
+    }
+
+    let mut dictionary = HashMap::new();
+    loop {
+        let value_type = reader.u8()?;
+        if value_type == VARIANT_END {
+            break;
+        }
+        let key_length = reader.u32()? as usize;
+        let key = String::from_utf8_lossy(reader.bytes(key_length)?).into_owned();
+        let value_length = reader.u32()? as usize;
+        let value = reader.bytes(value_length)?;
+
+        let value = match (value_type, value_length) {
+            (VARIANT_UINT32, 4) | (VARIANT_INT32, 4) => {
+                Variant::Integer(u32::from_le_bytes(value.try_into().unwrap()) as u64)
+            }
+            (VARIANT_UINT64, 8) | (VARIANT_INT64, 8) => Variant::Integer(u64::from_le_bytes(value.try_into().unwrap())),
+            (VARIANT_BOOL, 1) | (VARIANT_STRING, _) => Variant::Other,
+            (VARIANT_BYTES, _) => Variant::Bytes(value.to_vec()),
+            _ => return Err(format!("Invalid KDF parameter {}", key)),
+        };
+        dictionary.insert(key, value);
+    }
+    Ok(dictionary)
+}
+
+fn write_variant(output: &mut Vec<u8>, value_type: u8, key: &str, value: &[u8]) {
+    output.push(value_type);
+    output.extend((key.len() as u32).to_le_bytes());
+    output.extend(key.as_bytes());
+    output.extend((value.len() as u32).to_le_bytes());
+    output.extend(value);
+}
+
+fn variant_integer(dictionary: &HashMap<String, Variant>, key: &str) -> Result<u64, String> {
+    match dictionary.get(key) {
+        Some(Variant::Integer(value)) => Ok(*value),
+        _ => Err(format!("KDF parameter {} is missing", key)),
+    }
+}
+
+// KDF parameter that must not be larger than the limit
+fn variant_limited(dictionary: &HashMap<String, Variant>, key: &str, name: &str, maximum: u64) -> Result<u64, String> {
+    let value = variant_integer(dictionary, key)?;
+    if value > maximum {
+        return Err(format!("The {} of the key derivation ( {} ) is more than the {} allowed", name, value, maximum));
+    }
+    Ok(value)
+}
+
+fn variant_bytes<'a>(dictionary: &'a HashMap<String, Variant>, key: &str) -> Result<&'a [u8], String> {
+    match dictionary.get(key) {
+        Some(Variant::Bytes(value)) => Ok(value),
+        _ => Err(format!("KDF parameter {} is missing", key)),
+    }
+}
+
+//
+// Transforms the composite key with the KDF of the file ( AES-KDF, Argon2d or Argon2id )
+//
+fn transform_key(composite: &[u8; 32], kdf_parameters: &HashMap<String, Variant>) -> Result<[u8; 32], String> {
+    let kdf = variant_bytes(kdf_parameters, "$UUID")?;
+
+    if kdf == KDF_AES {
+        let seed = variant_bytes(kdf_parameters, "S")?;
+        let rounds = variant_limited(kdf_parameters, "R", "AES rounds", MAX_AES_ROUNDS)?;
+        if seed.len() != 32 {
+            return Err("AES-KDF seed must be 32 bytes long".into());
+        }
+
+        let cipher = Aes256::new(seed.into());
+        let mut blocks = [*aes::Block::from_slice(&composite[..16]), *aes::Block::from_slice(&composite[16..])];
+        for _ in 0..rounds {
+            cipher.encrypt_blocks(&mut blocks);
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(blocks[0]);
+        hasher.update(blocks[1]);
+        return Ok(hasher.finalize().into());
+    }
+
+    let algorithm = if kdf == KDF_ARGON2D {
+        Algorithm::Argon2d
+    } else if kdf == KDF_ARGON2ID {
+        Algorithm::Argon2id
+    } else {
+        return Err("Unsupported key derivation function".into());
+    };
+
+    let salt = variant_bytes(kdf_parameters, "S")?;
+    let memory = variant_limited(kdf_parameters, "M", "Argon2 memory in bytes", MAX_ARGON2_MEMORY)? / 1024;
+    let iterations = variant_limited(kdf_parameters, "I", "Argon2 iterations", MAX_ARGON2_ITERATIONS)?;
+    let parallelism = variant_limited(kdf_parameters, "P", "Argon2 parallelism", MAX_ARGON2_PARALLELISM)?;
+    let version = match variant_integer(kdf_parameters, "V")? {
+        0x10 => Version::V0x10,
+        0x13 => Version::V0x13,
+        version => return Err(format!("Unsupported Argon2 version {:#x}", version)),
+    };
+    if kdf_parameters.contains_key("A") {
+        return Err("Argon2 associated data is not supported".into());
+    }
+
+    let params = match Params::new(
+        memory as u32,
+        iterations as u32,
+        parallelism as u32,
+        Some(32),
+    ) {
+        Ok(params) => params,
+        Err(e) => return Err(format!("Invalid Argon2 parameters: {}", e)),
+    };
+    let argon2 = match kdf_parameters.get("K") {
+        Some(Variant::Bytes(secret)) => match Argon2::new_with_secret(secret, algorithm, version, params) {
+            Ok(argon2) => argon2,
+            Err(e) => return Err(format!("Invalid Argon2 secret: {}", e)),
+        },
+        _ => Argon2::new(algorithm, version, params),
+    };
+
+    let mut key = [0u8; 32];
+    if let Err(e) = argon2.hash_password_into(composite, salt, &mut key) {
+        return Err(format!("Argon2 key derivation failed: {}", e));
+    }
+    Ok(key)
+}
+
+//
+// HMAC key of a block, the header uses the block index u64::MAX
+//
+fn block_hmac(hmac_key: &[u8; 64], index: u64, parts: &[&[u8]]) -> Result<Vec<u8>, String> {
+    let mut hasher = Sha512::new();
+    hasher.update(index.to_le_bytes());
+    hasher.update(hmac_key);
+    let mut mac = match <Hmac<Sha256> as Mac>::new_from_slice(&hasher.finalize()) {
+        Ok(mac) => mac,
+        Err(e) => return Err(format!("HMAC key is invalid: {}", e)),
+    };
+    for part in parts {
+        mac.update(part);
+    }
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn keys(master_seed: &[u8], transformed_key: &[u8; 32]) -> ([u8; 32], [u8; 64]) {
+    let mut hasher = Sha256::new();
+    hasher.update(master_seed);
+    hasher.update(transformed_key);
+    let encryption_key: [u8; 32] = hasher.finalize().into();
+
+    let mut hasher = Sha512::new();
+    hasher.update(master_seed);
+    hasher.update(transformed_key);
+    hasher.update([1u8]);
+    let hmac_key: [u8; 64] = hasher.finalize().into();
+
+    (encryption_key, hmac_key)
+}
+
+//
+// Cipher of protected values in the XML document
+//
+enum InnerStream {
+    ChaCha20(Box<ChaCha20>),
+    Salsa20(Box<Salsa20>),
+}
+
+impl InnerStream {
+    fn new(id: u32, key: &[u8]) -> Result<Self, String> {
+        match id {
+            STREAM_CHACHA20 => {
+                let hash = Sha512::digest(key);
+                Ok(InnerStream::ChaCha20(Box::new(ChaCha20::new(
+                    hash[..32].into(),
+                    hash[32..44].into(),
+                ))))
+            }
+            STREAM_SALSA20 => {
+                let hash = Sha256::digest(key);
+                Ok(InnerStream::Salsa20(Box::new(Salsa20::new(
+                    hash.as_slice().into(),
+                    (&SALSA20_NONCE).into(),
+                ))))
+            }
+            _ => Err(format!("Unsupported inner random stream {}", id)),
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        match self {
+            InnerStream::ChaCha20(cipher) => cipher.apply_keystream(data),
+            InnerStream::Salsa20(cipher) => cipher.apply_keystream(data),
+        }
+    }
+}
+
+//
+// Decrypts a KDBX 4 file, wrong keys are detected by the header HMAC
+//
+pub fn read(data: &[u8], key: &KdbxKey) -> Result<KdbxDatabase, String> {
+    let mut reader = ByteReader::new(data);
+    if reader.u32()? != SIGNATURE_1 || reader.u32()? != SIGNATURE_2 {
+        return Err("Not a KeePass database".into());
+    }
+    let minor_version = reader.u16()?;
+    let major_version = reader.u16()?;
+    if major_version != MAJOR_VERSION {
+        return Err(format!(
+            "KDBX {}.{} is not supported, save the database as KDBX 4 first",
+            major_version, minor_version
+        ));
+    }
+
+    let mut cipher_id = Vec::new();
+    let mut compressed = false;
+    let mut master_seed = Vec::new();
+    let mut encryption_iv = Vec::new();
+    let mut kdf_parameters = HashMap::new();
+    loop {
+        let field = reader.u8()?;
+        let length = reader.u32()? as usize;
+        let value = reader.bytes(length)?;
+        match field {
+            HEADER_END => break,
+            HEADER_CIPHER_ID => cipher_id = value.to_vec(),
+            HEADER_COMPRESSION => compressed = value.first().copied().unwrap_or(0) != 0,
+            HEADER_MASTER_SEED => master_seed = value.to_vec(),
+            HEADER_ENCRYPTION_IV => encryption_iv = value.to_vec(),
+            HEADER_KDF_PARAMETERS => kdf_parameters = read_variant_dictionary(value)?,
+            _ => (),
+        }
+    }
+    let header = &data[..reader.position];
+    if master_seed.len() != 32 {
+        return Err("KDBX header has no valid master seed".into());
+    }
+
+    if reader.bytes(32)? != Sha256::digest(header).as_slice() {
+        return Err("KDBX header is damaged".into());
+    }
+    let header_hmac = reader.bytes(32)?;
+
+    let transformed_key = transform_key(&key.composite(), &kdf_parameters)?;
+    let (encryption_key, hmac_key) = keys(&master_seed, &transformed_key);
+    if block_hmac(&hmac_key, u64::MAX, &[header])? != header_hmac {
+        return Err("Wrong password or key file".into());
+    }
+
+    // HMAC protected blocks, the last one is empty
+    let mut payload = Vec::new();
+    let mut index: u64 = 0;
+    loop {
+        let hmac = reader.bytes(32)?;
+        let length_bytes = reader.bytes(4)?;
+        let length = u32::from_le_bytes(length_bytes.try_into().unwrap()) as usize;
+        let block = reader.bytes(length)?;
+        if block_hmac(&hmac_key, index, &[&index.to_le_bytes(), length_bytes, block])? != hmac {
+            return Err(format!("KDBX block {} is damaged", index));
+        }
+        if length == 0 {
+            break;
+        }
+        payload.extend_from_slice(block);
+        index += 1;
+    }
+
+    let payload = if cipher_id == CIPHER_CHACHA20 {
+        if encryption_iv.len() != 12 {
+            return Err("ChaCha20 nonce must be 12 bytes long".into());
+        }
+        let mut cipher = ChaCha20::new((&encryption_key).into(), encryption_iv.as_slice().into());
+        cipher.apply_keystream(&mut payload);
+        payload
+    } else if cipher_id == CIPHER_AES256 {
+        if encryption_iv.len() != 16 {
+            return Err("AES initialization vector must be 16 bytes long".into());
+        }
+        match cbc::Decryptor::<Aes256>::new((&encryption_key).into(), encryption_iv.as_slice().into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&payload)
+        {
+            Ok(payload) => payload,
+            Err(_) => return Err("KDBX payload decryption failed".into()),
+        }
+    } else {
+        return Err("Unsupported cipher, only AES-256 and ChaCha20 are supported".into());
+    };
+
+    let payload = if compressed {
+        let mut decompressed = Vec::new();
+        if let Err(e) = GzDecoder::new(payload.as_slice()).read_to_end(&mut decompressed) {
+            return Err(format!("KDBX payload decompression failed: {}", e));
+        }
+        decompressed
+    } else {
+        payload
+    };
+
+    // Inner header
+    let mut reader = ByteReader::new(&payload);
+    let mut stream_id = 0;
+    let mut stream_key = Vec::new();
+    let mut binaries: Vec<Vec<u8>> = Vec::new();
+    loop {
+        let field = reader.u8()?;
+        let length = reader.u32()? as usize;
+        let value = reader.bytes(length)?;
+        match field {
+            INNER_HEADER_END => break,
+            INNER_RANDOM_STREAM_ID if length == 4 => stream_id = u32::from_le_bytes(value.try_into().unwrap()),
+            INNER_RANDOM_STREAM_KEY => stream_key = value.to_vec(),
+            // The first byte holds flags ( memory protection )
+            INNER_BINARY if length > 0 => binaries.push(value[1..].to_vec()),
+            INNER_BINARY => binaries.push(Vec::new()),
+            _ => (),
+        }
+    }
+    if reader.is_empty() {
+        return Err("KDBX file has no XML document".into());
+    }
+    let xml = match std::str::from_utf8(&payload[reader.position..]) {
+        Ok(xml) => xml,
+        Err(_) => return Err("KDBX XML document is not UTF-8".into()),
+    };
+
+    let mut stream = InnerStream::new(stream_id, &stream_key)?;
+    read_xml(xml, &mut stream, &binaries)
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn children<'a, 'input: 'a>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.has_tag_name(name))
+}
+
+fn text_of(node: roxmltree::Node) -> String {
+    node.text().unwrap_or("").to_string()
+}
+
+fn read_xml(xml: &str, stream: &mut InnerStream, binaries: &[Vec<u8>]) -> Result<KdbxDatabase, String> {
+    let document = match roxmltree::Document::parse(xml) {
+        Ok(document) => document,
+        Err(e) => return Err(format!("KDBX XML document is invalid: {}", e)),
+    };
+
+    // Protected values have to be decrypted in document order, before anything is skipped
+    let mut protected = HashMap::new();
+    for node in document.descendants().filter(|node| node.has_tag_name("Value")) {
+        if node.attribute("Protected").is_some_and(|value| value.eq_ignore_ascii_case("true")) {
+            let mut value = match encode::decode_base64(node.text().unwrap_or("").trim()) {
+                Ok(value) => value,
+                Err(_) => return Err("KDBX protected value is not base64".into()),
+            };
+            stream.apply(&mut value);
+            protected.insert(node.id(), String::from_utf8_lossy(&value).into_owned());
+        }
+    }
+
+    let root = document.root_element();
+    let meta = child(root, "Meta");
+    let name = meta
+        .and_then(|meta| child(meta, "DatabaseName"))
+        .map(text_of)
+        .unwrap_or_default();
+    let recycle_bin = match meta {
+        Some(meta) if child(meta, "RecycleBinEnabled").map(text_of).as_deref() != Some("False") => {
+            child(meta, "RecycleBinUUID").map(text_of)
+        }
+        _ => None,
+    };
+
+    let mut database = KdbxDatabase {
+        name,
+        entries: Vec::new(),
+    };
+    let top_group = child(root, "Root").and_then(|root| child(root, "Group"));
+    if let Some(top_group) = top_group {
+        read_group(top_group, &[], true, recycle_bin.as_deref(), &protected, binaries, &mut database.entries)?;
+    }
+    Ok(database)
+}
+
+fn read_group(
+    group: roxmltree::Node,
+    path: &[String],
+    is_root: bool,
+    recycle_bin: Option<&str>,
+    protected: &HashMap<roxmltree::NodeId, String>,
+    binaries: &[Vec<u8>],
+    entries: &mut Vec<KdbxEntry>,
+) -> Result<(), String> {
+    // Deleted entries in the recycle bin are not imported
+    if !is_root && recycle_bin.is_some() && child(group, "UUID").map(text_of).as_deref() == recycle_bin {
+        return Ok(());
+    }
+
+    let mut path = path.to_vec();
+    if !is_root {
+        path.push(child(group, "Name").map(text_of).unwrap_or_default());
+    }
+
+    for entry in children(group, "Entry") {
+        entries.push(read_entry(entry, &path, protected, binaries)?);
+    }
+    for subgroup in children(group, "Group") {
+        read_group(subgroup, &path, false, recycle_bin, protected, binaries, entries)?;
+    }
+    Ok(())
+}
+
+fn read_entry(
+    node: roxmltree::Node,
+    path: &[String],
+    protected: &HashMap<roxmltree::NodeId, String>,
+    binaries: &[Vec<u8>],
+) -> Result<KdbxEntry, String> {
+    let mut entry = KdbxEntry {
+        uuid: child(node, "UUID").map(|uuid| parse_uuid(&text_of(uuid))).unwrap_or_default(),
+        group: path.to_vec(),
+        ..Default::default()
+    };
+
+    if let Some(tags) = child(node, "Tags") {
+        entry.tags = text_of(tags)
+            .split([';', ','])
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+    }
+
+    if let Some(times) = child(node, "Times") {
+        let time = |name: &str| child(times, name).and_then(|time| parse_time(&text_of(time)));
+        entry.times = KdbxTimes {
+            created: time("CreationTime"),
+            modified: time("LastModificationTime"),
+            accessed: time("LastAccessTime"),
+            expires: match child(times, "Expires").map(text_of) {
+                Some(expires) if expires.eq_ignore_ascii_case("true") => time("ExpiryTime"),
+                _ => None,
+            },
+        };
+    }
+
+    for string in children(node, "String") {
+        let key = child(string, "Key").map(text_of).unwrap_or_default();
+        let (value, is_protected) = match child(string, "Value") {
+            Some(value) => match protected.get(&value.id()) {
+                Some(decrypted) => (decrypted.clone(), true),
+                None => (text_of(value), false),
+            },
+            None => (String::new(), false),
+        };
+        entry.set_field(&key, &value, is_protected);
+    }
+
+    for binary in children(node, "Binary") {
+        let name = child(binary, "Key").map(text_of).unwrap_or_default();
+        let reference = child(binary, "Value").and_then(|value| value.attribute("Ref"));
+        let data = match reference.and_then(|reference| reference.parse::<usize>().ok()) {
+            Some(index) if index < binaries.len() => binaries[index].clone(),
+            _ => return Err(format!("Attachment {} refers to a missing binary", name)),
+        };
+        entry.attachments.push(KdbxAttachment { name, data });
+    }
+
+    if let Some(history) = child(node, "History") {
+        for version in children(history, "Entry") {
+            entry.history.push(read_entry(version, path, protected, binaries)?);
+        }
+    }
+
+    Ok(entry)
+}
+
+fn parse_uuid(text: &str) -> String {
+    match encode::decode_base64(text.trim()) {
+        Ok(bytes) if bytes.len() == 16 => Uuid::from_slice(&bytes).map(|uuid| uuid.to_string()).unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+//
+// KDBX 4 stores times as base64 seconds since 0001-01-01, older writers use ISO 8601
+//
+fn parse_time(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if text.contains('-') && text.contains(':') {
+        return chrono::DateTime::parse_from_rfc3339(text).ok().map(|time| time.timestamp());
+    }
+    match encode::decode_base64(text) {
+        Ok(bytes) if bytes.len() == 8 => Some(i64::from_le_bytes(bytes.try_into().unwrap()) - EPOCH_OFFSET),
+        _ => None,
+    }
+}
+
+fn format_time(time: i64) -> String {
+    encode::encode_base64((time + EPOCH_OFFSET).to_le_bytes().to_vec())
+}
+
+fn format_uuid(uuid: &str) -> String {
+    let uuid = Uuid::parse_str(uuid).unwrap_or_else(|_| Uuid::new_v4());
+    encode::encode_base64(uuid.as_bytes().to_vec())
+}
+
+//
+// Writes the database as a KDBX 4.0 file with Argon2id and ChaCha20
+//
+pub fn write(database: &KdbxDatabase, key: &KdbxKey, settings: &KdbxSettings) -> Result<Vec<u8>, String> {
+    let mut master_seed = [0u8; 32];
+    let mut encryption_iv = [0u8; 12];
+    let mut kdf_salt = [0u8; 32];
+    let mut stream_key = [0u8; 64];
+    OsRng.fill_bytes(&mut master_seed);
+    OsRng.fill_bytes(&mut encryption_iv);
+    OsRng.fill_bytes(&mut kdf_salt);
+    OsRng.fill_bytes(&mut stream_key);
+
+    let mut kdf_parameters = VARIANT_VERSION.to_le_bytes().to_vec();
+    write_variant(&mut kdf_parameters, VARIANT_BYTES, "$UUID", &KDF_ARGON2ID);
+    write_variant(&mut kdf_parameters, VARIANT_BYTES, "S", &kdf_salt);
+    write_variant(&mut kdf_parameters, VARIANT_UINT32, "P", &settings.parallelism.to_le_bytes());
+    write_variant(&mut kdf_parameters, VARIANT_UINT64, "M", &settings.memory.to_le_bytes());
+    write_variant(&mut kdf_parameters, VARIANT_UINT64, "I", &settings.iterations.to_le_bytes());
+    write_variant(&mut kdf_parameters, VARIANT_UINT32, "V", &0x13u32.to_le_bytes());
+    kdf_parameters.push(VARIANT_END);
+
+    let mut header = Vec::new();
+    header.extend(SIGNATURE_1.to_le_bytes());
+    header.extend(SIGNATURE_2.to_le_bytes());
+    header.extend(0u16.to_le_bytes());
+    header.extend(MAJOR_VERSION.to_le_bytes());
+    for (field, value) in [
+        (HEADER_CIPHER_ID, CIPHER_CHACHA20.as_slice()),
+        (HEADER_COMPRESSION, 1u32.to_le_bytes().as_slice()),
+        (HEADER_MASTER_SEED, master_seed.as_slice()),
+        (HEADER_ENCRYPTION_IV, encryption_iv.as_slice()),
+        (HEADER_KDF_PARAMETERS, kdf_parameters.as_slice()),
+        (HEADER_END, b"\r\n\r\n".as_slice()),
+    ] {
+        header.push(field);
+        header.extend((value.len() as u32).to_le_bytes());
+        header.extend(value);
+    }
+
+    let kdf_parameters = read_variant_dictionary(&kdf_parameters)?;
+    let transformed_key = transform_key(&key.composite(), &kdf_parameters)?;
+    let (encryption_key, hmac_key) = keys(&master_seed, &transformed_key);
+
+    // Inner header with every attachment stored once
+    let mut inner = Vec::new();
+    let mut inner_field = |field: u8, value: &[u8]| {
+        inner.push(field);
+        inner.extend((value.len() as u32).to_le_bytes());
+        inner.extend(value);
+    };
+    inner_field(INNER_RANDOM_STREAM_ID, &STREAM_CHACHA20.to_le_bytes());
+    inner_field(INNER_RANDOM_STREAM_KEY, &stream_key);
+    let mut binary_index: HashMap<&[u8], usize> = HashMap::new();
+    for entry in &database.entries {
+        for attachment in entry.attachments.iter().chain(entry.history.iter().flat_map(|version| &version.attachments)) {
+            if !binary_index.contains_key(attachment.data.as_slice()) {
+                binary_index.insert(&attachment.data, binary_index.len());
+                let mut value = vec![0u8];
+                value.extend(&attachment.data);
+                inner_field(INNER_BINARY, &value);
+            }
+        }
+    }
+    inner_field(INNER_HEADER_END, &[]);
+
+    let mut stream = InnerStream::new(STREAM_CHACHA20, &stream_key)?;
+    inner.extend(write_xml(database, &mut stream, &binary_index).into_bytes());
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder.write_all(&inner).and_then(|_| encoder.finish());
+    let mut payload = match compressed {
+        Ok(payload) => payload,
+        Err(e) => return Err(format!("KDBX payload compression failed: {}", e)),
+    };
+    ChaCha20::new((&encryption_key).into(), (&encryption_iv).into()).apply_keystream(&mut payload);
+
+    let mut output = header.clone();
+    output.extend(Sha256::digest(&header));
+    output.extend(block_hmac(&hmac_key, u64::MAX, &[&header])?);
+
+    let mut blocks: Vec<&[u8]> = payload.chunks(BLOCK_SIZE).collect();
+    blocks.push(&[]);
+    for (index, block) in blocks.into_iter().enumerate() {
+        let length = (block.len() as u32).to_le_bytes();
+        let index = index as u64;
+        output.extend(block_hmac(&hmac_key, index, &[&index.to_le_bytes(), &length, block])?);
+        output.extend(length);
+        output.extend(block);
+    }
+
+    Ok(output)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than tab and line breaks are not allowed in XML
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+//
+// Groups rebuilt from the group paths of the entries
+//
+#[derive(Default)]
+struct GroupTree<'a> {
+    entries: Vec<&'a KdbxEntry>,
+    groups: BTreeMap<String, GroupTree<'a>>,
+}
+
+fn write_xml(database: &KdbxDatabase, stream: &mut InnerStream, binaries: &HashMap<&[u8], usize>) -> String {
+    let mut tree = GroupTree::default();
+    for entry in &database.entries {
+        let mut group = &mut tree;
+        for name in &entry.group {
+            group = group.groups.entry(name.clone()).or_default();
+        }
+        group.entries.push(entry);
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let name = if database.name.is_empty() { "KeeManager" } else { &database.name };
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n<KeePassFile>\n");
+    xml.push_str("<Meta>\n<Generator>KeeManager</Generator>\n");
+    xml.push_str(&format!("<DatabaseName>{}</DatabaseName>\n", escape(name)));
+    xml.push_str(&format!("<DatabaseNameChanged>{}</DatabaseNameChanged>\n", format_time(now)));
+    xml.push_str("<MemoryProtection>\n<ProtectTitle>False</ProtectTitle>\n<ProtectUserName>False</ProtectUserName>\n");
+    xml.push_str("<ProtectPassword>True</ProtectPassword>\n<ProtectURL>False</ProtectURL>\n<ProtectNotes>False</ProtectNotes>\n</MemoryProtection>\n");
+    xml.push_str("<RecycleBinEnabled>False</RecycleBinEnabled>\n</Meta>\n<Root>\n");
+    write_group(&mut xml, name, &tree, now, stream, binaries);
+    xml.push_str("<DeletedObjects />\n</Root>\n</KeePassFile>\n");
+    xml
+}
+
+fn write_group(
+    xml: &mut String,
+    name: &str,
+    group: &GroupTree,
+    now: i64,
+    stream: &mut InnerStream,
+    binaries: &HashMap<&[u8], usize>,
+) {
+    xml.push_str("<Group>\n");
+    xml.push_str(&format!("<UUID>{}</UUID>\n", format_uuid("")));
+    xml.push_str(&format!("<Name>{}</Name>\n", escape(name)));
+    write_times(
+        xml,
+        &KdbxTimes {
+            created: Some(now),
+            modified: Some(now),
+            accessed: Some(now),
+            expires: None,
+        },
+    );
+    xml.push_str("<IsExpanded>True</IsExpanded>\n");
+    for entry in &group.entries {
+        write_entry(xml, entry, stream, binaries, false);
+    }
+    for (name, subgroup) in &group.groups {
+        write_group(xml, name, subgroup, now, stream, binaries);
+    }
+    xml.push_str("</Group>\n");
+}
+
+fn write_times(xml: &mut String, times: &KdbxTimes) {
+    let now = chrono::Utc::now().timestamp();
+    let modified = times.modified.unwrap_or(now);
+    xml.push_str("<Times>\n");
+    xml.push_str(&format!("<CreationTime>{}</CreationTime>\n", format_time(times.created.unwrap_or(modified))));
+    xml.push_str(&format!("<LastModificationTime>{}</LastModificationTime>\n", format_time(modified)));
+    xml.push_str(&format!("<LastAccessTime>{}</LastAccessTime>\n", format_time(times.accessed.unwrap_or(modified))));
+    xml.push_str(&format!("<ExpiryTime>{}</ExpiryTime>\n", format_time(times.expires.unwrap_or(modified))));
+    xml.push_str(&format!("<Expires>{}</Expires>\n", if times.expires.is_some() { "True" } else { "False" }));
+    xml.push_str("<UsageCount>0</UsageCount>\n");
+    xml.push_str(&format!("<LocationChanged>{}</LocationChanged>\n", format_time(modified)));
+    xml.push_str("</Times>\n");
+}
+
+fn write_entry(
+    xml: &mut String,
+    entry: &KdbxEntry,
+    stream: &mut InnerStream,
+    binaries: &HashMap<&[u8], usize>,
+    is_history: bool,
+) {
+    xml.push_str("<Entry>\n");
+    xml.push_str(&format!("<UUID>{}</UUID>\n", format_uuid(&entry.uuid)));
+    xml.push_str("<IconID>0</IconID>\n");
+    xml.push_str(&format!("<Tags>{}</Tags>\n", escape(&entry.tags.join(";"))));
+    write_times(xml, &entry.times);
+
+    for field in &entry.fields {
+        xml.push_str(&format!("<String>\n<Key>{}</Key>\n", escape(&field.key)));
+        if field.protected {
+            let mut value = field.value.as_bytes().to_vec();
+            stream.apply(&mut value);
+            xml.push_str(&format!("<Value Protected=\"True\">{}</Value>\n", encode::encode_base64(value)));
+        } else {
+            xml.push_str(&format!("<Value>{}</Value>\n", escape(&field.value)));
+        }
+        xml.push_str("</String>\n");
+    }
+
+    for attachment in &entry.attachments {
+        if let Some(index) = binaries.get(attachment.data.as_slice()) {
+            xml.push_str(&format!(
+                "<Binary>\n<Key>{}</Key>\n<Value Ref=\"{}\" />\n</Binary>\n",
+                escape(&attachment.name),
+                index
+            ));
+        }
+    }
+
+    if !is_history {
+        xml.push_str("<History>\n");
+        for version in &entry.history {
+            write_entry(xml, version, stream, binaries, true);
+        }
+        xml.push_str("</History>\n");
+    }
+    xml.push_str("</Entry>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(kdf: [u8; 16], integers: &[(&str, u64)]) -> HashMap<String, Variant> {
+        let mut parameters = HashMap::new();
+        parameters.insert("$UUID".to_string(), Variant::Bytes(kdf.to_vec()));
+        parameters.insert("S".to_string(), Variant::Bytes(vec![0; 32]));
+        for (key, value) in integers {
+            parameters.insert(key.to_string(), Variant::Integer(*value));
+        }
+        parameters
+    }
+
+    #[test]
+    fn kdf_parameters_out_of_range_are_refused() {
+        let argon2 = |memory: u64, iterations: u64, parallelism: u64| {
+            parameters(KDF_ARGON2ID, &[("M", memory), ("I", iterations), ("P", parallelism), ("V", 0x13)])
+        };
+        for (kdf_parameters, name) in [
+            (argon2(MAX_ARGON2_MEMORY + 1, 2, 1), "Argon2 memory"),
+            (argon2(1024 * 1024, MAX_ARGON2_ITERATIONS + 1, 1), "Argon2 iterations"),
+            (argon2(1024 * 1024, 2, MAX_ARGON2_PARALLELISM + 1), "Argon2 parallelism"),
+            (argon2(u64::MAX, 2, 1), "Argon2 memory"),
+            (parameters(KDF_AES, &[("R", MAX_AES_ROUNDS + 1)]), "AES rounds"),
+        ] {
+            let error = transform_key(&[0; 32], &kdf_parameters).unwrap_err();
+            assert!(error.contains(name) && error.contains("allowed"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn kdf_parameters_in_range_are_used() {
+        let argon2 = parameters(KDF_ARGON2ID, &[("M", 1024 * 1024), ("I", 2), ("P", 2), ("V", 0x13)]);
+        assert!(transform_key(&[0; 32], &argon2).is_ok());
+        let aes = parameters(KDF_AES, &[("R", 1000)]);
+        assert!(transform_key(&[0; 32], &aes).is_ok());
+    }
+}
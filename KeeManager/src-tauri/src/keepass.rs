@@ -0,0 +1,527 @@
+use serde_json::{Map, Value};
+
+use crate::entry::{format_timestamp, parse_timestamp, with_entry_type, Entry, EntryKind, FieldDescriptor, FieldType};
+use crate::import::{ImportAttachment, ImportItem};
+use crate::kdbx::{self, KdbxAttachment, KdbxDatabase, KdbxEntry, KdbxKey, KdbxSettings, KdbxTimes};
+use crate::{reference, totp};
+
+// Custom field holding the KeeManager entry type of entries exported by KeeManager
+pub const TYPE_FIELD: &str = "KeeManager Type";
+
+// One time password fields written by KeePass 2.47+ and the KeeTrayTOTP plugin,
+// KeePassXC stores an otpauth:// URI in "otp" like KeeManager does
+const TIME_OTP_SECRET: &str = "TimeOtp-Secret-Base32";
+const TIME_OTP_PERIOD: &str = "TimeOtp-Period";
+const TIME_OTP_LENGTH: &str = "TimeOtp-Length";
+const TIME_OTP_ALGORITHM: &str = "TimeOtp-Algorithm";
+const TRAY_TOTP_SEED: &str = "TOTP Seed";
+const TRAY_TOTP_SETTINGS: &str = "TOTP Settings";
+
+// Fields written as protected values, KeePass keeps them encrypted in memory
+const PROTECTED_FIELDS: [&str; 11] = [
+    "password",
+    "otp",
+    "card_number",
+    "cvv",
+    "identification_number",
+    "private_key",
+    "passphrase",
+    "iban",
+    "api_key",
+    "secret",
+    "license_key",
+];
+
+//
+// Reads a KDBX file and maps its entries to import items,
+// the warnings list what could not be mapped
+//
+pub fn read_file(path: &str, password: &str, key_file: Option<&str>) -> Result<(Vec<ImportItem>, Vec<String>), String> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("Reading {} failed: {}", path, e)),
+    };
+    let key_file = match key_file.filter(|key_file| !key_file.trim().is_empty()) {
+        Some(key_file) => match std::fs::read(key_file) {
+            Ok(key_file) => Some(key_file),
+            Err(e) => return Err(format!("Reading key file {} failed: {}", key_file, e)),
+        },
+        None => None,
+    };
+
+    let key = KdbxKey::new(password, key_file.as_deref())?;
+    let database = kdbx::read(&data, &key)?;
+    Ok(import_items(&database))
+}
+
+//
+// Writes the items to a KDBX file protected by the password and / or key file
+//
+pub fn write_file(path: &str, items: &[ImportItem], password: &str, key_file: Option<&str>) -> Result<(), String> {
+    let key_file = match key_file.filter(|key_file| !key_file.trim().is_empty()) {
+        Some(key_file) => match std::fs::read(key_file) {
+            Ok(key_file) => Some(key_file),
+            Err(e) => return Err(format!("Reading key file {} failed: {}", key_file, e)),
+        },
+        None => None,
+    };
+    if password.is_empty() && key_file.is_none() {
+        return Err("The export needs a password or a key file".into());
+    }
+
+    let key = KdbxKey::new(password, key_file.as_deref())?;
+    let data = kdbx::write(&export_database(items), &key, &KdbxSettings::default())?;
+    match std::fs::write(path, data) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("Writing {} failed: {}", path, e)),
+    }
+}
+
+pub fn import_items(database: &KdbxDatabase) -> (Vec<ImportItem>, Vec<String>) {
+    let mut warnings = Vec::new();
+    let items = database
+        .entries
+        .iter()
+        .map(|entry| import_item(entry, &mut warnings))
+        .collect();
+    (items, warnings)
+}
+
+//
+// KeePass name of an entry field, the standard KeePass fields are used where they fit
+// and the other fields get their name in title case ( "card_number" -> "Card Number" )
+//
+pub fn kdbx_key(kind: EntryKind, field: &str) -> String {
+    match (kind, field) {
+        (_, "title") => kdbx::TITLE.to_string(),
+        (EntryKind::Login, "username") => kdbx::USERNAME.to_string(),
+        (EntryKind::Login, "password") | (EntryKind::Wifi, "password") => kdbx::PASSWORD.to_string(),
+        (EntryKind::Login, "url") => kdbx::URL.to_string(),
+        (EntryKind::Login, "otp") => "otp".to_string(),
+        (EntryKind::Note, "content") | (_, "notes") => kdbx::NOTES.to_string(),
+        _ => field
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" "),
+    }
+}
+
+//
+// Entries exported by KeeManager carry their type, other entries are logins
+// unless they only hold a title and notes
+//
+fn entry_kind(entry: &KdbxEntry, warnings: &mut Vec<String>) -> EntryKind {
+    if let Some(kind) = entry.field(TYPE_FIELD) {
+        match serde_json::from_value(Value::String(kind.trim().to_string())) {
+            Ok(kind) => return kind,
+            Err(_) => warnings.push(format!("{}: unknown entry type {}, imported as login", entry_title(entry), kind)),
+        }
+    }
+
+    let only_notes = entry
+        .fields
+        .iter()
+        .all(|field| field.value.is_empty() || field.key == kdbx::TITLE || field.key == kdbx::NOTES);
+    if only_notes && entry.field(kdbx::NOTES).is_some_and(|notes| !notes.is_empty()) {
+        return EntryKind::Note;
+    }
+    EntryKind::Login
+}
+
+fn entry_title(entry: &KdbxEntry) -> String {
+    match entry.field(kdbx::TITLE) {
+        Some(title) if !title.is_empty() => title.to_string(),
+        _ => format!("Entry {}", entry.uuid),
+    }
+}
+
+fn field_value(field: &FieldDescriptor, value: &str) -> Value {
+    match field.field_type {
+        FieldType::Text => Value::String(value.to_string()),
+        FieldType::Integer => Value::from(value.trim().parse::<i64>().unwrap_or(0)),
+        FieldType::Json => serde_json::from_str(value).unwrap_or(Value::Object(Map::new())),
+    }
+}
+
+fn import_item(entry: &KdbxEntry, warnings: &mut Vec<String>) -> ImportItem {
+    let kind = entry_kind(entry, warnings);
+    let title = entry_title(entry);
+    let mut item = ImportItem::new(kind);
+    let mut used = vec![TYPE_FIELD.to_string()];
+
+    item.set("id", entry.uuid.clone());
+    let fields = with_entry_type!(kind, T => T::FIELDS);
+    for field in fields {
+        let key = kdbx_key(kind, field.name);
+        item.set(field.name, field_value(field, entry.field(&key).unwrap_or("")));
+        used.push(key);
+    }
+    item.set("title", title.clone());
+
+    if kind == EntryKind::Login && entry.field("otp").unwrap_or("").is_empty() {
+        if let Some(otp) = otp_uri(entry, &title) {
+            match totp::parse(&otp) {
+                Ok(_) => {
+                    item.set("otp", otp);
+                    used.extend(
+                        [TIME_OTP_SECRET, TIME_OTP_PERIOD, TIME_OTP_LENGTH, TIME_OTP_ALGORITHM, TRAY_TOTP_SEED, TRAY_TOTP_SETTINGS]
+                            .map(String::from),
+                    );
+                }
+                Err(e) => warnings.push(format!("{}: one time password not imported: {}", title, e)),
+            }
+        }
+    }
+
+    // Fields KeeManager has no place for are kept in the notes
+    let extra: Vec<String> = entry
+        .fields
+        .iter()
+        .filter(|field| !field.value.is_empty() && !used.contains(&field.key))
+        .map(|field| format!("{}: {}", field.key, field.value))
+        .collect();
+    if !extra.is_empty() {
+        match fields.iter().find(|field| kdbx_key(kind, field.name) == kdbx::NOTES) {
+            Some(notes_field) => {
+                let notes = item.entry.get(notes_field.name).and_then(Value::as_str).unwrap_or("").to_string();
+                let notes = if notes.is_empty() {
+                    extra.join("\n")
+                } else {
+                    format!("{}\n\n{}", notes, extra.join("\n"))
+                };
+                item.set(notes_field.name, notes);
+            }
+            None => warnings.push(format!("{}: custom fields not imported: {}", title, extra.join(", "))),
+        }
+    }
+
+    item.set("tags", entry.tags.clone());
+    item.group = entry.group.join("/");
+    item.created = entry.times.created;
+    item.modified = entry.times.modified;
+    item.accessed = entry.times.accessed;
+    if let Some(expires) = entry.times.expires {
+        item.set("expires", format_timestamp(expires));
+    }
+
+    item.attachments = entry
+        .attachments
+        .iter()
+        .map(|attachment| ImportAttachment {
+            name: attachment.name.clone(),
+            data: attachment.data.clone(),
+        })
+        .collect();
+
+    if kind == EntryKind::Login {
+        item.password_history = password_history(entry);
+    } else if !entry.history.is_empty() {
+        warnings.push(format!("{}: history is only imported for logins", title));
+    }
+
+    item
+}
+
+//
+// otpauth:// URI from the KeePass or KeeTrayTOTP fields of an entry
+//
+fn otp_uri(entry: &KdbxEntry, title: &str) -> Option<String> {
+    let (secret, period, digits, algorithm) = if let Some(secret) = entry.field(TIME_OTP_SECRET) {
+        let algorithm = match entry.field(TIME_OTP_ALGORITHM).unwrap_or("") {
+            "HMAC-SHA-256" => "SHA256",
+            "HMAC-SHA-512" => "SHA512",
+            _ => "SHA1",
+        };
+        (
+            secret,
+            entry.field(TIME_OTP_PERIOD).unwrap_or("30"),
+            entry.field(TIME_OTP_LENGTH).unwrap_or("6"),
+            algorithm,
+        )
+    } else if let Some(seed) = entry.field(TRAY_TOTP_SEED) {
+        // "30;6" or "30;S" for Steam codes, which are not supported
+        let mut settings = entry.field(TRAY_TOTP_SETTINGS).unwrap_or("30;6").split(';');
+        let period = settings.next().unwrap_or("30");
+        let digits = settings.next().unwrap_or("6");
+        (seed, period, digits, "SHA1")
+    } else {
+        return None;
+    };
+
+    if secret.trim().is_empty() {
+        return None;
+    }
+    Some(format!(
+        "otpauth://totp/{}?secret={}&period={}&digits={}&algorithm={}",
+        percent_encode(title),
+        secret.trim().replace(' ', ""),
+        period.trim(),
+        digits.trim(),
+        algorithm
+    ))
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+//
+// Passwords the history versions had before they were changed, with the time of the change
+//
+fn password_history(entry: &KdbxEntry) -> Vec<(String, i64)> {
+    let mut history = Vec::new();
+    for (index, version) in entry.history.iter().enumerate() {
+        let next = entry.history.get(index + 1).unwrap_or(entry);
+        let password = version.field(kdbx::PASSWORD).unwrap_or("");
+        if password.is_empty() || Some(password) == next.field(kdbx::PASSWORD) {
+            continue;
+        }
+        let replaced = next.times.modified.or(version.times.modified).unwrap_or(0);
+        history.push((password.to_string(), replaced));
+    }
+    history
+}
+
+//
+// KeePass database with one entry per item, every entry is in the root group
+// and entries other than logins carry their type in a custom field
+//
+pub fn export_database(items: &[ImportItem]) -> KdbxDatabase {
+    KdbxDatabase {
+        name: "KeeManager".into(),
+        entries: items.iter().map(export_entry).collect(),
+    }
+}
+
+fn export_entry(item: &ImportItem) -> KdbxEntry {
+    let value_of = |field: &str| match item.entry.get(field) {
+        Some(Value::String(value)) => value.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    };
+
+    let mut entry = KdbxEntry {
+        uuid: value_of("id"),
+        tags: item.tags(),
+        times: KdbxTimes {
+            created: item.created,
+            modified: item.modified,
+            accessed: item.accessed,
+            expires: parse_timestamp(&value_of("expires")).unwrap_or(None),
+        },
+        ..Default::default()
+    };
+
+    if item.kind != EntryKind::Login {
+        entry.set_field(TYPE_FIELD, item.kind.as_str(), false);
+    }
+    for field in with_entry_type!(item.kind, T => T::FIELDS) {
+        let mut value = value_of(field.name);
+        if item.kind == EntryKind::Login {
+            value = keepass_references(&value);
+        }
+        entry.set_field(&kdbx_key(item.kind, field.name), &value, PROTECTED_FIELDS.contains(&field.name));
+    }
+
+    entry.attachments = item
+        .attachments
+        .iter()
+        .map(|attachment| KdbxAttachment {
+            name: attachment.name.clone(),
+            data: attachment.data.clone(),
+        })
+        .collect();
+
+    // Each history version holds a previous password and was current until it was replaced
+    let mut current_since = item.created;
+    for (password, replaced) in &item.password_history {
+        let mut version = KdbxEntry {
+            uuid: entry.uuid.clone(),
+            times: KdbxTimes {
+                created: item.created,
+                modified: current_since,
+                accessed: current_since,
+                expires: None,
+            },
+            ..Default::default()
+        };
+        version.set_field(kdbx::TITLE, entry.field(kdbx::TITLE).unwrap_or(""), false);
+        version.set_field(kdbx::PASSWORD, password, true);
+        entry.history.push(version);
+        current_since = Some(*replaced);
+    }
+
+    entry
+}
+
+//
+// KeePass looks referenced entries up by their UUID in hex without dashes
+//
+fn keepass_references(value: &str) -> String {
+    let references = match reference::find_references(value) {
+        Ok(references) => references,
+        Err(_) => return value.to_string(),
+    };
+
+    let mut value = value.to_string();
+    for found in references.iter().rev() {
+        let code = reference::REFERENCE_FIELDS
+            .iter()
+            .find(|(_, field)| *field == found.field)
+            .map(|(code, _)| *code)
+            .unwrap_or('P');
+        let uuid = found.entry_id.replace('-', "").to_ascii_uppercase();
+        value.replace_range(found.start..found.end, &format!("{{REF:{}@I:{}}}", code, uuid));
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(kind: EntryKind, entry: Value) -> ImportItem {
+        let mut item = ImportItem::new(kind);
+        item.entry = entry.as_object().unwrap().clone();
+        item.created = Some(1_600_000_000);
+        item.modified = Some(1_700_000_000);
+        item.accessed = Some(1_700_000_500);
+        item
+    }
+
+    fn vault() -> Vec<ImportItem> {
+        let mut login = item(
+            EntryKind::Login,
+            json!({
+                "id": "4f1e2d3c-0000-4000-8000-000000000001",
+                "title": "Mail & <Inbox>",
+                "username": "alice",
+                "password": "s3cr3t\"pass",
+                "url": "https://mail.example.com",
+                "notes": "first line\nsecond line",
+                "otp": "otpauth://totp/Mail?secret=JBSWY3DPEHPK3PXP",
+                "tags": ["work", "mail"],
+                "expires": "2030-01-01T00:00:00Z",
+            }),
+        );
+        login.attachments.push(ImportAttachment {
+            name: "recovery.txt".into(),
+            data: b"recovery codes".to_vec(),
+        });
+        login.password_history = vec![("first".into(), 1_650_000_000), ("second".into(), 1_700_000_000)];
+
+        vec![
+            login,
+            item(
+                EntryKind::CreditCard,
+                json!({
+                    "id": "4f1e2d3c-0000-4000-8000-000000000002",
+                    "title": "Visa",
+                    "card_number": "4111111111111111",
+                    "expiry_date": "12/30",
+                    "cardholder_name": "Alice",
+                    "cvv": "123",
+                }),
+            ),
+            item(
+                EntryKind::Note,
+                json!({
+                    "id": "4f1e2d3c-0000-4000-8000-000000000003",
+                    "title": "Wifi at home",
+                    "content": "ask the neighbours",
+                }),
+            ),
+            item(
+                EntryKind::Identity,
+                json!({
+                    "id": "4f1e2d3c-0000-4000-8000-000000000004",
+                    "title": "Passport",
+                    "full_name": "Alice Example",
+                    "date_of_birth": "1990-05-01",
+                    "nationality": "NL",
+                    "identification_number": "X1234567",
+                    "issue_date": "2020-01-01",
+                    "expiry_date": "2030-01-01",
+                    "issuer": "Gemeente",
+                    "notes": "",
+                }),
+            ),
+        ]
+    }
+
+    fn round_trip(items: &[ImportItem]) -> Vec<ImportItem> {
+        let settings = KdbxSettings {
+            memory: 1024 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        };
+        let key = KdbxKey::new("export password", None).unwrap();
+        let data = kdbx::write(&export_database(items), &key, &settings).unwrap();
+
+        assert!(kdbx::read(&data, &KdbxKey::new("wrong password", None).unwrap()).is_err());
+        let (imported, warnings) = import_items(&kdbx::read(&data, &key).unwrap());
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        imported
+    }
+
+    #[test]
+    fn export_round_trips_through_the_importer() {
+        let items = vault();
+        let imported = round_trip(&items);
+        assert_eq!(imported.len(), items.len());
+
+        for (original, imported) in items.iter().zip(&imported) {
+            assert_eq!(imported.kind, original.kind);
+            let fields = with_entry_type!(original.kind, T => T::FIELDS);
+            for field in fields {
+                assert_eq!(imported.entry.get(field.name), original.entry.get(field.name), "{}", field.name);
+            }
+            assert_eq!(imported.entry.get("id"), original.entry.get("id"));
+            assert_eq!(imported.tags(), original.tags());
+            assert_eq!(imported.created, original.created);
+            assert_eq!(imported.modified, original.modified);
+            assert_eq!(imported.accessed, original.accessed);
+        }
+
+        let login = &imported[0];
+        assert_eq!(login.entry["expires"], "2030-01-01T00:00:00Z");
+        assert_eq!(login.password_history, items[0].password_history);
+        assert_eq!(login.attachments.len(), 1);
+        assert_eq!(login.attachments[0].name, "recovery.txt");
+        assert_eq!(login.attachments[0].data, b"recovery codes");
+    }
+
+    #[test]
+    fn references_use_the_keepass_uuid_format() {
+        let mut login = item(
+            EntryKind::Login,
+            json!({
+                "id": "4f1e2d3c-0000-4000-8000-000000000005",
+                "title": "Copy",
+                "username": "{REF:U@I:4f1e2d3c-0000-4000-8000-000000000001}",
+                "password": "{ref:p@i:4F1E2D3C000040008000000000000001}",
+                "url": "",
+                "notes": "",
+                "otp": "",
+            }),
+        );
+        login.created = None;
+
+        let entry = &export_database(&[login]).entries[0];
+        assert_eq!(entry.field(kdbx::USERNAME), Some("{REF:U@I:4F1E2D3C000040008000000000000001}"));
+        assert_eq!(entry.field(kdbx::PASSWORD), Some("{REF:P@I:4F1E2D3C000040008000000000000001}"));
+    }
+}
@@ -1,24 +1,67 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod backup;
+mod bitwarden;
+mod breach;
+mod card;
 mod config;
+mod csv_import;
 mod database;
 mod encode;
 mod encryption;
 mod entry;
+mod expiry;
+mod files;
+mod generator;
 mod hash;
+mod health;
+mod import;
+mod kdbx;
+mod keepass;
 mod key_derivation;
+mod onepassword;
+mod passphrase;
+mod placeholder;
+mod plain_export;
+mod reference;
+mod ssh;
+mod strength;
+mod template;
+mod totp;
+mod validation;
 
-use entry::IdentityEntry;
-use entry::NoteEntry;
-use tauri::WindowEvent;
+use entry::AttachmentInfo;
+use entry::EntryKind;
+use entry::PasswordHistoryItem;
+use entry::TagCount;
+use entry::TimestampField;
+use serde_json::Value;
+use tauri::{Emitter, WindowEvent};
+use crate::bitwarden::ExportKdf;
+use crate::breach::{BreachSource, BreachedLogin};
+use crate::card::CardInfo;
 use crate::config::Config;
+use crate::csv_import::CsvOptions;
 use crate::database::Database;
-use crate::entry::CreditCardEntry;
-use crate::entry::LoginEntry;
+use crate::expiry::ExpiringItem;
+use crate::generator::{GeneratedPassword, PasswordPolicy};
+use crate::plain_export::{PlainEntry, PlainFormat};
+use crate::health::HealthReport;
+use crate::import::{ImportItem, ImportOptions, ImportPreview, ImportResult};
+use crate::passphrase::PassphraseOptions;
+use crate::reference::ReferencingEntry;
+use crate::ssh::{GeneratedSshKey, SshKeyInfo};
+use crate::strength::{LoginStrength, PasswordStrength};
+use crate::template::Template;
+use crate::totp::OtpCode;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::str;
+use std::time::Duration;
 
 #[tauri::command]
-fn add_login(db_path: &str, master_key: &str, entry: entry::LoginEntry) -> bool {
+fn add_entry(db_path: &str, master_key: &str, kind: EntryKind, entry: Value) -> bool {
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
@@ -27,259 +70,1194 @@ fn add_login(db_path: &str, master_key: &str, entry: entry::LoginEntry) -> bool
     {
         let db = db_instance.lock().unwrap();
 
-        db.add_login(entry)
+        db.add_entry_value(kind, entry).is_ok()
     }
+}
+
+#[tauri::command]
+fn get_entries(
+    db_path: &str,
+    master_key: &str,
+    kind: EntryKind,
+    sort_by: Option<TimestampField>,
+    descending: Option<bool>,
+) -> Result<Vec<Value>, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let mut entries: Vec<Value>;
+
+    {
+        // Lock the database instance and get entries
+        let db = db_instance.lock().unwrap();
+
+        entries = db.get_entry_values(kind).map_err(|e| e.to_string())?;
+    }
+    if let Some(sort_by) = sort_by {
+        Database::sort_entry_values(&mut entries, sort_by, descending.unwrap_or(true));
+    }
+    // Return the entries
+    Ok(entries)
+}
+
+#[tauri::command]
+fn update_entry(db_path: &str, master_key: &str, kind: EntryKind, entry: Value) -> bool {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        let db = db_instance.lock().unwrap();
+        db.update_entry_value(kind, entry)
+    }
+}
+
+#[tauri::command]
+fn delete_entry(db_path: &str, master_key: &str, kind: EntryKind, id: &str) -> bool {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        let db = db_instance.lock().unwrap();
+        db.delete_entry(kind, id)
+    }
+}
+
+#[tauri::command]
+fn mark_entry_accessed(db_path: &str, master_key: &str, kind: EntryKind, id: &str) -> bool {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        let db = db_instance.lock().unwrap();
+        db.mark_accessed(kind, id)
+    }
+}
+
+#[tauri::command]
+fn get_password_history(db_path: &str, master_key: &str, entry_id: &str) -> Result<Vec<PasswordHistoryItem>, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let history: Vec<PasswordHistoryItem>;
+
+    {
+        // Lock the database instance and get the history
+        let db = db_instance.lock().unwrap();
+
+        history = db.get_password_history(entry_id).map_err(|e| e.to_string())?;
+    }
+    // Return the history
+    Ok(history)
+}
+
+#[tauri::command]
+fn is_previous_password(db_path: &str, master_key: &str, entry_id: &str, password: &str) -> Result<bool, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        let db = db_instance.lock().unwrap();
+        db.is_previous_password(entry_id, password).map_err(|e| e.to_string())
+    }
+}
+
+#[tauri::command]
+fn get_attachments(db_path: &str, master_key: &str, entry_id: &str) -> Result<Vec<AttachmentInfo>, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let attachments: Vec<AttachmentInfo>;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        attachments = db.get_attachments(entry_id).map_err(|e| e.to_string())?;
+    }
+    Ok(attachments)
+}
+
+//
+// Attaches the file at the given path to an entry
+//
+#[tauri::command]
+fn add_attachment(db_path: &str, master_key: &str, kind: EntryKind, entry_id: &str, file_path: &str) -> Result<String, String> {
+    let data = fs::read(file_path).map_err(|e| e.to_string())?;
+    let name = match Path::new(file_path).file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Err(format!("Not a file: {}", file_path)),
+    };
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let id: String;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        id = db.add_attachment(kind, entry_id, &name, &data).map_err(|e| e.to_string())?;
+    }
+    Ok(id)
+}
+
+//
+// Writes the content of an attachment to the given path
+//
+#[tauri::command]
+fn save_attachment(db_path: &str, master_key: &str, id: &str, file_path: &str) -> Result<(), String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let data: Vec<u8>;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        (_, data) = db.get_attachment_data(id).map_err(|e| e.to_string())?;
+    }
+    fs::write(file_path, data).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_attachment(db_path: &str, master_key: &str, id: &str) -> bool {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        let db = db_instance.lock().unwrap();
+        db.delete_attachment(id)
+    }
+}
+
+#[tauri::command]
+fn resolve_field(db_path: &str, master_key: &str, entry_id: &str, field: &str) -> Result<String, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        // Resolve the references of the field when it is copied
+        let db = db_instance.lock().unwrap();
+        db.resolve_login_field(entry_id, field)
+    }
+}
+
+#[tauri::command]
+fn expand_placeholders(db_path: &str, master_key: &str, kind: EntryKind, entry_id: &str, text: &str) -> Result<String, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        // Expand the placeholders with the values of the entry
+        let db = db_instance.lock().unwrap();
+        db.expand_placeholders(kind, entry_id, text)
+    }
+}
+
+#[tauri::command]
+fn get_referencing_entries(db_path: &str, master_key: &str, entry_id: &str) -> Result<Vec<ReferencingEntry>, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let entries: Vec<ReferencingEntry>;
+
+    {
+        // Lock the database instance and find the entries referencing this one
+        let db = db_instance.lock().unwrap();
+
+        entries = db.get_referencing_entries(entry_id).map_err(|e| e.to_string())?;
+    }
+    // Return the entries
+    Ok(entries)
+}
+
+// Attempts before giving up on generating a password the entry has not used before
+const GENERATE_PASSWORD_ATTEMPTS: usize = 10;
+
+#[tauri::command]
+fn generate_password(
+    db_path: &str,
+    master_key: &str,
+    policy: Option<PasswordPolicy>,
+    policy_name: Option<String>,
+    entry_id: Option<String>,
+) -> Result<GeneratedPassword, String> {
+    // An explicit policy wins over a named one
+    let policy = match (policy, policy_name) {
+        (Some(policy), _) => policy,
+        (None, Some(policy_name)) => {
+            let config = Config::load().map_err(|e| e.to_string())?;
+            match generator::find_policy(&config.password_policies, &policy_name) {
+                Some(policy) => policy,
+                None => return Err(format!("Unknown password policy: {}", policy_name)),
+            }
+        }
+        (None, None) => PasswordPolicy::default(),
+    };
+
+    let entry_id = match entry_id {
+        Some(entry_id) => entry_id,
+        None => return generator::generate_password(&policy),
+    };
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        // Generate again while the password is one the entry had before
+        let db = db_instance.lock().unwrap();
+        for _ in 0..GENERATE_PASSWORD_ATTEMPTS {
+            let generated = generator::generate_password(&policy)?;
+            if !db.is_previous_password(&entry_id, &generated.password).map_err(|e| e.to_string())? {
+                return Ok(generated);
+            }
+        }
+    }
+
+    Err("Could not generate a password the entry has not used before, use a stronger policy".into())
+}
+
+#[tauri::command]
+fn generate_passphrase(options: Option<PassphraseOptions>) -> Result<GeneratedPassword, String> {
+    passphrase::generate_passphrase(&options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn estimate_password_strength(password: &str, user_inputs: Option<Vec<String>>) -> PasswordStrength {
+    let user_inputs = user_inputs.unwrap_or_default();
+    let user_inputs: Vec<&str> = user_inputs.iter().map(String::as_str).collect();
+    strength::estimate(password, &user_inputs)
+}
+
+#[tauri::command]
+fn score_logins(db_path: &str, master_key: &str) -> Result<Vec<LoginStrength>, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let scores: Vec<LoginStrength>;
+
+    {
+        // Lock the database instance and score the passwords
+        let db = db_instance.lock().unwrap();
+
+        scores = db.score_logins().map_err(|e| e.to_string())?;
+    }
+    // Return the scores
+    Ok(scores)
+}
+
+#[tauri::command]
+fn vault_health_report(
+    db_path: &str,
+    master_key: &str,
+    max_age_days: Option<u32>,
+    min_score: Option<u8>,
+) -> Result<HealthReport, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let report: HealthReport;
+
+    {
+        // Lock the database instance and check the logins
+        let db = db_instance.lock().unwrap();
+
+        report = db
+            .vault_health_report(
+                max_age_days.unwrap_or(health::DEFAULT_MAX_AGE_DAYS),
+                min_score.unwrap_or(health::DEFAULT_MIN_SCORE),
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    // Return the report
+    Ok(report)
+}
+
+#[tauri::command]
+fn check_breached_passwords(
+    db_path: &str,
+    master_key: &str,
+    hash_file: Option<String>,
+    range_endpoint: Option<String>,
+) -> Result<Vec<BreachedLogin>, String> {
+    let config = Config::load().map_err(|e| e.to_string())?;
+
+    // Sources given with the call win over the configured ones, a hash file over the range API
+    let hash_file = hash_file.filter(|path| !path.trim().is_empty());
+    let range_endpoint = range_endpoint.filter(|endpoint| !endpoint.trim().is_empty());
+    let source = match (hash_file, range_endpoint) {
+        (Some(hash_file), _) => BreachSource::HashFile(hash_file),
+        (None, Some(range_endpoint)) => BreachSource::RangeApi(range_endpoint),
+        (None, None) if !config.breach_hash_file.is_empty() => BreachSource::HashFile(config.breach_hash_file),
+        (None, None) if !config.breach_range_endpoint.is_empty() => {
+            BreachSource::RangeApi(config.breach_range_endpoint)
+        }
+        (None, None) => return Err("No breached password hash file or range API is configured".into()),
+    };
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    {
+        // Lock the database instance and check the passwords
+        let db = db_instance.lock().unwrap();
+        db.check_breached_passwords(source)
+    }
+}
+
+#[tauri::command]
+fn set_breach_sources(hash_file: &str, range_endpoint: &str) -> Result<(), String> {
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return Err(e.to_string()),
+    };
+    config.breach_hash_file = hash_file.trim().to_string();
+    config.breach_range_endpoint = range_endpoint.trim().to_string();
+    match config.save() {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn expiring_items(
+    db_path: &str,
+    master_key: &str,
+    within_days: Option<u32>,
+) -> Result<Vec<ExpiringItem>, String> {
+    let within_days = match within_days {
+        Some(within_days) => within_days,
+        None => match Config::load() {
+            Ok(config) => config.expiry_warning_days,
+            Err(e) => return Err(e.to_string()),
+        },
+    };
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let items: Vec<ExpiringItem>;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        items = db.expiring_items(within_days).map_err(|e| e.to_string())?;
+    }
+    Ok(items)
+}
+
+//
+// Run by the front end once the vault is open, sends the expired and
+// soon expiring cards and documents as an event when there are any
+//
+#[tauri::command]
+fn check_expiring_items(app: tauri::AppHandle, db_path: &str, master_key: &str) -> Result<usize, String> {
+    let items = expiring_items(db_path, master_key, None)?;
+    if !items.is_empty() {
+        if let Err(e) = app.emit(expiry::EXPIRING_ITEMS_EVENT, items.clone()) {
+            return Err(e.to_string());
+        }
+    }
+    Ok(items.len())
+}
+
+#[tauri::command]
+fn set_expiry_warning_days(days: u32) -> Result<(), String> {
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return Err(e.to_string()),
+    };
+    config.expiry_warning_days = days;
+    match config.save() {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn build_breach_index(source: &str, target: &str) -> Result<u64, String> {
+    breach::build_index(source, target)
+}
+
+#[tauri::command]
+fn set_min_master_password_score(score: u8) -> Result<(), String> {
+    if score > strength::MAX_SCORE {
+        return Err(format!("Score must be between 0 and {}", strength::MAX_SCORE));
+    }
+
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return Err(e.to_string()),
+    };
+    config.min_master_password_score = score;
+    match config.save() {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn get_password_policies() -> Result<Vec<PasswordPolicy>, String> {
+    match Config::load() {
+        Ok(config) => Ok(generator::all_policies(&config.password_policies)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn save_password_policy(mut policy: PasswordPolicy) -> Result<(), String> {
+    policy.name = policy.name.trim().to_string();
+    if policy.name.is_empty() {
+        return Err("Password policy name is empty".into());
+    }
+    generator::validate_policy(&policy)?;
+
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return Err(e.to_string()),
+    };
+    // Replace a saved policy with the same name
+    config
+        .password_policies
+        .retain(|saved| !saved.name.eq_ignore_ascii_case(&policy.name));
+    config.password_policies.push(policy);
+    match config.save() {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn delete_password_policy(name: &str) -> Result<(), String> {
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return Err(e.to_string()),
+    };
+    config
+        .password_policies
+        .retain(|saved| !saved.name.eq_ignore_ascii_case(name));
+    match config.save() {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+fn create_template(db_path: &str, master_key: &str, template: Template) -> bool {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
 
+    {
+        let db = db_instance.lock().unwrap();
+        db.add_template(template)
+    }
 }
 
 #[tauri::command]
-fn add_credit_card(db_path: &str, master_key: &str, entry: entry::CreditCardEntry) -> bool {
+fn update_template(db_path: &str, master_key: &str, template: Template) -> bool {
+    // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
-    // Add an entry
+
     {
         let db = db_instance.lock().unwrap();
+        db.update_template(template)
+    }
+}
+
+#[tauri::command]
+fn delete_template(db_path: &str, master_key: &str, id: &str) -> bool {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
 
-        db.add_credit_card(entry)
+    {
+        let db = db_instance.lock().unwrap();
+        db.delete_template(id)
     }
 }
 
 #[tauri::command]
-fn add_note(db_path: &str, master_key: &str, entry: entry::NoteEntry) -> bool {
+fn get_templates(db_path: &str, master_key: &str) -> Result<Vec<Template>, String> {
+    // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
-    // Add an entry
+
+    let templates: Vec<Template>;
+
     {
+        // Lock the database instance and get templates
         let db = db_instance.lock().unwrap();
 
-        db.add_note(entry)
+        templates = db.get_templates().map_err(|e| e.to_string())?;
     }
+    // Return the templates
+    Ok(templates)
 }
 
 #[tauri::command]
-fn add_identity(db_path: &str, master_key: &str, entry: entry::IdentityEntry) -> bool {
+fn instantiate_template(
+    db_path: &str,
+    master_key: &str,
+    template_id: &str,
+    title: &str,
+    fields: HashMap<String, String>,
+    tags: Vec<String>,
+) -> bool {
+    // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
-    // Add an entry
+
+    {
+        let db = db_instance.lock().unwrap();
+        db.add_entry_from_template(template_id, title, fields, tags).is_ok()
+    }
+}
+
+#[tauri::command]
+fn get_totp(db_path: &str, master_key: &str, entry_id: &str) -> Result<OtpCode, String> {
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let code: OtpCode;
+
+    {
+        // Lock the database instance and generate the code
+        let db = db_instance.lock().unwrap();
+
+        code = db.get_totp(entry_id).map_err(|e| e.to_string())?;
+    }
+    // Return the code
+    Ok(code)
+}
+
+#[tauri::command]
+fn generate_ssh_key(key_type: &str, bits: Option<usize>, comment: &str, passphrase: &str) -> Result<GeneratedSshKey, String> {
+    ssh::generate_key(key_type, bits, comment, passphrase)
+}
+
+#[tauri::command]
+fn inspect_ssh_key(private_key: &str, passphrase: &str) -> Result<SshKeyInfo, String> {
+    ssh::inspect_private_key(private_key, passphrase)
+}
+
+#[tauri::command]
+fn validate_iban(iban: &str) -> Result<String, String> {
+    validation::validate_iban(iban)
+}
+
+#[tauri::command]
+fn validate_card(card_number: &str, cvv: &str, expiry_date: &str) -> Result<CardInfo, String> {
+    card::validate_card(card_number, cvv, expiry_date, chrono::Local::now().date_naive())
+}
+
+#[tauri::command]
+fn mask_card_number(card_number: &str) -> String {
+    card::mask_number(card_number)
+}
+
+//
+// Reads a KeePass database and shows what importing it would do
+//
+#[tauri::command]
+fn preview_kdbx_import(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    password: &str,
+    key_file: Option<&str>,
+) -> Result<ImportPreview, String> {
+    let (items, warnings) = keepass::read_file(file_path, password, key_file)?;
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let preview: ImportPreview;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        preview = db.preview_import(&items, warnings).map_err(|e| e.to_string())?;
+    }
+    Ok(preview)
+}
+
+#[tauri::command]
+fn import_kdbx(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    password: &str,
+    key_file: Option<&str>,
+    options: Option<ImportOptions>,
+) -> Result<ImportResult, String> {
+    let (items, warnings) = keepass::read_file(file_path, password, key_file)?;
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let result: ImportResult;
+
     {
         let db = db_instance.lock().unwrap();
 
-        db.add_identity(entry)
+        result = db.import_items(items, &options.unwrap_or_default(), warnings)?;
     }
+    Ok(result)
 }
 
+//
+// Reads a Bitwarden JSON export and shows what importing it would do,
+// the password is only needed for password protected exports
+//
 #[tauri::command]
-fn get_logins(db_path: &str, master_key: &str) -> Result<Vec<LoginEntry>, String> {
+fn preview_bitwarden_import(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    password: Option<&str>,
+) -> Result<ImportPreview, String> {
+    let (items, warnings) = bitwarden::read_file(file_path, password)?;
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
-    let entries: Vec<LoginEntry>;
+    let preview: ImportPreview;
 
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
 
-        entries = db.get_logins().map_err(|e| e.to_string())?;
-        // Optionally print entries for debugging
-        println!("\n-------------------------------------------");
-        for entry in &entries {
-            println!("ID: {}", entry.id);
-            println!("Title: {}", entry.title);
-            println!("Username: {}", entry.username);
-            println!("Password Hash: {}", entry.password);
-            println!("Url: {}", entry.url);
-            println!("Notes: {}", entry.notes);
-            println!("Last Updated: {}", entry.last_updated);
-            println!("-------------------------------------------");
+        preview = db.preview_import(&items, warnings).map_err(|e| e.to_string())?;
+    }
+    Ok(preview)
+}
+
+#[tauri::command]
+fn import_bitwarden(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    password: Option<&str>,
+    options: Option<ImportOptions>,
+) -> Result<ImportResult, String> {
+    let (items, warnings) = bitwarden::read_file(file_path, password)?;
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let result: ImportResult;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        result = db.import_items(items, &options.unwrap_or_default(), warnings)?;
+    }
+    Ok(result)
+}
+
+//
+// Writes the whole vault as a Bitwarden JSON export, protected by the password when one
+// is given. Without a password every secret is readable, so like export_plaintext the
+// master password has to be entered again and the risk accepted. Returns what Bitwarden
+// has no place for, like attachments
+//
+#[tauri::command]
+fn export_bitwarden(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    password: Option<&str>,
+    kdf: Option<ExportKdf>,
+    confirm_master_key: Option<&str>,
+    accept_risk: bool,
+) -> Result<Vec<String>, String> {
+    let password = password.filter(|password| !password.is_empty());
+    if password.is_none() && !accept_risk {
+        return Err("Confirm that the export will contain every password in plain text".into());
+    }
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let items: Vec<ImportItem>;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        if password.is_none()
+            && !db.confirm_master_key(&encode::encode_base64(confirm_master_key.unwrap_or("").as_bytes().to_vec()))
+        {
+            return Err("The master password is wrong".into());
         }
+        items = db.export_resolved_items().map_err(|e| e.to_string())?;
     }
-    // Return the entries
-    Ok(entries)
+    bitwarden::write_file(file_path, &items, password, kdf.unwrap_or_default())
 }
 
+//
+// Reads a 1Password 1PUX export and shows what importing it would do
+//
 #[tauri::command]
-fn get_credit_cards(db_path: &str, master_key: &str) -> Result<Vec<CreditCardEntry>, String> {
+fn preview_onepassword_import(db_path: &str, master_key: &str, file_path: &str) -> Result<ImportPreview, String> {
+    let (items, warnings) = onepassword::read_file(file_path)?;
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
-    let entries: Vec<CreditCardEntry>;
+    let preview: ImportPreview;
 
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
 
-        entries = db.get_credit_cards().map_err(|e| e.to_string())?;
+        preview = db.preview_import(&items, warnings).map_err(|e| e.to_string())?;
     }
-    // Return the entries
-    Ok(entries)
+    Ok(preview)
 }
 
 #[tauri::command]
-fn get_notes(db_path: &str, master_key: &str) -> Result<Vec<NoteEntry>, String> {
+fn import_onepassword(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    options: Option<ImportOptions>,
+) -> Result<ImportResult, String> {
+    let (items, warnings) = onepassword::read_file(file_path)?;
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
-    let entries: Vec<NoteEntry>;
+    let result: ImportResult;
 
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
 
-        entries = db.get_notes().map_err(|e| e.to_string())?;
+        result = db.import_items(items, &options.unwrap_or_default(), warnings)?;
     }
-    // Return the entries
-    Ok(entries)
+    Ok(result)
+}
+
+//
+// Column names of a CSV file, mapped to login fields for the generic CSV import
+//
+#[tauri::command]
+fn get_csv_columns(file_path: &str, encoding: Option<&str>) -> Result<Vec<String>, String> {
+    csv_import::read_columns(file_path, encoding)
 }
 
+//
+// Reads a CSV export with a preset or a column mapping and shows what importing it would do
+//
 #[tauri::command]
-fn get_identities(db_path: &str, master_key: &str) -> Result<Vec<IdentityEntry>, String> {
+fn preview_csv_import(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    csv_options: CsvOptions,
+) -> Result<ImportPreview, String> {
+    let (items, warnings) = csv_import::read_file(file_path, &csv_options)?;
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
-    let entries: Vec<IdentityEntry>;
+    let preview: ImportPreview;
 
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
 
-        entries = db.get_identities().map_err(|e| e.to_string())?;
+        preview = db.preview_import(&items, warnings).map_err(|e| e.to_string())?;
     }
-    // Return the entries
-    Ok(entries)
+    Ok(preview)
 }
 
 #[tauri::command]
-fn delete_login(db_path: &str, master_key: &str, id: &str) -> () {
+fn import_csv(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    csv_options: CsvOptions,
+    options: Option<ImportOptions>,
+) -> Result<ImportResult, String> {
+    let (items, warnings) = csv_import::read_file(file_path, &csv_options)?;
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let result: ImportResult;
+
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
-        let _ = db.delete_login(id);
+
+        result = db.import_items(items, &options.unwrap_or_default(), warnings)?;
     }
+    Ok(result)
 }
 
+//
+// Writes the whole vault to a KeePass database protected by the given
+// password and / or key file, returns the number of exported entries
+//
 #[tauri::command]
-fn delete_credit_card(db_path: &str, master_key: &str, id: &str) -> () {
+fn export_kdbx(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    password: &str,
+    key_file: Option<&str>,
+) -> Result<usize, String> {
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let items: Vec<ImportItem>;
+
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
-        let _ = db.delete_credit_card(id);
+
+        items = db.export_items().map_err(|e| e.to_string())?;
     }
+    keepass::write_file(file_path, &items, password, key_file)?;
+    Ok(items.len())
 }
 
+//
+// Writes an encrypted backup of the whole vault, the backup password is held
+// to the same strength rules as the master password
+//
 #[tauri::command]
-fn delete_note(db_path: &str, master_key: &str, id: &str) -> () {
+fn export_backup(db_path: &str, master_key: &str, file_path: &str, password: &str) -> Result<usize, String> {
+    let min_score = match Config::load() {
+        Ok(config) => config.min_master_password_score,
+        Err(e) => return Err(e.to_string()),
+    };
+    strength::check_master_password(password, min_score)?;
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let templates: Vec<Template>;
+    let items: Vec<ImportItem>;
+
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
-        let _ = db.delete_note(id);
+
+        templates = db.get_templates().map_err(|e| e.to_string())?;
+        items = db.export_items().map_err(|e| e.to_string())?;
     }
+    backup::write_file(file_path, &templates, &items, password)?;
+    Ok(items.len())
 }
 
 #[tauri::command]
-fn delete_identity(db_path: &str, master_key: &str, id: &str) -> () {
+fn preview_backup_import(db_path: &str, master_key: &str, file_path: &str, password: &str) -> Result<ImportPreview, String> {
+    let backup = backup::read_file(file_path, password)?;
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let preview: ImportPreview;
+
     {
-        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
-        let _ = db.delete_identity(id);
+
+        preview = db.preview_import(&backup.items, Vec::new()).map_err(|e| e.to_string())?;
+    }
+    Ok(preview)
+}
+
+//
+// Merges a backup into the open vault, its templates are added first
+// so the custom entries using them validate
+//
+#[tauri::command]
+fn import_backup(
+    db_path: &str,
+    master_key: &str,
+    file_path: &str,
+    password: &str,
+    options: Option<ImportOptions>,
+) -> Result<ImportResult, String> {
+    let backup = backup::read_file(file_path, password)?;
+
+    // Get the database instance
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let result: ImportResult;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        if db.import_templates(backup.templates).is_err() {
+            return Err("Adding the templates of the backup failed".into());
+        }
+        result = db.import_items(backup.items, &options.unwrap_or_default(), Vec::new())?;
+    }
+    Ok(result)
+}
+
+//
+// Creates a new vault from a backup, like create_database the file must not exist yet
+//
+#[tauri::command]
+fn restore_backup(db_path: &str, master_key: &str, file_path: &str, password: &str) -> Result<ImportResult, String> {
+    if Database::database_exists(db_path) {
+        return Err(format!("{} already exists, pick another location for the new vault", db_path));
+    }
+    // The open vault would be returned instead of the new one and get the backup merged into it
+    if Database::has_instance() {
+        return Err("Close the open vault before restoring a backup".into());
+    }
+
+    // Refuse master passwords below the configured strength
+    let min_score = match Config::load() {
+        Ok(config) => config.min_master_password_score,
+        Err(e) => return Err(e.to_string()),
+    };
+    strength::check_master_password(master_key, min_score)?;
+
+    // Read the backup before the vault is created so a wrong password leaves nothing behind
+    let backup = backup::read_file(file_path, password)?;
+
+    let db_instance = database::Database::get_instance(
+        db_path,
+        &encode::encode_base64(master_key.as_bytes().to_vec()),
+    );
+
+    let result: ImportResult;
+
+    {
+        let db = db_instance.lock().unwrap();
+
+        if db.import_templates(backup.templates).is_err() {
+            return Err("Adding the templates of the backup failed".into());
+        }
+        result = db.import_items(backup.items, &ImportOptions::default(), Vec::new())?;
     }
+    Ok(result)
 }
 
+//
+// Writes every entry in plain text, only after the master password was entered
+// again and the user confirmed the risk. The files are readable by their owner only
+// and overwritten and removed after wipe_after seconds when it is set
+//
 #[tauri::command]
-fn update_login(db_path: &str, master_key: &str, entry: entry::LoginEntry) -> () {
+fn export_plaintext(
+    db_path: &str,
+    master_key: &str,
+    confirm_master_key: &str,
+    file_path: &str,
+    format: PlainFormat,
+    accept_risk: bool,
+    wipe_after: Option<u64>,
+) -> Result<Vec<String>, String> {
+    if !accept_risk {
+        return Err("Confirm that the export will contain every password in plain text".into());
+    }
+
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let entries: Vec<PlainEntry>;
+
     {
         let db = db_instance.lock().unwrap();
-        let _ = db.update_login(entry);
+
+        if !db.confirm_master_key(&encode::encode_base64(confirm_master_key.as_bytes().to_vec())) {
+            return Err("The master password is wrong".into());
+        }
+        entries = db.plain_entries().map_err(|e| e.to_string())?;
     }
+
+    let paths = plain_export::write_files(file_path, format, &entries)?;
+    if let Some(seconds) = wipe_after {
+        plain_export::schedule_wipe(paths.clone(), Duration::from_secs(seconds));
+    }
+    Ok(paths)
 }
 
 #[tauri::command]
-fn update_credit_card(db_path: &str, master_key: &str, entry: entry::CreditCardEntry) -> () {
+fn get_tags(db_path: &str, master_key: &str) -> Result<Vec<TagCount>, String> {
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let tags: Vec<TagCount>;
+
     {
+        // Lock the database instance and get tags with their entry counts
         let db = db_instance.lock().unwrap();
-        let _ = db.update_credit_card(entry);
+
+        tags = db.get_tags().map_err(|e| e.to_string())?;
     }
+    // Return the tags
+    Ok(tags)
 }
 
 #[tauri::command]
-fn update_note(db_path: &str, master_key: &str, entry: entry::NoteEntry) -> () {
+fn get_entries_by_tag(
+    db_path: &str,
+    master_key: &str,
+    tag: &str,
+    sort_by: Option<TimestampField>,
+    descending: Option<bool>,
+) -> Result<Vec<Value>, String> {
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let mut entries: Vec<Value>;
+
     {
+        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
-        let _ = db.update_note(entry);
+
+        entries = db.get_entries_by_tag(tag).map_err(|e| e.to_string())?;
+    }
+    if let Some(sort_by) = sort_by {
+        Database::sort_entry_values(&mut entries, sort_by, descending.unwrap_or(true));
     }
+    // Return the entries
+    Ok(entries)
 }
 
 #[tauri::command]
-fn update_identity(db_path: &str, master_key: &str, entry: entry::IdentityEntry) -> () {
+fn get_favorites(
+    db_path: &str,
+    master_key: &str,
+    sort_by: Option<TimestampField>,
+    descending: Option<bool>,
+) -> Result<Vec<Value>, String> {
     // Get the database instance
     let db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
+    let mut entries: Vec<Value>;
+
     {
+        // Lock the database instance and get entries
         let db = db_instance.lock().unwrap();
-        let _ = db.update_identity(entry);
+
+        entries = db.get_favorites().map_err(|e| e.to_string())?;
+    }
+    if let Some(sort_by) = sort_by {
+        Database::sort_entry_values(&mut entries, sort_by, descending.unwrap_or(true));
     }
+    // Return the entries
+    Ok(entries)
 }
 
 #[tauri::command]
@@ -325,17 +1303,24 @@ fn check_master_key_hash(db_path: &str, master_key: &str) -> bool {
 }
 
 #[tauri::command]
-fn create_database(db_path: &str, master_key: &str) -> bool {
+fn create_database(db_path: &str, master_key: &str) -> Result<bool, String> {
     if Database::database_exists(db_path) {
-        return false;
+        return Ok(false);
     }
 
+    // Refuse master passwords below the configured strength
+    let min_score = match Config::load() {
+        Ok(config) => config.min_master_password_score,
+        Err(e) => return Err(e.to_string()),
+    };
+    strength::check_master_password(master_key, min_score)?;
+
     let _db_instance = database::Database::get_instance(
         db_path,
         &encode::encode_base64(master_key.as_bytes().to_vec()),
     );
 
-    Database::database_exists(db_path)
+    Ok(Database::database_exists(db_path))
 }
 
 
@@ -377,25 +1362,68 @@ fn main() {
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
             create_database,
-            add_login,
-            add_credit_card,
-            add_note,
-            add_identity,
-            get_logins,
-            get_credit_cards,
-            get_notes,
-            get_identities,
+            add_entry,
+            get_entries,
+            update_entry,
+            delete_entry,
+            mark_entry_accessed,
+            get_password_history,
+            is_previous_password,
+            get_attachments,
+            add_attachment,
+            save_attachment,
+            delete_attachment,
+            resolve_field,
+            expand_placeholders,
+            get_referencing_entries,
+            generate_password,
+            generate_passphrase,
+            estimate_password_strength,
+            score_logins,
+            vault_health_report,
+            check_breached_passwords,
+            set_breach_sources,
+            build_breach_index,
+            expiring_items,
+            check_expiring_items,
+            set_expiry_warning_days,
+            set_min_master_password_score,
+            get_password_policies,
+            save_password_policy,
+            delete_password_policy,
+            create_template,
+            update_template,
+            delete_template,
+            get_templates,
+            instantiate_template,
+            get_totp,
+            generate_ssh_key,
+            inspect_ssh_key,
+            validate_iban,
+            validate_card,
+            mask_card_number,
+            preview_kdbx_import,
+            import_kdbx,
+            export_kdbx,
+            preview_bitwarden_import,
+            import_bitwarden,
+            export_bitwarden,
+            get_csv_columns,
+            preview_csv_import,
+            import_csv,
+            preview_onepassword_import,
+            import_onepassword,
+            export_backup,
+            preview_backup_import,
+            import_backup,
+            restore_backup,
+            export_plaintext,
+            get_tags,
+            get_entries_by_tag,
+            get_favorites,
             get_database_path,
             set_database_path,
             is_database_valid,
-            update_login,
-            update_credit_card,
-            update_note,
-            update_identity,
-            delete_login,
-            delete_credit_card,
-            delete_note,
-            delete_identity,
             check_master_key_hash,
             save_master_key_hash,
             minimize_window,
@@ -0,0 +1,408 @@
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use zip::ZipArchive;
+
+use crate::database::MAX_ATTACHMENT_SIZE;
+use crate::entry::EntryKind;
+use crate::import::{ImportAttachment, ImportItem, Unmapped};
+use crate::totp;
+
+//
+// 1Password 1PUX exports, a zip archive with the accounts, vaults and items in
+// export.data ( JSON ) and the attached files in files/<document id>__<file name>.
+// Logins, passwords, credit cards, secure notes and identities are imported,
+// section fields KeeManager has no field for end up in the notes
+//
+
+const EXPORT_DATA: &str = "export.data";
+
+const CATEGORY_LOGIN: &str = "001";
+const CATEGORY_CREDIT_CARD: &str = "002";
+const CATEGORY_SECURE_NOTE: &str = "003";
+const CATEGORY_IDENTITY: &str = "004";
+const CATEGORY_PASSWORD: &str = "005";
+const CATEGORY_DOCUMENT: &str = "006";
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Export {
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Account {
+    vaults: Vec<Vault>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Vault {
+    attrs: VaultAttributes,
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct VaultAttributes {
+    name: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Item {
+    fav_index: i64,
+    created_at: Option<i64>,
+    updated_at: Option<i64>,
+    trashed: bool,
+    state: String,
+    category_uuid: String,
+    details: Details,
+    overview: Overview,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Details {
+    login_fields: Vec<LoginField>,
+    notes_plain: Option<String>,
+    sections: Vec<Section>,
+    password_history: Vec<PreviousPassword>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LoginField {
+    value: String,
+    name: String,
+    designation: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Section {
+    title: String,
+    fields: Vec<SectionField>,
+}
+
+// The value is an object with a single key naming its kind, like { "concealed": "..." }
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SectionField {
+    title: String,
+    id: String,
+    value: Value,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PreviousPassword {
+    value: String,
+    time: i64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Overview {
+    title: String,
+    url: String,
+    urls: Vec<OverviewUrl>,
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct OverviewUrl {
+    url: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct FileAttributes {
+    file_name: String,
+    document_id: String,
+}
+
+//
+// Value of a section field as text, dates become YYYY-MM-DD,
+// month-year values ( 202512 ) MM/YYYY and addresses one line
+//
+fn field_text(value: &Value) -> String {
+    let (kind, value) = match value.as_object().and_then(|object| object.iter().next()) {
+        Some(entry) => entry,
+        None => return String::new(),
+    };
+
+    match (kind.as_str(), value) {
+        ("date", Value::Number(seconds)) => seconds
+            .as_i64()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+        ("monthYear", Value::Number(month_year)) => match month_year.as_i64() {
+            Some(month_year) if month_year > 0 => format!("{:02}/{}", month_year % 100, month_year / 100),
+            _ => String::new(),
+        },
+        ("email", Value::Object(email)) => email.get("email_address").and_then(Value::as_str).unwrap_or("").to_string(),
+        ("address", Value::Object(address)) => ["street", "city", "state", "zip", "country"]
+            .iter()
+            .filter_map(|part| address.get(*part).and_then(Value::as_str))
+            .filter(|part| !part.trim().is_empty())
+            .collect::<Vec<&str>>()
+            .join(", "),
+        (_, Value::String(text)) => text.clone(),
+        (_, Value::Number(number)) => number.to_string(),
+        (_, Value::Bool(flag)) => flag.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn field_kind(value: &Value) -> &str {
+    value.as_object().and_then(|object| object.keys().next()).map(String::as_str).unwrap_or("")
+}
+
+// Name of a section field in the notes, with the section title when it has one
+fn field_name(section: &Section, field: &SectionField) -> String {
+    let name = if field.title.is_empty() { field.id.as_str() } else { field.title.as_str() };
+    if section.title.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} / {}", section.title, name)
+    }
+}
+
+//
+// Reads a 1PUX export with its attached files
+//
+pub fn read_file(path: &str) -> Result<(Vec<ImportItem>, Vec<String>), String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Reading {} failed: {}", path, e)),
+    };
+    let mut archive = match ZipArchive::new(file) {
+        Ok(archive) => archive,
+        Err(e) => return Err(format!("Not a 1PUX export: {}", e)),
+    };
+
+    let mut json = String::new();
+    match archive.by_name(EXPORT_DATA) {
+        Ok(mut data) => {
+            if let Err(e) = data.read_to_string(&mut json) {
+                return Err(format!("Reading {} failed: {}", EXPORT_DATA, e));
+            }
+        }
+        Err(_) => return Err(format!("Not a 1PUX export: {} is missing", EXPORT_DATA)),
+    };
+    let export: Export = match serde_json::from_str(&json) {
+        Ok(export) => export,
+        Err(e) => return Err(format!("Invalid {}: {}", EXPORT_DATA, e)),
+    };
+
+    let mut items = Vec::new();
+    let mut warnings = Vec::new();
+    for vault in export.accounts.iter().flat_map(|account| &account.vaults) {
+        for item in &vault.items {
+            if item.trashed || item.state == "deleted" {
+                warnings.push(format!("{}: deleted item not imported", item.overview.title));
+                continue;
+            }
+            if let Some(mut imported) = import_item(item, &mut archive, &mut warnings) {
+                imported.group = vault.attrs.name.clone();
+                items.push(imported);
+            }
+        }
+    }
+    Ok((items, warnings))
+}
+
+//
+// Attached file from files/<document id>__<file name>, files over
+// the attachment size limit are left out
+//
+fn read_attachment(
+    archive: &mut ZipArchive<File>,
+    file: &FileAttributes,
+    title: &str,
+    warnings: &mut Vec<String>,
+) -> Option<ImportAttachment> {
+    let prefix = format!("files/{}__", file.document_id);
+    let name = archive.file_names().find(|name| !file.document_id.is_empty() && name.starts_with(&prefix)).map(str::to_string);
+    let mut entry = match name.and_then(|name| archive.by_name(&name).ok()) {
+        Some(entry) => entry,
+        None => {
+            warnings.push(format!("{}: attachment {} is missing from the export", title, file.file_name));
+            return None;
+        }
+    };
+    if entry.size() > MAX_ATTACHMENT_SIZE as u64 {
+        warnings.push(format!("{}: attachment {} is larger than {} MiB", title, file.file_name, MAX_ATTACHMENT_SIZE / 1024 / 1024));
+        return None;
+    }
+
+    let mut data = Vec::new();
+    if let Err(e) = entry.read_to_end(&mut data) {
+        warnings.push(format!("{}: reading attachment {} failed: {}", title, file.file_name, e));
+        return None;
+    }
+    Some(ImportAttachment { name: file.file_name.clone(), data })
+}
+
+fn import_item(item: &Item, archive: &mut ZipArchive<File>, warnings: &mut Vec<String>) -> Option<ImportItem> {
+    let title = item.overview.title.as_str();
+    let kind = match item.category_uuid.as_str() {
+        CATEGORY_LOGIN | CATEGORY_PASSWORD => EntryKind::Login,
+        CATEGORY_CREDIT_CARD => EntryKind::CreditCard,
+        CATEGORY_SECURE_NOTE => EntryKind::Note,
+        CATEGORY_IDENTITY => EntryKind::Identity,
+        CATEGORY_DOCUMENT => {
+            warnings.push(format!("{}: document items are not imported", title));
+            return None;
+        }
+        category => {
+            warnings.push(format!("{}: unsupported item category {}", title, category));
+            return None;
+        }
+    };
+
+    let mut imported = ImportItem::new(kind);
+    let mut unmapped = Unmapped::default();
+    imported.set("id", "");
+    imported.set("title", title);
+    imported.set("favorite", item.fav_index > 0);
+    imported.set("tags", item.overview.tags.clone());
+    imported.created = item.created_at;
+    imported.modified = item.updated_at;
+    let notes = item.details.notes_plain.clone().unwrap_or_default();
+
+    // Section fields the item type maps, the rest goes to the notes
+    let mapped: &[&str] = match kind {
+        EntryKind::CreditCard => &["cardholder", "type", "ccnum", "cvv", "expiry"],
+        EntryKind::Identity => &["firstname", "initial", "lastname", "birthdate"],
+        _ => &[],
+    };
+    let section_value = |id: &str| {
+        item.details
+            .sections
+            .iter()
+            .flat_map(|section| &section.fields)
+            .find(|field| field.id == id)
+            .map(|field| field_text(&field.value))
+            .unwrap_or_default()
+    };
+
+    let notes_field = match kind {
+        EntryKind::Login => {
+            import_login(&mut imported, item, &mut unmapped);
+            imported.set("notes", notes);
+            Some("notes")
+        }
+        EntryKind::CreditCard => {
+            imported.set("cardholder_name", section_value("cardholder"));
+            imported.set("card_number", section_value("ccnum"));
+            imported.set("cvv", section_value("cvv"));
+            imported.set("expiry_date", section_value("expiry"));
+            unmapped.add("Notes", &notes);
+            None
+        }
+        EntryKind::Identity => {
+            let full_name: Vec<String> = ["firstname", "initial", "lastname"]
+                .iter()
+                .map(|id| section_value(id))
+                .filter(|name| !name.trim().is_empty())
+                .collect();
+            imported.set("full_name", full_name.join(" "));
+            imported.set("date_of_birth", section_value("birthdate"));
+            for field in ["nationality", "identification_number", "issue_date", "expiry_date", "issuer"] {
+                imported.set(field, "");
+            }
+            imported.set("notes", notes);
+            Some("notes")
+        }
+        _ => {
+            imported.set("content", notes);
+            Some("content")
+        }
+    };
+
+    for section in &item.details.sections {
+        for field in &section.fields {
+            if mapped.contains(&field.id.as_str()) {
+                continue;
+            }
+            match field_kind(&field.value) {
+                "file" => {
+                    let file: FileAttributes = serde_json::from_value(field.value["file"].clone()).unwrap_or_default();
+                    if let Some(attachment) = read_attachment(archive, &file, title, warnings) {
+                        imported.attachments.push(attachment);
+                    }
+                }
+                // The first valid one-time password of a login is its otp
+                "totp" if kind == EntryKind::Login
+                    && imported.entry.get("otp").and_then(Value::as_str) == Some("")
+                    && totp::parse(&field_text(&field.value)).is_ok() =>
+                {
+                    imported.set("otp", field_text(&field.value));
+                }
+                _ => unmapped.add(&field_name(section, field), &field_text(&field.value)),
+            }
+        }
+    }
+
+    unmapped.finish(&mut imported, notes_field, warnings);
+    Some(imported)
+}
+
+//
+// Username and password of a login come from its login fields, password items
+// only have a password, the first url is the login's and the others go to the notes
+//
+fn import_login(imported: &mut ImportItem, item: &Item, unmapped: &mut Unmapped) {
+    let details = &item.details;
+    let designated = |designation: &str| {
+        details
+            .login_fields
+            .iter()
+            .find(|field| field.designation == designation)
+            .map(|field| field.value.clone())
+            .unwrap_or_default()
+    };
+
+    imported.set("username", designated("username"));
+    let password = match &details.password {
+        Some(password) if item.category_uuid == CATEGORY_PASSWORD => password.clone(),
+        _ => designated("password"),
+    };
+    imported.set("password", password);
+    imported.set("otp", "");
+
+    let mut urls: Vec<&str> = Vec::new();
+    for url in std::iter::once(item.overview.url.as_str()).chain(item.overview.urls.iter().map(|url| url.url.as_str())) {
+        if !url.trim().is_empty() && !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    imported.set("url", urls.first().copied().unwrap_or(""));
+    for (index, url) in urls.iter().enumerate().skip(1) {
+        unmapped.add(&format!("URL {}", index + 1), url);
+    }
+
+    // Form fields besides username and password, like a second login field
+    for field in &details.login_fields {
+        if field.designation.is_empty() && !field.name.is_empty() {
+            unmapped.add(&field.name, &field.value);
+        }
+    }
+
+    let mut history: Vec<(String, i64)> = details
+        .password_history
+        .iter()
+        .filter(|previous| !previous.value.is_empty())
+        .map(|previous| (previous.value.clone(), previous.time))
+        .collect();
+    history.sort_by_key(|(_, replaced)| *replaced);
+    imported.password_history = history;
+}
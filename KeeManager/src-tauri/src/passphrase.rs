@@ -0,0 +1,279 @@
+use aes_gcm::aead::OsRng;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+
+use crate::generator::GeneratedPassword;
+
+// EFF long word list, five dice per word ( "11111 abacus" )
+const EFF_LARGE_WORDLIST: &str = include_str!("../wordlists/eff_large_wordlist.txt");
+
+static EFF_WORDS: Lazy<Vec<String>> =
+    Lazy::new(|| parse_word_list(EFF_LARGE_WORDLIST).expect("Embedded word list is invalid"));
+
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!@#$%^&*-_=+?~";
+
+const MIN_WORDS: usize = 3;
+const MAX_WORDS: usize = 20;
+// Custom word lists need enough words to be worth using
+const MIN_WORD_LIST_LENGTH: usize = 100;
+const MAX_WORD_LIST_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capitalization {
+    #[default]
+    Lowercase,
+    Uppercase,
+    // First letter of every word
+    Capitalize,
+    // Every word is capitalized or not at random, one more bit per word
+    Random,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PassphraseOptions {
+    #[serde(default = "default_word_count")]
+    pub word_count: usize,
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    #[serde(default)]
+    pub capitalization: Capitalization,
+    #[serde(default)]
+    pub append_digit: bool,
+    #[serde(default)]
+    pub append_symbol: bool,
+    // Path of a custom word list, the EFF long word list is used when empty
+    #[serde(default)]
+    pub word_list: String,
+}
+
+fn default_word_count() -> usize {
+    6
+}
+
+fn default_separator() -> String {
+    "-".into()
+}
+
+impl Default for PassphraseOptions {
+    fn default() -> Self {
+        PassphraseOptions {
+            word_count: default_word_count(),
+            separator: default_separator(),
+            capitalization: Capitalization::default(),
+            append_digit: false,
+            append_symbol: false,
+            word_list: String::new(),
+        }
+    }
+}
+
+//
+// Reads a word list, either one word per line or
+// diceware lines with the dice rolls before the word
+//
+pub fn load_word_list(path: &str) -> Result<Vec<String>, String> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > MAX_WORD_LIST_SIZE => {
+            return Err(format!("Word list {} is too large", path));
+        }
+        Ok(_) => (),
+        Err(e) => return Err(format!("Reading word list {} failed: {}", path, e)),
+    };
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return Err(format!("Reading word list {} failed: {}", path, e)),
+    };
+
+    let words = parse_word_list(&content)?;
+    if words.len() < MIN_WORD_LIST_LENGTH {
+        return Err(format!(
+            "Word list {} has {} different words, at least {} are needed",
+            path,
+            words.len(),
+            MIN_WORD_LIST_LENGTH
+        ));
+    }
+
+    Ok(words)
+}
+
+//
+// Words of a list without duplicates, empty lines and "#" comments are skipped
+//
+fn parse_word_list(content: &str) -> Result<Vec<String>, String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let word = match parts.as_slice() {
+            [word] => *word,
+            [dice, word] if dice.chars().all(|c| c.is_ascii_digit()) => *word,
+            _ => return Err(format!("Word list line {} has more than one word", number + 1)),
+        };
+
+        if seen.insert(word.to_lowercase()) {
+            words.push(word.to_lowercase());
+        }
+    }
+
+    Ok(words)
+}
+
+//
+// Generates a passphrase of random words with the operating system's random number generator
+//
+pub fn generate_passphrase(options: &PassphraseOptions) -> Result<GeneratedPassword, String> {
+    if options.word_count < MIN_WORDS || options.word_count > MAX_WORDS {
+        return Err(format!(
+            "Passphrases must have between {} and {} words",
+            MIN_WORDS, MAX_WORDS
+        ));
+    }
+
+    let custom_words;
+    let words: &Vec<String> = if options.word_list.trim().is_empty() {
+        &EFF_WORDS
+    } else {
+        custom_words = load_word_list(options.word_list.trim())?;
+        &custom_words
+    };
+
+    let mut entropy = options.word_count as f64 * (words.len() as f64).log2();
+
+    let mut chosen: Vec<String> = Vec::with_capacity(options.word_count);
+    for _ in 0..options.word_count {
+        let word = &words[OsRng.gen_range(0..words.len())];
+        let word = match options.capitalization {
+            Capitalization::Lowercase => word.clone(),
+            Capitalization::Uppercase => word.to_uppercase(),
+            Capitalization::Capitalize => capitalize(word),
+            Capitalization::Random if OsRng.gen_bool(0.5) => capitalize(word),
+            Capitalization::Random => word.clone(),
+        };
+        chosen.push(word);
+    }
+    if options.capitalization == Capitalization::Random {
+        entropy += options.word_count as f64;
+    }
+
+    let mut passphrase = chosen.join(&options.separator);
+    for (append, characters) in [(options.append_digit, DIGITS), (options.append_symbol, SYMBOLS)] {
+        if append {
+            let characters: Vec<char> = characters.chars().collect();
+            passphrase.push(characters[OsRng.gen_range(0..characters.len())]);
+            entropy += (characters.len() as f64).log2();
+        }
+    }
+
+    Ok(GeneratedPassword {
+        password: passphrase,
+        entropy,
+    })
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_word_list(name: &str, words: usize) -> String {
+        let path = std::env::temp_dir().join(format!("keemanager-{}-{}.txt", name, std::process::id()));
+        let content: Vec<String> = (0..words).map(|number| format!("word{}", number)).collect();
+        fs::write(&path, content.join("\n")).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn embedded_word_list_has_7776_unique_words() {
+        assert_eq!(EFF_WORDS.len(), 7776);
+        assert_eq!(EFF_WORDS.iter().collect::<HashSet<_>>().len(), 7776);
+        assert!(EFF_WORDS.iter().all(|word| !word.is_empty() && !word.chars().any(char::is_whitespace)));
+    }
+
+    #[test]
+    fn word_count_and_separator() {
+        let options = PassphraseOptions { word_count: 5, separator: " + ".into(), ..Default::default() };
+        let generated = generate_passphrase(&options).unwrap();
+        let words: Vec<&str> = generated.password.split(" + ").collect();
+        assert_eq!(words.len(), 5);
+        assert!(words.iter().all(|word| EFF_WORDS.contains(&word.to_string())));
+        assert!((generated.entropy - 5.0 * 7776f64.log2()).abs() < 1e-9);
+
+        for word_count in [2, 21] {
+            let options = PassphraseOptions { word_count, ..Default::default() };
+            assert!(generate_passphrase(&options).is_err());
+        }
+    }
+
+    #[test]
+    fn capitalization() {
+        let passphrase = |capitalization| {
+            let options = PassphraseOptions { capitalization, ..Default::default() };
+            generate_passphrase(&options).unwrap()
+        };
+
+        let lowercase = passphrase(Capitalization::Lowercase).password;
+        assert_eq!(lowercase, lowercase.to_lowercase());
+        let uppercase = passphrase(Capitalization::Uppercase).password;
+        assert_eq!(uppercase, uppercase.to_uppercase());
+        let capitalized = passphrase(Capitalization::Capitalize).password;
+        for word in capitalized.split('-') {
+            assert_eq!(word, capitalize(&word.to_lowercase()));
+        }
+
+        let random = passphrase(Capitalization::Random);
+        assert!((random.entropy - 6.0 * 7776f64.log2() - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn appended_digit_and_symbol() {
+        let options = PassphraseOptions { append_digit: true, append_symbol: true, ..Default::default() };
+        let generated = generate_passphrase(&options).unwrap();
+        let mut end = generated.password.chars().rev();
+        assert!(SYMBOLS.contains(end.next().unwrap()));
+        assert!(DIGITS.contains(end.next().unwrap()));
+    }
+
+    #[test]
+    fn custom_word_lists() {
+        let short = write_word_list("short", MIN_WORD_LIST_LENGTH - 1);
+        let error = load_word_list(&short).unwrap_err();
+        assert!(error.contains("has 99 different words, at least 100 are needed"), "{}", error);
+        let options = PassphraseOptions { word_list: short.clone(), ..Default::default() };
+        assert!(generate_passphrase(&options).is_err());
+        fs::remove_file(short).unwrap();
+
+        let list = write_word_list("list", 128);
+        let options = PassphraseOptions { word_count: 4, word_list: list.clone(), ..Default::default() };
+        let generated = generate_passphrase(&options).unwrap();
+        assert!(generated.password.split('-').all(|word| word.starts_with("word")));
+        assert!((generated.entropy - 28.0).abs() < 1e-9);
+        fs::remove_file(list).unwrap();
+    }
+
+    #[test]
+    fn word_list_formats() {
+        let words = parse_word_list("\u{feff}# comment\n11111 Abacus\n\nabacus\nzebra\n").unwrap();
+        assert_eq!(words, vec!["abacus", "zebra"]);
+        assert!(parse_word_list("two words\n").is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use chrono::{DateTime, Local, Utc};
+use serde_json::{Map, Value};
+
+//
+// Values placeholders are expanded from, the entry is the JSON object
+// of a login or custom entry ( custom entries keep their values in "fields" )
+//
+pub struct PlaceholderContext<'a> {
+    pub entry: &'a Map<String, Value>,
+    pub totp: Option<&'a str>,
+    pub now: DateTime<Local>,
+}
+
+// Parts of a URL, "{URL:HOST}" etc.
+struct UrlParts {
+    scheme: String,
+    host: String,
+    port: String,
+    path: String,
+    query: String,
+    without_scheme: String,
+}
+
+//
+// Expands KeePass style placeholders:
+// {TITLE} {USERNAME} {PASSWORD} {URL} {NOTES}, {URL:SCM|HOST|PORT|PATH|QUERY|RMVSCM},
+// {TOTP}, {DT_SIMPLE} {DT_UTC_SIMPLE} {DT_YEAR} ... {DT_SECOND}, {ENV:VAR},
+// {S:Name} and {Name} for custom fields, {{} and {}} for literal braces.
+// Unknown placeholders are kept as they are and inserted values are not expanded again,
+// except the URL of the entry which may itself use placeholders ( "https://{USERNAME}.example.com" )
+//
+pub fn expand(text: &str, context: &PlaceholderContext) -> Result<String, String> {
+    expand_text(text, context, false)
+}
+
+//
+// Whether the text uses the placeholder ( e.g. "TOTP" ), used to skip
+// work with side effects like incrementing a HOTP counter
+//
+pub fn uses_placeholder(text: &str, name: &str) -> bool {
+    text.to_uppercase().contains(&format!("{{{}}}", name.to_uppercase()))
+}
+
+fn expand_text(text: &str, context: &PlaceholderContext, in_url: bool) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // Escaped braces
+        if rest.starts_with("{{}") {
+            expanded.push('{');
+            rest = &rest[3..];
+            continue;
+        }
+        if rest.starts_with("{}}") {
+            expanded.push('}');
+            rest = &rest[3..];
+            continue;
+        }
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        let name = &rest[1..end];
+
+        match placeholder_value(name, context, in_url)? {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[..=end]),
+        };
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+//
+// Value of one placeholder, None when the placeholder is unknown
+//
+fn placeholder_value(name: &str, context: &PlaceholderContext, in_url: bool) -> Result<Option<String>, String> {
+    let upper = name.to_uppercase();
+
+    let value = match upper.as_str() {
+        "TITLE" | "USERNAME" | "PASSWORD" | "NOTES" => field(context.entry, &upper.to_lowercase()),
+        "URL" if !in_url => Some(url(context)?),
+        "URL:SCM" | "URL:HOST" | "URL:PORT" | "URL:PATH" | "URL:QUERY" | "URL:RMVSCM" if !in_url => {
+            let parts = url_parts(&url(context)?);
+            Some(match upper.as_str() {
+                "URL:SCM" => parts.scheme,
+                "URL:HOST" => parts.host,
+                "URL:PORT" => parts.port,
+                "URL:PATH" => parts.path,
+                "URL:QUERY" => parts.query,
+                _ => parts.without_scheme,
+            })
+        }
+        "TOTP" => match context.totp {
+            Some(code) => Some(code.to_string()),
+            None => return Err("Entry has no one time password".into()),
+        },
+        "DT_SIMPLE" => Some(context.now.format("%Y%m%d%H%M%S").to_string()),
+        "DT_UTC_SIMPLE" => Some(context.now.with_timezone(&Utc).format("%Y%m%d%H%M%S").to_string()),
+        "DT_YEAR" => Some(context.now.format("%Y").to_string()),
+        "DT_MONTH" => Some(context.now.format("%m").to_string()),
+        "DT_DAY" => Some(context.now.format("%d").to_string()),
+        "DT_HOUR" => Some(context.now.format("%H").to_string()),
+        "DT_MINUTE" => Some(context.now.format("%M").to_string()),
+        "DT_SECOND" => Some(context.now.format("%S").to_string()),
+        // Unset variables expand to nothing like in KeePass
+        _ if upper.starts_with("ENV:") => Some(std::env::var(&name[4..]).unwrap_or_default()),
+        _ if upper.starts_with("S:") => custom_field(context.entry, &name[2..]),
+        _ => custom_field(context.entry, name),
+    };
+
+    Ok(value)
+}
+
+//
+// Field of the entry, custom entries may hold it as a custom field instead
+//
+fn field(entry: &Map<String, Value>, name: &str) -> Option<String> {
+    match entry.get(name).and_then(Value::as_str) {
+        Some(value) => Some(value.to_string()),
+        None => custom_field(entry, name),
+    }
+}
+
+fn custom_field(entry: &Map<String, Value>, name: &str) -> Option<String> {
+    let fields = entry.get("fields").and_then(Value::as_object)?;
+    fields
+        .iter()
+        .find(|(field, _)| field.eq_ignore_ascii_case(name))
+        .and_then(|(_, value)| value.as_str())
+        .map(String::from)
+}
+
+// URL of the entry with its own placeholders expanded
+fn url(context: &PlaceholderContext) -> Result<String, String> {
+    let url = field(context.entry, "url").unwrap_or_default();
+    expand_text(&url, context, true)
+}
+
+fn url_parts(url: &str) -> UrlParts {
+    let url = url.trim();
+
+    let (scheme, rest) = match url.find("://") {
+        Some(index) => (&url[..index], &url[index + 3..]),
+        None => ("", url),
+    };
+
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    let after_authority = &rest[authority_end..];
+
+    // Drop "user:password@"
+    let host_port = match authority.rfind('@') {
+        Some(index) => &authority[index + 1..],
+        None => authority,
+    };
+
+    let (host, port) = if host_port.starts_with('[') {
+        // IPv6 literal, "[::1]:8080"
+        match host_port.find(']') {
+            Some(index) => (&host_port[..=index], host_port[index + 1..].trim_start_matches(':')),
+            None => (host_port, ""),
+        }
+    } else {
+        match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, port),
+            None => (host_port, ""),
+        }
+    };
+
+    let port = if port.is_empty() {
+        match scheme.to_lowercase().as_str() {
+            "http" => "80",
+            "https" => "443",
+            "ftp" => "21",
+            _ => "",
+        }
+    } else {
+        port
+    };
+
+    let path_end = after_authority.find(['?', '#']).unwrap_or(after_authority.len());
+    let path = &after_authority[..path_end];
+    let query = match after_authority[path_end..].strip_prefix('?') {
+        Some(query) => format!("?{}", query.split('#').next().unwrap_or("")),
+        None => String::new(),
+    };
+
+    UrlParts {
+        scheme: scheme.to_string(),
+        host: host.to_string(),
+        port: port.to_string(),
+        path: path.to_string(),
+        query,
+        without_scheme: rest.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn login() -> Map<String, Value> {
+        json!({
+            "id": "4f1e2d3c-0000-4000-8000-000000000001",
+            "title": "Mail",
+            "username": "alice",
+            "password": "p{a}ss",
+            "url": "https://mail.example.com:8443/inbox?folder=1#top",
+            "notes": "note",
+        })
+        .as_object()
+        .unwrap()
+        .clone()
+    }
+
+    fn context(entry: &Map<String, Value>) -> PlaceholderContext<'_> {
+        PlaceholderContext {
+            entry,
+            totp: Some("123456"),
+            now: Local.with_ymd_and_hms(2024, 3, 5, 7, 8, 9).unwrap(),
+        }
+    }
+
+    fn expand_entry(text: &str, entry: &Map<String, Value>) -> String {
+        expand(text, &context(entry)).unwrap()
+    }
+
+    #[test]
+    fn expands_entry_fields() {
+        let entry = login();
+        assert_eq!(expand_entry("{TITLE}/{USERNAME}/{NOTES}", &entry), "Mail/alice/note");
+        assert_eq!(expand_entry("{PASSWORD}", &entry), "p{a}ss");
+        assert_eq!(
+            expand_entry("{URL}", &entry),
+            "https://mail.example.com:8443/inbox?folder=1#top"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_case_insensitive() {
+        let entry = login();
+        assert_eq!(expand_entry("{username}{UserName}", &entry), "alicealice");
+        assert_eq!(expand_entry("{url:host}", &entry), "mail.example.com");
+    }
+
+    #[test]
+    fn expands_url_parts() {
+        let entry = login();
+        assert_eq!(expand_entry("{URL:SCM}", &entry), "https");
+        assert_eq!(expand_entry("{URL:HOST}", &entry), "mail.example.com");
+        assert_eq!(expand_entry("{URL:PORT}", &entry), "8443");
+        assert_eq!(expand_entry("{URL:PATH}", &entry), "/inbox");
+        assert_eq!(expand_entry("{URL:QUERY}", &entry), "?folder=1");
+        assert_eq!(
+            expand_entry("{URL:RMVSCM}", &entry),
+            "mail.example.com:8443/inbox?folder=1#top"
+        );
+    }
+
+    #[test]
+    fn url_parts_of_unusual_urls() {
+        let parts = url_parts("http://user:secret@[::1]/x");
+        assert_eq!(parts.host, "[::1]");
+        assert_eq!(parts.port, "80");
+        assert_eq!(parts.path, "/x");
+
+        let parts = url_parts("example.com");
+        assert_eq!(parts.scheme, "");
+        assert_eq!(parts.host, "example.com");
+        assert_eq!(parts.port, "");
+        assert_eq!(parts.path, "");
+        assert_eq!(parts.query, "");
+
+        let parts = url_parts("ssh://admin@server:2222");
+        assert_eq!(parts.host, "server");
+        assert_eq!(parts.port, "2222");
+    }
+
+    #[test]
+    fn dynamic_urls() {
+        let mut entry = login();
+        entry.insert("url".into(), json!("https://{USERNAME}.example.com/{URL:HOST}"));
+        assert_eq!(
+            expand_entry("{URL}", &entry),
+            "https://alice.example.com/{URL:HOST}"
+        );
+        assert_eq!(expand_entry("{URL:HOST}", &entry), "alice.example.com");
+    }
+
+    #[test]
+    fn inserted_values_are_not_expanded_again() {
+        let mut entry = login();
+        entry.insert("password".into(), json!("{USERNAME}{{}"));
+        assert_eq!(expand_entry("{PASSWORD}", &entry), "{USERNAME}{{}");
+    }
+
+    #[test]
+    fn command_line_templates() {
+        let entry = login();
+        assert_eq!(
+            expand_entry("ssh -p {URL:PORT} {USERNAME}@{URL:HOST}", &entry),
+            "ssh -p 8443 alice@mail.example.com"
+        );
+    }
+
+    #[test]
+    fn expands_totp() {
+        let entry = login();
+        assert_eq!(expand_entry("{PASSWORD}{TOTP}", &entry), "p{a}ss123456");
+
+        let context = PlaceholderContext { totp: None, ..context(&entry) };
+        assert!(expand("{TOTP}", &context).is_err());
+        assert_eq!(expand("{USERNAME}", &context).unwrap(), "alice");
+    }
+
+    #[test]
+    fn expands_dates() {
+        let entry = login();
+        assert_eq!(expand_entry("{DT_SIMPLE}", &entry), "20240305070809");
+        assert_eq!(
+            expand_entry("{DT_YEAR}-{DT_MONTH}-{DT_DAY} {DT_HOUR}:{DT_MINUTE}:{DT_SECOND}", &entry),
+            "2024-03-05 07:08:09"
+        );
+        let utc = Local.with_ymd_and_hms(2024, 3, 5, 7, 8, 9).unwrap().with_timezone(&Utc);
+        assert_eq!(
+            expand_entry("{DT_UTC_SIMPLE}", &entry),
+            utc.format("%Y%m%d%H%M%S").to_string()
+        );
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        std::env::set_var("KEEMANAGER_PLACEHOLDER_TEST", "value");
+        let entry = login();
+        assert_eq!(expand_entry("{ENV:KEEMANAGER_PLACEHOLDER_TEST}", &entry), "value");
+        assert_eq!(expand_entry("[{ENV:KEEMANAGER_PLACEHOLDER_UNSET}]", &entry), "[]");
+    }
+
+    #[test]
+    fn expands_custom_fields() {
+        let entry = json!({
+            "id": "4f1e2d3c-0000-4000-8000-000000000002",
+            "title": "Server",
+            "fields": { "Hostname": "db1", "Username": "root", "Root Password": "toor" },
+        })
+        .as_object()
+        .unwrap()
+        .clone();
+
+        assert_eq!(expand_entry("{S:Hostname}", &entry), "db1");
+        assert_eq!(expand_entry("{hostname}", &entry), "db1");
+        assert_eq!(expand_entry("{Root Password}", &entry), "toor");
+        // Standard placeholders fall back to custom fields of the same name
+        assert_eq!(expand_entry("{USERNAME}@{S:HOSTNAME}", &entry), "root@db1");
+        assert_eq!(expand_entry("{TITLE}", &entry), "Server");
+    }
+
+    #[test]
+    fn keeps_unknown_and_unterminated_placeholders() {
+        let entry = login();
+        assert_eq!(expand_entry("{UNKNOWN} {USERNAME}", &entry), "{UNKNOWN} alice");
+        assert_eq!(expand_entry("{S:Missing}", &entry), "{S:Missing}");
+        assert_eq!(expand_entry("{USERNAME", &entry), "{USERNAME");
+        assert_eq!(expand_entry("a } b", &entry), "a } b");
+        assert_eq!(expand_entry("", &entry), "");
+    }
+
+    #[test]
+    fn escaped_braces() {
+        let entry = login();
+        assert_eq!(expand_entry("{{}USERNAME{}}", &entry), "{USERNAME}");
+        assert_eq!(expand_entry("{{}{USERNAME}{}}", &entry), "{alice}");
+    }
+
+    #[test]
+    fn detects_used_placeholders() {
+        assert!(uses_placeholder("{USERNAME}{tab}{totp}", "TOTP"));
+        assert!(!uses_placeholder("{USERNAME}", "TOTP"));
+    }
+}
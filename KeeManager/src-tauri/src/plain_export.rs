@@ -0,0 +1,315 @@
+use aes_gcm::aead::OsRng;
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::entry::EntryKind;
+use crate::files::write_private;
+
+//
+// Plaintext exports for auditors or printing: JSON, one CSV file per entry type,
+// and a Markdown or HTML report. Every secret ends up readable in these files,
+// they are written for the owner only and can be overwritten and removed after a delay
+//
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlainFormat {
+    Json,
+    Csv,
+    Markdown,
+    Html,
+}
+
+//
+// Entry with its references resolved, the fields in the order of the entry type
+// ( custom entries list their template's fields by name )
+//
+pub struct PlainEntry {
+    pub kind: EntryKind,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl PlainEntry {
+    fn title(&self) -> String {
+        self.fields
+            .iter()
+            .find(|(name, _)| name == "title")
+            .map(|(_, value)| text(value))
+            .unwrap_or_default()
+    }
+}
+
+fn heading(kind: EntryKind) -> &'static str {
+    match kind {
+        EntryKind::Login => "Logins",
+        EntryKind::CreditCard => "Credit cards",
+        EntryKind::Note => "Notes",
+        EntryKind::Identity => "Identities",
+        EntryKind::SshKey => "SSH keys",
+        EntryKind::BankAccount => "Bank accounts",
+        EntryKind::Wifi => "Wi-Fi networks",
+        EntryKind::ApiCredential => "API credentials",
+        EntryKind::SoftwareLicense => "Software licenses",
+        EntryKind::Custom => "Custom entries",
+    }
+}
+
+// "card_number" -> "Card number"
+pub fn label(name: &str) -> String {
+    let name = name.replace('_', " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+// Value as text, tags are joined with commas
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values.iter().map(text).collect::<Vec<String>>().join(", "),
+        value => value.to_string(),
+    }
+}
+
+// The reports leave out the title ( it is the heading ), empty fields and unset flags
+fn shown(name: &str, value: &Value) -> bool {
+    name != "title" && *value != Value::Bool(false) && !text(value).is_empty()
+}
+
+//
+// Writes the export, CSV exports become one file per entry type next to the chosen
+// path ( vault.csv -> vault-login.csv, vault-note.csv, ... ), returns the written files
+//
+pub fn write_files(path: &str, format: PlainFormat, entries: &[PlainEntry]) -> Result<Vec<String>, String> {
+    let exported = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+    if format != PlainFormat::Csv {
+        let data = match format {
+            PlainFormat::Json => to_json(entries, &exported)?,
+            PlainFormat::Markdown => to_markdown(entries, &exported),
+            _ => to_html(entries, &exported),
+        };
+        write_private(path, data.as_bytes())?;
+        return Ok(vec![path.to_string()]);
+    }
+
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("export");
+    let mut written = Vec::new();
+    for kind in EntryKind::ALL {
+        let of_kind: Vec<&PlainEntry> = entries.iter().filter(|entry| entry.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        let file = path.with_file_name(format!("{}-{}.csv", stem, kind.as_str()));
+        let file = file.to_string_lossy().to_string();
+        write_private(&file, &to_csv(&of_kind)?)?;
+        written.push(file);
+    }
+    Ok(written)
+}
+
+fn to_json(entries: &[PlainEntry], exported: &str) -> Result<String, String> {
+    let mut by_kind = Map::new();
+    for kind in EntryKind::ALL {
+        let objects: Vec<Value> = entries
+            .iter()
+            .filter(|entry| entry.kind == kind)
+            .map(|entry| Value::Object(entry.fields.iter().cloned().collect()))
+            .collect();
+        if !objects.is_empty() {
+            by_kind.insert(kind.as_str().to_string(), Value::Array(objects));
+        }
+    }
+
+    match serde_json::to_string_pretty(&json!({ "exported": exported, "entries": by_kind })) {
+        Ok(json) => Ok(json),
+        Err(e) => Err(format!("Serializing the export failed: {}", e)),
+    }
+}
+
+//
+// Entries of one type, the columns are every field name in the order they first appear
+// since custom entries of different templates have different fields
+//
+fn to_csv(entries: &[&PlainEntry]) -> Result<Vec<u8>, String> {
+    let mut columns: Vec<&str> = Vec::new();
+    for (name, _) in entries.iter().flat_map(|entry| &entry.fields) {
+        if !columns.contains(&name.as_str()) {
+            columns.push(name);
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut records = vec![columns.iter().map(|name| name.to_string()).collect::<Vec<String>>()];
+    for entry in entries {
+        records.push(
+            columns
+                .iter()
+                .map(|column| entry.fields.iter().find(|(name, _)| name == column).map(|(_, value)| text(value)).unwrap_or_default())
+                .collect(),
+        );
+    }
+    for record in records {
+        if let Err(e) = writer.write_record(&record) {
+            return Err(format!("Writing the CSV export failed: {}", e));
+        }
+    }
+    match writer.into_inner() {
+        Ok(data) => Ok(data),
+        Err(e) => Err(format!("Writing the CSV export failed: {}", e)),
+    }
+}
+
+// Characters with a meaning in Markdown
+fn escape_markdown(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if "\\`*_[]<>#|~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn to_markdown(entries: &[PlainEntry], exported: &str) -> String {
+    let mut markdown = format!(
+        "# KeeManager export\n\nExported {}. Every password in this file is readable, destroy it when it is no longer needed.\n",
+        exported
+    );
+
+    for kind in EntryKind::ALL {
+        let of_kind: Vec<&PlainEntry> = entries.iter().filter(|entry| entry.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        markdown.push_str(&format!("\n## {}\n", heading(kind)));
+
+        for entry in of_kind {
+            markdown.push_str(&format!("\n### {}\n\n", escape_markdown(&entry.title())));
+            for (name, value) in &entry.fields {
+                if !shown(name, value) {
+                    continue;
+                }
+                let value = text(value);
+                if value.contains('\n') {
+                    // A fence longer than any run of backticks in the value keeps it verbatim
+                    let longest = value.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                    let fence = "`".repeat(longest.max(2) + 1);
+                    markdown.push_str(&format!("- **{}:**\n\n{}\n{}\n{}\n\n", escape_markdown(&label(name)), fence, value, fence));
+                } else {
+                    markdown.push_str(&format!("- **{}:** {}\n", escape_markdown(&label(name)), escape_markdown(&value)));
+                }
+            }
+        }
+    }
+    markdown
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn to_html(entries: &[PlainEntry], exported: &str) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>KeeManager export</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         section { break-inside: avoid; border-top: 1px solid #ccc; }\n\
+         dt { font-weight: bold; }\n\
+         dd { margin: 0 0 0.5em 1em; font-family: monospace; white-space: pre-wrap; word-break: break-all; }\n\
+         </style>\n</head>\n<body>\n<h1>KeeManager export</h1>\n",
+    );
+    html.push_str(&format!(
+        "<p>Exported {}. Every password in this file is readable, destroy it when it is no longer needed.</p>\n",
+        escape_html(exported)
+    ));
+
+    for kind in EntryKind::ALL {
+        let of_kind: Vec<&PlainEntry> = entries.iter().filter(|entry| entry.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        html.push_str(&format!("<h2>{}</h2>\n", heading(kind)));
+
+        for entry in of_kind {
+            html.push_str(&format!("<section>\n<h3>{}</h3>\n<dl>\n", escape_html(&entry.title())));
+            for (name, value) in &entry.fields {
+                if !shown(name, value) {
+                    continue;
+                }
+                let value = text(value);
+                html.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", escape_html(&label(name)), escape_html(&value)));
+            }
+            html.push_str("</dl>\n</section>\n");
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+//
+// Overwrites the file with random bytes and removes it, a file that is gone already
+// is fine. Solid state drives and copy-on-write file systems may keep the old blocks,
+// so this shortens how long the export lies around rather than guaranteeing it is unrecoverable
+//
+pub fn wipe_file(path: &str) -> Result<(), String> {
+    let length = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Ok(()),
+    };
+
+    let mut file = match OpenOptions::new().write(true).open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("Opening {} failed: {}", path, e)),
+    };
+    let mut block = vec![0u8; 64 * 1024];
+    let mut remaining = length;
+    while remaining > 0 {
+        let size = remaining.min(block.len() as u64) as usize;
+        OsRng.fill_bytes(&mut block[..size]);
+        if let Err(e) = file.write_all(&block[..size]) {
+            return Err(format!("Overwriting {} failed: {}", path, e));
+        }
+        remaining -= size as u64;
+    }
+    if let Err(e) = file.sync_all() {
+        return Err(format!("Overwriting {} failed: {}", path, e));
+    }
+    drop(file);
+
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("Removing {} failed: {}", path, e)),
+    }
+}
+
+//
+// Wipes the files once the delay has passed, files are left alone
+// when the application is closed before that
+//
+pub fn schedule_wipe(paths: Vec<String>, delay: Duration) {
+    thread::spawn(move || {
+        thread::sleep(delay);
+        for path in paths {
+            if let Err(e) = wipe_file(&path) {
+                eprintln!("Wipe Export: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,391 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+
+// Login fields a reference can point to ( KeePass field codes )
+pub const REFERENCE_FIELDS: [(char, &str); 5] = [
+    ('T', "title"),
+    ('U', "username"),
+    ('P', "password"),
+    ('A', "url"),
+    ('N', "notes"),
+];
+
+const REFERENCE_START: &str = "{REF:";
+
+#[derive(Debug, PartialEq)]
+pub enum ReferenceError {
+    Invalid(String),
+    Unresolved(String),
+    Cycle(String),
+}
+
+impl fmt::Display for ReferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReferenceError::Invalid(reference) => write!(f, "Invalid reference: {}", reference),
+            ReferenceError::Unresolved(reference) => write!(f, "Unresolved reference: {}", reference),
+            ReferenceError::Cycle(reference) => write!(f, "Reference cycle: {}", reference),
+        }
+    }
+}
+
+//
+// One "{REF:P@I:<uuid>}" placeholder inside a field value
+//
+pub struct Reference {
+    pub start: usize,
+    pub end: usize,
+    pub field: &'static str,
+    pub entry_id: String,
+}
+
+// Entry referencing another one, returned before the referenced entry is deleted
+#[derive(Serialize, Deserialize)]
+pub struct ReferencingEntry {
+    pub id: String,
+    pub title: String,
+    pub fields: Vec<String>,
+}
+
+//
+// Finds the references in a field value, only references by
+// entry UUID ( "@I:" ) are supported
+//
+pub fn find_references(value: &str) -> Result<Vec<Reference>, ReferenceError> {
+    // ASCII upper case keeps the byte offsets of the original value
+    let upper = value.to_ascii_uppercase();
+
+    let mut references = Vec::new();
+    let mut offset = 0;
+    while let Some(position) = upper[offset..].find(REFERENCE_START) {
+        let start = offset + position;
+        let end = match upper[start..].find('}') {
+            Some(length) => start + length + 1,
+            None => return Err(ReferenceError::Invalid(value[start..].to_string())),
+        };
+        let reference = &value[start..end];
+
+        // "P@I:<uuid>"
+        let body = &upper[start + REFERENCE_START.len()..end - 1];
+        let (field_code, target) = match body.split_once('@') {
+            Some(parts) => parts,
+            None => return Err(ReferenceError::Invalid(reference.to_string())),
+        };
+        let field = match REFERENCE_FIELDS
+            .iter()
+            .find(|(code, _)| field_code.len() == 1 && field_code.starts_with(*code))
+        {
+            Some((_, field)) => *field,
+            None => return Err(ReferenceError::Invalid(reference.to_string())),
+        };
+        let entry_id = match target.strip_prefix("I:").map(Uuid::parse_str) {
+            Some(Ok(uuid)) => uuid.to_string(),
+            _ => return Err(ReferenceError::Invalid(reference.to_string())),
+        };
+
+        references.push(Reference {
+            start,
+            end,
+            field,
+            entry_id,
+        });
+        offset = end;
+    }
+
+    Ok(references)
+}
+
+pub fn has_references(value: &str) -> bool {
+    value.to_ascii_uppercase().contains(REFERENCE_START)
+}
+
+//
+// Replaces every reference in the value with the referenced field,
+// visiting holds the ( entry id, field ) pairs being resolved to detect cycles
+//
+pub fn resolve_value(
+    entries: &HashMap<String, Map<String, Value>>,
+    value: &str,
+    visiting: &mut Vec<(String, &'static str)>,
+) -> Result<String, ReferenceError> {
+    let references = find_references(value)?;
+
+    let mut resolved = String::new();
+    let mut offset = 0;
+    for reference in references {
+        resolved.push_str(&value[offset..reference.start]);
+        let text = &value[reference.start..reference.end];
+
+        if visiting
+            .iter()
+            .any(|(entry_id, field)| *entry_id == reference.entry_id && *field == reference.field)
+        {
+            return Err(ReferenceError::Cycle(text.to_string()));
+        }
+
+        let target = match entries
+            .get(&reference.entry_id)
+            .and_then(|entry| entry.get(reference.field))
+            .and_then(Value::as_str)
+        {
+            Some(target) => target,
+            None => return Err(ReferenceError::Unresolved(text.to_string())),
+        };
+
+        visiting.push((reference.entry_id.clone(), reference.field));
+        let target = resolve_value(entries, target, visiting);
+        visiting.pop();
+
+        resolved.push_str(&target?);
+        offset = reference.end;
+    }
+    resolved.push_str(&value[offset..]);
+
+    Ok(resolved)
+}
+
+//
+// Resolves one field of an entry ( e.g. when it is copied )
+//
+pub fn resolve_field(
+    entries: &HashMap<String, Map<String, Value>>,
+    entry_id: &str,
+    field: &str,
+) -> Result<String, ReferenceError> {
+    let field = match REFERENCE_FIELDS.iter().find(|(_, name)| *name == field) {
+        Some((_, field)) => *field,
+        None => return Err(ReferenceError::Invalid(format!("unknown field {}", field))),
+    };
+
+    let value = match entries
+        .get(entry_id)
+        .and_then(|entry| entry.get(field))
+        .and_then(Value::as_str)
+    {
+        Some(value) => value,
+        None => return Err(ReferenceError::Unresolved(format!("{}@{}", field, entry_id))),
+    };
+
+    resolve_value(entries, value, &mut vec![(entry_id.to_string(), field)])
+}
+
+//
+// Names of the fields of the entry that reference the given entry
+//
+pub fn referencing_fields(entry: &Map<String, Value>, entry_id: &str) -> Vec<String> {
+    REFERENCE_FIELDS
+        .iter()
+        .filter(|(_, field)| {
+            let value = entry.get(*field).and_then(Value::as_str).unwrap_or("");
+            match find_references(value) {
+                Ok(references) => references.iter().any(|reference| reference.entry_id == entry_id),
+                Err(_) => false,
+            }
+        })
+        .map(|(_, field)| field.to_string())
+        .collect()
+}
+
+//
+// Replaces the references in the fields of an entry with the values they point to,
+// the stored values are kept in "references" so they survive an update.
+// Fields that cannot be resolved keep their stored value
+//
+pub fn resolve_references(entries: &HashMap<String, Map<String, Value>>, entry: &mut Map<String, Value>) -> Vec<ReferenceError> {
+    let id = entry.get("id").and_then(Value::as_str).unwrap_or("").to_string();
+
+    let mut references = Map::new();
+    let mut errors = Vec::new();
+    for (_, field) in REFERENCE_FIELDS {
+        let value = match entry.get(field).and_then(Value::as_str) {
+            Some(value) if has_references(value) => value.to_string(),
+            _ => continue,
+        };
+
+        match resolve_value(entries, &value, &mut vec![(id.clone(), field)]) {
+            Ok(resolved) => {
+                entry.insert(field.to_string(), Value::String(resolved));
+            }
+            Err(e) => errors.push(e),
+        };
+        references.insert(field.to_string(), Value::String(value));
+    }
+
+    entry.insert("references".into(), Value::Object(references));
+    errors
+}
+
+//
+// Puts the stored references back into fields the front end did not change,
+// a field that was edited keeps the new value
+//
+pub fn restore_references(entries: &HashMap<String, Map<String, Value>>, entry: &mut Map<String, Value>) {
+    let id = entry.get("id").and_then(Value::as_str).unwrap_or("").to_string();
+
+    let references = match entry.remove("references") {
+        Some(Value::Object(references)) => references,
+        _ => return,
+    };
+
+    for (_, field) in REFERENCE_FIELDS {
+        let reference = match references.get(field).and_then(Value::as_str) {
+            Some(reference) => reference,
+            None => continue,
+        };
+        let value = entry.get(field).and_then(Value::as_str).unwrap_or("");
+
+        if value == reference {
+            continue;
+        }
+        if let Ok(resolved) = resolve_value(entries, reference, &mut vec![(id.clone(), field)]) {
+            if value == resolved {
+                entry.insert(field.to_string(), Value::String(reference.to_string()));
+            }
+        }
+    }
+}
+
+//
+// Checks that every reference of the entry points to an existing field
+// and does not lead back to itself, entries must already contain the entry
+//
+pub fn check_references(entries: &HashMap<String, Map<String, Value>>, entry: &Map<String, Value>) -> Result<(), ReferenceError> {
+    let id = entry.get("id").and_then(Value::as_str).unwrap_or("").to_string();
+
+    for (_, field) in REFERENCE_FIELDS {
+        let value = entry.get(field).and_then(Value::as_str).unwrap_or("");
+        if has_references(value) {
+            resolve_value(entries, value, &mut vec![(id.clone(), field)])?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const A: &str = "4f1e2d3c-0000-4000-8000-00000000000a";
+    const B: &str = "4f1e2d3c-0000-4000-8000-00000000000b";
+    const MISSING: &str = "4f1e2d3c-0000-4000-8000-0000000000ff";
+
+    fn login(id: &str, username: &str, password: &str) -> Map<String, Value> {
+        json!({ "id": id, "title": "Mail", "username": username, "password": password, "url": "", "notes": "" })
+            .as_object()
+            .unwrap()
+            .clone()
+    }
+
+    fn by_id(logins: Vec<Map<String, Value>>) -> HashMap<String, Map<String, Value>> {
+        logins
+            .into_iter()
+            .map(|login| (login["id"].as_str().unwrap().to_string(), login))
+            .collect()
+    }
+
+    #[test]
+    fn finds_references() {
+        let value = format!("{{REF:U@I:{}}} and {{ref:p@i:{}}}", A, B.to_uppercase());
+        let references = find_references(&value).unwrap();
+        assert_eq!(references.len(), 2);
+        assert_eq!((references[0].start, references[0].end), (0, 46));
+        assert_eq!((references[0].field, references[0].entry_id.as_str()), ("username", A));
+        // Lower case references are found and the UUID is normalized
+        assert_eq!((references[1].field, references[1].entry_id.as_str()), ("password", B));
+        assert_eq!(&value[references[1].start..references[1].end], format!("{{ref:p@i:{}}}", B.to_uppercase()));
+
+        assert!(find_references("no references").unwrap().is_empty());
+        assert!(has_references("x{ref:T@I:abc}"));
+        assert!(!has_references("{REFERENCE}"));
+    }
+
+    #[test]
+    fn invalid_references_are_refused() {
+        for value in [
+            format!("{{REF:P@I:{}", A),
+            format!("{{REF:P{}}}", A),
+            format!("{{REF:X@I:{}}}", A),
+            format!("{{REF:PU@I:{}}}", A),
+            "{REF:P@T:Mail}".to_string(),
+            "{REF:P@I:not-a-uuid}".to_string(),
+        ] {
+            assert!(matches!(find_references(&value), Err(ReferenceError::Invalid(_))), "{}", value);
+        }
+    }
+
+    #[test]
+    fn resolves_chained_references() {
+        let b = login(B, "bob", &format!("{{REF:P@I:{}}}", A));
+        let entries = by_id(vec![login(A, "alice", "secret"), b.clone()]);
+        assert_eq!(resolve_field(&entries, B, "password").unwrap(), "secret");
+        assert_eq!(
+            resolve_value(&entries, &format!("{{REF:U@I:{}}}:{{REF:P@I:{}}}", A, B), &mut Vec::new()).unwrap(),
+            "alice:secret"
+        );
+        assert!(check_references(&entries, &b).is_ok());
+    }
+
+    #[test]
+    fn cycles_are_refused() {
+        // A -> B -> A
+        let a = login(A, "alice", &format!("{{REF:P@I:{}}}", B));
+        let entries = by_id(vec![a.clone(), login(B, "bob", &format!("{{REF:P@I:{}}}", A))]);
+        assert_eq!(
+            check_references(&entries, &a).unwrap_err(),
+            ReferenceError::Cycle(format!("{{REF:P@I:{}}}", A))
+        );
+
+        // A field referencing itself
+        let a = login(A, "alice", &format!("{{REF:P@I:{}}}", A));
+        let entries = by_id(vec![a.clone()]);
+        assert!(matches!(check_references(&entries, &a), Err(ReferenceError::Cycle(_))));
+
+        // Another field of the same entry is not a cycle
+        let a = login(A, "alice", &format!("{{REF:U@I:{}}}", A));
+        let entries = by_id(vec![a.clone()]);
+        assert_eq!(resolve_field(&entries, A, "password").unwrap(), "alice");
+    }
+
+    #[test]
+    fn unresolved_references_are_refused() {
+        let a = login(A, "alice", &format!("{{REF:P@I:{}}}", MISSING));
+        let entries = by_id(vec![a.clone()]);
+        assert_eq!(
+            check_references(&entries, &a).unwrap_err(),
+            ReferenceError::Unresolved(format!("{{REF:P@I:{}}}", MISSING))
+        );
+        assert_eq!(
+            resolve_field(&entries, MISSING, "password").unwrap_err().to_string(),
+            format!("Unresolved reference: password@{}", MISSING)
+        );
+        assert!(matches!(resolve_field(&entries, A, "otp"), Err(ReferenceError::Invalid(_))));
+    }
+
+    #[test]
+    fn restores_unchanged_references() {
+        let reference = format!("{{REF:P@I:{}}}", A);
+        let entries = by_id(vec![login(A, "alice", "secret"), login(B, "bob", &reference)]);
+
+        let mut b = entries[B].clone();
+        assert!(resolve_references(&entries, &mut b).is_empty());
+        assert_eq!(b["password"], "secret");
+        assert_eq!(b["references"]["password"], reference);
+
+        // The resolved value comes back unchanged, the reference is kept
+        let mut unchanged = b.clone();
+        restore_references(&entries, &mut unchanged);
+        assert_eq!(unchanged["password"], reference);
+        assert!(unchanged.get("references").is_none());
+
+        // An edited value replaces the reference
+        let mut edited = b.clone();
+        edited.insert("password".into(), Value::String("changed".into()));
+        restore_references(&entries, &mut edited);
+        assert_eq!(edited["password"], "changed");
+    }
+}
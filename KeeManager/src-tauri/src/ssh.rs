@@ -0,0 +1,212 @@
+use aes_gcm::aead::OsRng;
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use serde::{Deserialize, Serialize};
+use ssh_key::private::{KeypairData, RsaKeypair};
+use ssh_key::{Algorithm, HashAlg, LineEnding, PrivateKey, PublicKey};
+
+const DEFAULT_RSA_BITS: usize = 4096;
+const ALLOWED_RSA_BITS: [usize; 3] = [2048, 3072, 4096];
+
+//
+// Public details computed from a key,
+// the front end never has to parse keys itself
+//
+#[derive(Serialize, Deserialize)]
+pub struct SshKeyInfo {
+    pub key_type: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub comment: String,
+    pub encrypted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GeneratedSshKey {
+    pub private_key: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub key_type: String,
+}
+
+//
+// Parses an OpenSSH or PEM ( PKCS#1 / PKCS#8 ) private key,
+// the passphrase is only needed for encrypted keys
+//
+pub fn inspect_private_key(private_key: &str, passphrase: &str) -> Result<SshKeyInfo, String> {
+    let (key, encrypted) = parse_private_key(private_key, passphrase)?;
+
+    let public_key = match key.public_key().to_openssh() {
+        Ok(public_key) => public_key,
+        Err(e) => return Err(format!("Public key encoding failed: {}", e)),
+    };
+
+    Ok(SshKeyInfo {
+        key_type: key.algorithm().as_str().to_string(),
+        public_key,
+        fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+        comment: key.comment().to_string(),
+        encrypted,
+    })
+}
+
+//
+// Parses an OpenSSH public key line ( "ssh-ed25519 AAAA... comment" )
+//
+pub fn inspect_public_key(public_key: &str) -> Result<SshKeyInfo, String> {
+    let key = match PublicKey::from_openssh(public_key.trim()) {
+        Ok(key) => key,
+        Err(e) => return Err(format!("Invalid public key: {}", e)),
+    };
+
+    let public_key = match key.to_openssh() {
+        Ok(public_key) => public_key,
+        Err(e) => return Err(format!("Public key encoding failed: {}", e)),
+    };
+
+    Ok(SshKeyInfo {
+        key_type: key.algorithm().as_str().to_string(),
+        public_key,
+        fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+        comment: key.comment().to_string(),
+        encrypted: false,
+    })
+}
+
+//
+// Generates a new ed25519 or RSA keypair in OpenSSH format,
+// the private key is encrypted when a passphrase is given
+//
+pub fn generate_key(
+    key_type: &str,
+    bits: Option<usize>,
+    comment: &str,
+    passphrase: &str,
+) -> Result<GeneratedSshKey, String> {
+    let mut key = match key_type.to_ascii_lowercase().as_str() {
+        "ed25519" | "ssh-ed25519" => match PrivateKey::random(&mut OsRng, Algorithm::Ed25519) {
+            Ok(key) => key,
+            Err(e) => return Err(format!("Key generation failed: {}", e)),
+        },
+        "rsa" | "ssh-rsa" => {
+            let bits = bits.unwrap_or(DEFAULT_RSA_BITS);
+            if !ALLOWED_RSA_BITS.contains(&bits) {
+                return Err(format!("Unsupported RSA key size: {}", bits));
+            }
+            let keypair = match RsaKeypair::random(&mut OsRng, bits) {
+                Ok(keypair) => keypair,
+                Err(e) => return Err(format!("Key generation failed: {}", e)),
+            };
+            match PrivateKey::new(KeypairData::from(keypair), comment) {
+                Ok(key) => key,
+                Err(e) => return Err(format!("Key generation failed: {}", e)),
+            }
+        }
+        _ => return Err(format!("Unsupported key type: {}", key_type)),
+    };
+    key.set_comment(comment);
+
+    let public_key = match key.public_key().to_openssh() {
+        Ok(public_key) => public_key,
+        Err(e) => return Err(format!("Public key encoding failed: {}", e)),
+    };
+    let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+    let key_type = key.algorithm().as_str().to_string();
+
+    if !passphrase.is_empty() {
+        key = match key.encrypt(&mut OsRng, passphrase) {
+            Ok(key) => key,
+            Err(e) => return Err(format!("Private key encryption failed: {}", e)),
+        };
+    }
+
+    let private_key = match key.to_openssh(LineEnding::LF) {
+        Ok(private_key) => private_key.to_string(),
+        Err(e) => return Err(format!("Private key encoding failed: {}", e)),
+    };
+
+    Ok(GeneratedSshKey {
+        private_key,
+        public_key,
+        fingerprint,
+        key_type,
+    })
+}
+
+//
+// Returns the decrypted key and whether it was stored encrypted
+//
+fn parse_private_key(private_key: &str, passphrase: &str) -> Result<(PrivateKey, bool), String> {
+    let pem = private_key.trim();
+
+    if pem.contains("BEGIN OPENSSH PRIVATE KEY") {
+        let key = match PrivateKey::from_openssh(pem) {
+            Ok(key) => key,
+            Err(e) => return Err(format!("Invalid OpenSSH private key: {}", e)),
+        };
+        if !key.is_encrypted() {
+            return Ok((key, false));
+        }
+        if passphrase.is_empty() {
+            return Err("Private key is encrypted, a passphrase is required".into());
+        }
+        return match key.decrypt(passphrase) {
+            Ok(key) => Ok((key, true)),
+            Err(_) => Err("Wrong passphrase for private key".into()),
+        };
+    }
+
+    if pem.contains("BEGIN RSA PRIVATE KEY") {
+        if pem.contains("Proc-Type: 4,ENCRYPTED") {
+            return Err("Legacy encrypted PEM keys are not supported, convert the key to OpenSSH or PKCS#8".into());
+        }
+        let key = match rsa::RsaPrivateKey::from_pkcs1_pem(pem) {
+            Ok(key) => key,
+            Err(e) => return Err(format!("Invalid PKCS#1 private key: {}", e)),
+        };
+        return Ok((rsa_private_key(key)?, false));
+    }
+
+    if pem.contains("BEGIN ENCRYPTED PRIVATE KEY") {
+        if passphrase.is_empty() {
+            return Err("Private key is encrypted, a passphrase is required".into());
+        }
+        if let Ok(key) = rsa::RsaPrivateKey::from_pkcs8_encrypted_pem(pem, passphrase) {
+            return Ok((rsa_private_key(key)?, true));
+        }
+        return match ed25519_dalek::SigningKey::from_pkcs8_encrypted_pem(pem, passphrase) {
+            Ok(key) => Ok((ed25519_private_key(key)?, true)),
+            Err(_) => Err("Wrong passphrase or unsupported PKCS#8 private key".into()),
+        };
+    }
+
+    if pem.contains("BEGIN PRIVATE KEY") {
+        if let Ok(key) = rsa::RsaPrivateKey::from_pkcs8_pem(pem) {
+            return Ok((rsa_private_key(key)?, false));
+        }
+        return match ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+            Ok(key) => Ok((ed25519_private_key(key)?, false)),
+            Err(_) => Err("Unsupported PKCS#8 private key ( only RSA and ed25519 are supported )".into()),
+        };
+    }
+
+    Err("Unrecognized private key format".into())
+}
+
+fn rsa_private_key(key: rsa::RsaPrivateKey) -> Result<PrivateKey, String> {
+    let keypair = match RsaKeypair::try_from(key) {
+        Ok(keypair) => keypair,
+        Err(e) => return Err(format!("Unsupported RSA key: {}", e)),
+    };
+    match PrivateKey::new(KeypairData::from(keypair), "") {
+        Ok(key) => Ok(key),
+        Err(e) => Err(format!("Unsupported RSA key: {}", e)),
+    }
+}
+
+fn ed25519_private_key(key: ed25519_dalek::SigningKey) -> Result<PrivateKey, String> {
+    match PrivateKey::new(KeypairData::Ed25519(key.into()), "") {
+        Ok(key) => Ok(key),
+        Err(e) => Err(format!("Unsupported ed25519 key: {}", e)),
+    }
+}
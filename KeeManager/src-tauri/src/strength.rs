@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use zxcvbn::zxcvbn;
+
+// Score needed for a master password unless the configuration sets another one
+pub const DEFAULT_MIN_MASTER_PASSWORD_SCORE: u8 = 3;
+pub const MAX_SCORE: u8 = 4;
+
+//
+// Strength of a password from 0 ( too guessable ) to 4 ( very unguessable ),
+// estimated by matching dictionary words, keyboard patterns, dates,
+// repeats and sequences like zxcvbn does
+//
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PasswordStrength {
+    pub score: u8,
+    pub guesses_log10: f64,
+    // Time to crack an offline hash with slow hashing ( 10k guesses per second )
+    pub crack_time: String,
+    pub crack_time_seconds: u64,
+    // Time to guess online against a rate limited service ( 100 guesses per hour )
+    pub online_crack_time: String,
+    pub warning: String,
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginStrength {
+    pub id: String,
+    pub title: String,
+    pub strength: PasswordStrength,
+}
+
+//
+// Estimates the strength of a password, user inputs are values like the
+// username or the site that make a password easier to guess when used in it
+//
+pub fn estimate(password: &str, user_inputs: &[&str]) -> PasswordStrength {
+    let entropy = zxcvbn(password, user_inputs);
+    let crack_times = entropy.crack_times();
+
+    let (warning, suggestions) = match entropy.feedback() {
+        Some(feedback) => (
+            feedback.warning().map(|warning| warning.to_string()).unwrap_or_default(),
+            feedback.suggestions().iter().map(|suggestion| suggestion.to_string()).collect(),
+        ),
+        None => (String::new(), Vec::new()),
+    };
+
+    PasswordStrength {
+        score: entropy.score().into(),
+        // An empty password has no guesses at all
+        guesses_log10: entropy.guesses_log10().max(0.0),
+        crack_time: crack_times.offline_slow_hashing_1e4_per_second().to_string(),
+        crack_time_seconds: Duration::from(crack_times.offline_slow_hashing_1e4_per_second()).as_secs(),
+        online_crack_time: crack_times.online_throttling_100_per_hour().to_string(),
+        warning,
+        suggestions,
+    }
+}
+
+//
+// Refuses a master password scoring below the minimum
+//
+pub fn check_master_password(master_password: &str, min_score: u8) -> Result<PasswordStrength, String> {
+    let strength = estimate(master_password, &["keemanager", "master", "password"]);
+    if strength.score < min_score.min(MAX_SCORE) {
+        let mut message = format!(
+            "Master password is too weak: it scores {} of {} and at least {} is required.",
+            strength.score, MAX_SCORE, min_score
+        );
+        if !strength.warning.is_empty() {
+            message.push_str(&format!(" {}", strength.warning));
+        }
+        return Err(message);
+    }
+
+    Ok(strength)
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::validation;
+
+//
+// Kinds of values a template field can hold,
+// every value is stored as a string
+//
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateFieldType {
+    Text,
+    Multiline,
+    Password,
+    Number,
+    Url,
+    Email,
+    Date,
+    Boolean,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TemplateField {
+    pub name: String,
+    pub field_type: TemplateFieldType,
+    #[serde(default)]
+    pub required: bool,
+}
+
+//
+// User-defined entry schema ( e.g. "Server" with hostname, port and root password ),
+// the whole definition is stored encrypted
+//
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Template {
+    pub id: String,
+    pub name: String,
+    pub fields: Vec<TemplateField>,
+    #[serde(default)]
+    pub modified: String,
+}
+
+//
+// Checks the template definition itself ( name and unique field names )
+//
+pub fn validate_template(template: &mut Template) -> Result<(), String> {
+    template.name = template.name.trim().to_string();
+    if template.name.is_empty() {
+        return Err("Template name is empty".into());
+    }
+    if template.fields.is_empty() {
+        return Err(format!("Template {} has no fields", template.name));
+    }
+
+    let mut names = HashSet::new();
+    for field in template.fields.iter_mut() {
+        field.name = field.name.trim().to_string();
+        if field.name.is_empty() {
+            return Err(format!("Template {} has a field without a name", template.name));
+        }
+        if !names.insert(field.name.to_lowercase()) {
+            return Err(format!("Template {} has the field {} twice", template.name, field.name));
+        }
+    }
+
+    Ok(())
+}
+
+//
+// Checks entry values against the template and returns them normalized,
+// missing optional fields are filled with an empty string and values of
+// fields the template no longer has are dropped
+//
+pub fn validate_values(
+    template: &Template,
+    values: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    let mut normalized = HashMap::new();
+    for field in &template.fields {
+        let value = values.get(&field.name).map(String::as_str).unwrap_or("");
+
+        let value = match field.field_type {
+            // Whitespace is part of passwords and multiline text
+            TemplateFieldType::Password | TemplateFieldType::Multiline => value.to_string(),
+            _ => value.trim().to_string(),
+        };
+
+        if value.is_empty() {
+            if field.required {
+                return Err(format!("{} is required", field.name));
+            }
+            normalized.insert(field.name.clone(), value);
+            continue;
+        }
+
+        let value = match field.field_type {
+            TemplateFieldType::Text | TemplateFieldType::Multiline | TemplateFieldType::Password => value,
+            TemplateFieldType::Number => {
+                if value.parse::<f64>().map(|number| !number.is_finite()).unwrap_or(true) {
+                    return Err(format!("{} must be a number", field.name));
+                }
+                value
+            }
+            TemplateFieldType::Url => {
+                validation::validate_endpoint(&value)?;
+                value
+            }
+            TemplateFieldType::Email => {
+                let valid = match value.split_once('@') {
+                    Some((local, domain)) => {
+                        !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+                    }
+                    None => false,
+                };
+                if !valid || value.contains(char::is_whitespace) {
+                    return Err(format!("{} must be an email address", field.name));
+                }
+                value
+            }
+            TemplateFieldType::Date => {
+                validation::validate_iso_date(&value)?;
+                value
+            }
+            TemplateFieldType::Boolean => match value.to_lowercase().as_str() {
+                "true" | "yes" | "1" => "true".to_string(),
+                "false" | "no" | "0" => "false".to_string(),
+                _ => return Err(format!("{} must be true or false", field.name)),
+            },
+        };
+        normalized.insert(field.name.clone(), value);
+    }
+
+    Ok(normalized)
+}
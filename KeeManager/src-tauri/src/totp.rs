@@ -0,0 +1,416 @@
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OtpKind {
+    Totp,
+    Hotp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+//
+// Parameters of a one time password generator,
+// read from an otpauth:// URI or a bare base32 secret
+//
+#[derive(Debug)]
+pub struct OtpParams {
+    pub kind: OtpKind,
+    pub secret: Vec<u8>,
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+    pub period: u64,
+    pub counter: u64,
+}
+
+// Code sent to the front end, remaining is the number of seconds
+// the code stays valid ( 0 for counter based codes )
+#[derive(Serialize, Deserialize)]
+pub struct OtpCode {
+    pub code: String,
+    pub remaining: u64,
+    pub period: u64,
+}
+
+//
+// Parses either an otpauth:// URI or a base32 secret
+// ( a bare secret is treated as a default SHA1 / 6 digits / 30s TOTP )
+//
+pub fn parse(input: &str) -> Result<OtpParams, String> {
+    let input = input.trim();
+
+    if !input.to_ascii_lowercase().starts_with("otpauth://") {
+        return Ok(OtpParams {
+            kind: OtpKind::Totp,
+            secret: decode_base32(input)?,
+            algorithm: OtpAlgorithm::Sha1,
+            digits: DEFAULT_DIGITS,
+            period: DEFAULT_PERIOD,
+            counter: 0,
+        });
+    }
+
+    let rest = &input["otpauth://".len()..];
+    let (kind, rest) = match rest.split_once('/') {
+        Some((kind, rest)) => (kind.to_ascii_lowercase(), rest),
+        None => return Err("Missing OTP type in URI".into()),
+    };
+    let kind = match kind.as_str() {
+        "totp" => OtpKind::Totp,
+        "hotp" => OtpKind::Hotp,
+        _ => return Err(format!("Unsupported OTP type: {}", kind)),
+    };
+
+    let mut params = OtpParams {
+        kind,
+        secret: Vec::new(),
+        algorithm: OtpAlgorithm::Sha1,
+        digits: DEFAULT_DIGITS,
+        period: DEFAULT_PERIOD,
+        counter: 0,
+    };
+    let mut has_counter = false;
+
+    let query = rest.split_once('?').map(|(_, query)| query).unwrap_or("");
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value)?;
+
+        match key.to_ascii_lowercase().as_str() {
+            "secret" => params.secret = decode_base32(&value)?,
+            "algorithm" => {
+                params.algorithm = match value.to_ascii_uppercase().as_str() {
+                    "SHA1" => OtpAlgorithm::Sha1,
+                    "SHA256" => OtpAlgorithm::Sha256,
+                    "SHA512" => OtpAlgorithm::Sha512,
+                    _ => return Err(format!("Unsupported OTP algorithm: {}", value)),
+                }
+            }
+            "digits" => {
+                params.digits = match value.parse::<u32>() {
+                    Ok(digits @ 6..=8) => digits,
+                    _ => return Err(format!("Unsupported OTP digit count: {}", value)),
+                }
+            }
+            "period" => {
+                params.period = match value.parse::<u64>() {
+                    Ok(period) if period > 0 => period,
+                    _ => return Err(format!("Invalid OTP period: {}", value)),
+                }
+            }
+            "counter" => {
+                params.counter = match value.parse::<u64>() {
+                    Ok(counter) => counter,
+                    Err(_) => return Err(format!("Invalid OTP counter: {}", value)),
+                };
+                has_counter = true;
+            }
+            // issuer, image etc. are only informative
+            _ => (),
+        }
+    }
+
+    if params.secret.is_empty() {
+        return Err("Missing OTP secret".into());
+    }
+    if params.kind == OtpKind::Hotp && !has_counter {
+        return Err("HOTP URI is missing its counter".into());
+    }
+
+    Ok(params)
+}
+
+//
+// Returns the URI with its counter parameter replaced
+// ( used to store the next HOTP counter )
+//
+pub fn set_counter(uri: &str, counter: u64) -> String {
+    let (base, query) = match uri.trim().split_once('?') {
+        Some((base, query)) => (base, query),
+        None => (uri.trim(), ""),
+    };
+
+    let mut pairs: Vec<String> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| !pair.to_ascii_lowercase().starts_with("counter="))
+        .map(|pair| pair.to_string())
+        .collect();
+    pairs.push(format!("counter={}", counter));
+
+    format!("{}?{}", base, pairs.join("&"))
+}
+
+//
+// The edited URI with the counter of the stored one when both are HOTP with the same
+// secret and the stored counter is further, so an edit made with an older copy of
+// the entry does not hand out used codes again
+//
+pub fn keep_counter(stored: &str, edited: &str) -> String {
+    match (parse(stored), parse(edited)) {
+        (Ok(stored), Ok(edited_params))
+            if stored.kind == OtpKind::Hotp
+                && edited_params.kind == OtpKind::Hotp
+                && stored.secret == edited_params.secret
+                && stored.counter > edited_params.counter =>
+        {
+            set_counter(edited, stored.counter)
+        }
+        _ => edited.to_string(),
+    }
+}
+
+//
+// RFC 4226 HMAC based one time password
+//
+pub fn hotp(secret: &[u8], counter: u64, digits: u32, algorithm: OtpAlgorithm) -> String {
+    let message = counter.to_be_bytes();
+    let hash = match algorithm {
+        OtpAlgorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(secret, &message),
+        OtpAlgorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(secret, &message),
+        OtpAlgorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(secret, &message),
+    };
+
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+
+    let code = binary % 10u32.pow(digits);
+    format!("{:0width$}", code, width = digits as usize)
+}
+
+//
+// RFC 6238 time based one time password for the given unix time
+//
+pub fn totp(params: &OtpParams, unix_time: u64) -> OtpCode {
+    let step = unix_time / params.period;
+    OtpCode {
+        code: hotp(&params.secret, step, params.digits, params.algorithm),
+        remaining: params.period - unix_time % params.period,
+        period: params.period,
+    }
+}
+
+fn hmac_digest<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as KeyInit>::new_from_slice(key)
+        .expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+//
+// Decodes RFC 4648 base32 ( case insensitive, spaces and padding ignored )
+//
+pub fn decode_base32(input: &str) -> Result<Vec<u8>, String> {
+    let mut output = Vec::new();
+    let mut buffer: u64 = 0;
+    let mut bits = 0;
+
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=' && *c != '-') {
+        let value = match BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())
+        {
+            Some(value) => value as u64,
+            None => return Err(format!("Invalid base32 character: {}", c)),
+        };
+
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if output.is_empty() {
+        return Err("Empty base32 secret".into());
+    }
+
+    Ok(output)
+}
+
+fn percent_decode(input: &str) -> Result<String, String> {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                // Digits are read from the bytes, slicing the text could split a character
+                match ((bytes[i + 1] as char).to_digit(16), (bytes[i + 2] as char).to_digit(16)) {
+                    (Some(high), Some(low)) => output.push((high * 16 + low) as u8),
+                    _ => return Err(format!("Invalid percent encoding in: {}", input)),
+                }
+                i += 3;
+            }
+            b'+' => {
+                output.push(b' ');
+                i += 1;
+            }
+            byte => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(output).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1_SECRET: &[u8] = b"12345678901234567890";
+    const SHA256_SECRET: &[u8] = b"12345678901234567890123456789012";
+    const SHA512_SECRET: &[u8] =
+        b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    fn params(secret: &[u8], algorithm: OtpAlgorithm) -> OtpParams {
+        OtpParams {
+            kind: OtpKind::Totp,
+            secret: secret.to_vec(),
+            algorithm,
+            digits: 8,
+            period: 30,
+            counter: 0,
+        }
+    }
+
+    #[test]
+    fn rfc4226_hotp_vectors() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(SHA1_SECRET, counter as u64, 6, OtpAlgorithm::Sha1), *code);
+        }
+    }
+
+    #[test]
+    fn rfc6238_totp_vectors() {
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, sha1, sha256, sha512) in vectors {
+            assert_eq!(totp(&params(SHA1_SECRET, OtpAlgorithm::Sha1), time).code, sha1);
+            assert_eq!(totp(&params(SHA256_SECRET, OtpAlgorithm::Sha256), time).code, sha256);
+            assert_eq!(totp(&params(SHA512_SECRET, OtpAlgorithm::Sha512), time).code, sha512);
+        }
+    }
+
+    #[test]
+    fn remaining_validity() {
+        let code = totp(&params(SHA1_SECRET, OtpAlgorithm::Sha1), 59);
+        assert_eq!(code.remaining, 1);
+        let code = totp(&params(SHA1_SECRET, OtpAlgorithm::Sha1), 60);
+        assert_eq!(code.remaining, 30);
+    }
+
+    #[test]
+    fn base32_decoding() {
+        // "12345678901234567890" in base32
+        let encoded = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        assert_eq!(decode_base32(encoded).unwrap(), SHA1_SECRET);
+        assert_eq!(decode_base32(&encoded.to_lowercase()).unwrap(), SHA1_SECRET);
+        assert_eq!(decode_base32("GEZD GNBV GY3T QOJQ GEZD GNBV GY3T QOJQ").unwrap(), SHA1_SECRET);
+        assert!(decode_base32("GEZ1").is_err());
+        assert!(decode_base32("").is_err());
+    }
+
+    #[test]
+    fn parses_bare_secret() {
+        let params = parse("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        assert_eq!(params.kind, OtpKind::Totp);
+        assert_eq!(params.algorithm, OtpAlgorithm::Sha1);
+        assert_eq!(params.digits, 6);
+        assert_eq!(params.period, 30);
+        assert_eq!(params.secret, SHA1_SECRET);
+    }
+
+    #[test]
+    fn parses_totp_uri() {
+        let params = parse(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert_eq!(params.kind, OtpKind::Totp);
+        assert_eq!(params.algorithm, OtpAlgorithm::Sha256);
+        assert_eq!(params.digits, 8);
+        assert_eq!(params.period, 60);
+    }
+
+    #[test]
+    fn rejects_invalid_uris() {
+        assert!(parse("otpauth://totp/label?issuer=x").is_err());
+        assert!(parse("otpauth://totp/label?secret=GEZDGNBV&digits=5").is_err());
+        assert!(parse("otpauth://totp/label?secret=GEZDGNBV&algorithm=MD5").is_err());
+        assert!(parse("otpauth://totp/label?secret=GEZDGNBV&period=0").is_err());
+        assert!(parse("otpauth://hotp/label?secret=GEZDGNBV").is_err());
+        assert!(parse("otpauth://motp/label?secret=GEZDGNBV").is_err());
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("ACME%20Co+Ltd").unwrap(), "ACME Co Ltd");
+        assert_eq!(percent_decode("%C3%A9t%C3%A9").unwrap(), "été");
+        assert!(percent_decode("%aé").is_err());
+        assert!(percent_decode("%éa").is_err());
+        assert!(percent_decode("%zz").is_err());
+        assert!(parse("otpauth://totp/label?secret=GEZDGNBV&issuer=%aé").is_err());
+    }
+
+    #[test]
+    fn hotp_counter_round_trip() {
+        let uri = "otpauth://hotp/label?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=1&digits=6";
+        let params = parse(uri).unwrap();
+        assert_eq!(params.kind, OtpKind::Hotp);
+        assert_eq!(params.counter, 1);
+        assert_eq!(hotp(&params.secret, params.counter, params.digits, params.algorithm), "287082");
+
+        let next = set_counter(uri, params.counter + 1);
+        assert_eq!(parse(&next).unwrap().counter, 2);
+        assert!(next.starts_with("otpauth://hotp/label?secret="));
+    }
+
+    #[test]
+    fn edits_keep_the_stored_counter() {
+        let stored = "otpauth://hotp/label?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=5";
+        let edited = "otpauth://hotp/label?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=2&digits=8";
+        let kept = keep_counter(stored, edited);
+        assert_eq!(parse(&kept).unwrap().counter, 5);
+        assert_eq!(parse(&kept).unwrap().digits, 8);
+
+        // A counter set further by hand, a new secret or a TOTP secret is taken as edited
+        let further = "otpauth://hotp/label?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=9";
+        assert_eq!(keep_counter(stored, further), further);
+        let other_secret = "otpauth://hotp/label?secret=JBSWY3DPEHPK3PXP&counter=0";
+        assert_eq!(keep_counter(stored, other_secret), other_secret);
+        assert_eq!(keep_counter(stored, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(keep_counter("", edited), edited);
+    }
+}
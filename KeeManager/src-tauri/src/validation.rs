@@ -0,0 +1,267 @@
+use chrono::NaiveDate;
+
+// Wi-Fi security types the front end can choose from
+pub const WIFI_SECURITY_TYPES: [&str; 7] = [
+    "Open",
+    "WEP",
+    "WPA",
+    "WPA2",
+    "WPA3",
+    "WPA2/WPA3",
+    "WPA2-Enterprise",
+];
+
+// IBAN lengths per country ( ISO 13616 registry )
+const IBAN_LENGTHS: [(&str, usize); 89] = [
+    ("AD", 24), ("AE", 23), ("AL", 28), ("AT", 20), ("AZ", 28), ("BA", 20), ("BE", 16),
+    ("BG", 22), ("BH", 22), ("BI", 27), ("BR", 29), ("BY", 28), ("CH", 21), ("CR", 22),
+    ("CY", 28), ("CZ", 24), ("DE", 22), ("DJ", 27), ("DK", 18), ("DO", 28), ("EE", 20),
+    ("EG", 29), ("ES", 24), ("FI", 18), ("FK", 18), ("FO", 18), ("FR", 27), ("GB", 22),
+    ("GE", 22), ("GI", 23), ("GL", 18), ("GR", 27), ("GT", 28), ("HN", 28), ("HR", 21),
+    ("HU", 28), ("IE", 22), ("IL", 23), ("IQ", 23), ("IS", 26), ("IT", 27), ("JO", 30),
+    ("KW", 30), ("KZ", 20), ("LB", 28), ("LC", 32), ("LI", 21), ("LT", 20), ("LU", 20),
+    ("LV", 21), ("LY", 25), ("MC", 27), ("MD", 24), ("ME", 22), ("MK", 19), ("MN", 20),
+    ("MR", 27), ("MT", 31), ("MU", 30), ("NI", 28), ("NL", 18), ("NO", 15), ("OM", 23),
+    ("PK", 24), ("PL", 28), ("PS", 29), ("PT", 25), ("QA", 29), ("RO", 24), ("RS", 22),
+    ("RU", 33), ("SA", 24), ("SC", 31), ("SD", 18), ("SE", 24), ("SI", 19), ("SK", 24),
+    ("SM", 27), ("SO", 23), ("ST", 25), ("SV", 28), ("TL", 23), ("TN", 24), ("TR", 26),
+    ("UA", 29), ("VA", 22), ("VG", 24), ("XK", 20), ("YE", 30),
+];
+
+//
+// Validates an IBAN ( country length and ISO 7064 mod 97 checksum )
+// and returns it without spaces in upper case
+//
+pub fn validate_iban(iban: &str) -> Result<String, String> {
+    let iban: String = iban
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_ascii_uppercase();
+
+    if iban.len() < 15 || iban.len() > 34 {
+        return Err("IBAN must be between 15 and 34 characters long".into());
+    }
+    if !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("IBAN may only contain letters and digits".into());
+    }
+
+    let country = &iban[0..2];
+    if !country.chars().all(|c| c.is_ascii_alphabetic())
+        || !iban[2..4].chars().all(|c| c.is_ascii_digit())
+    {
+        return Err("IBAN must start with a country code and two check digits".into());
+    }
+
+    if let Some((_, length)) = IBAN_LENGTHS.iter().find(|(code, _)| *code == country) {
+        if iban.len() != *length {
+            return Err(format!("IBAN for {} must be {} characters long", country, length));
+        }
+    }
+
+    // Move the first four characters to the end and turn letters into numbers ( A = 10 )
+    let rearranged = format!("{}{}", &iban[4..], &iban[0..4]);
+    let mut remainder: u32 = 0;
+    for c in rearranged.chars() {
+        let value = c.to_digit(36).unwrap();
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+
+    if remainder != 1 {
+        return Err("IBAN checksum is invalid".into());
+    }
+
+    Ok(iban)
+}
+
+//
+// Validates a BIC / SWIFT code ( 8 or 11 characters ) and returns it in upper case,
+// an empty BIC is allowed since not every account has one
+//
+pub fn validate_bic(bic: &str) -> Result<String, String> {
+    let bic = bic.trim().replace(' ', "").to_ascii_uppercase();
+    if bic.is_empty() {
+        return Ok(bic);
+    }
+
+    // Checked first so the byte lengths and slices below count characters
+    if !bic.is_ascii() {
+        return Err("BIC may only contain letters and digits".into());
+    }
+    if bic.len() != 8 && bic.len() != 11 {
+        return Err("BIC must be 8 or 11 characters long".into());
+    }
+    if !bic[0..6].chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err("BIC must start with a 4 letter bank code and a 2 letter country code".into());
+    }
+    if !bic[6..].chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("BIC location and branch codes may only contain letters and digits".into());
+    }
+
+    Ok(bic)
+}
+
+//
+// Checks SSID and password against what the security type allows
+//
+pub fn validate_wifi(ssid: &str, security_type: &str, password: &str) -> Result<(), String> {
+    if ssid.is_empty() || ssid.len() > 32 {
+        return Err("SSID must be between 1 and 32 bytes long".into());
+    }
+
+    if !WIFI_SECURITY_TYPES.contains(&security_type) {
+        return Err(format!("Unknown Wi-Fi security type: {}", security_type));
+    }
+
+    let is_hex = |value: &str| value.chars().all(|c| c.is_ascii_hexdigit());
+
+    match security_type {
+        "Open" if !password.is_empty() => {
+            return Err("Open networks have no password".into());
+        }
+        "WEP" => {
+            let valid = match password.len() {
+                5 | 13 => true,
+                10 | 26 => is_hex(password),
+                _ => false,
+            };
+            if !valid {
+                return Err("WEP keys are 5 or 13 characters, or 10 or 26 hex digits".into());
+            }
+        }
+        "WPA" | "WPA2" | "WPA3" | "WPA2/WPA3" => {
+            let valid = (8..=63).contains(&password.len()) || (password.len() == 64 && is_hex(password));
+            if !valid {
+                return Err("WPA passphrases are 8 to 63 characters, or 64 hex digits".into());
+            }
+        }
+        // Open networks and WPA2-Enterprise have no pre-shared key
+        _ => (),
+    }
+
+    Ok(())
+}
+
+//
+// Optional ISO 8601 date ( YYYY-MM-DD ), empty means no date
+//
+pub fn validate_iso_date(date: &str) -> Result<Option<NaiveDate>, String> {
+    let date = date.trim();
+    if date.is_empty() {
+        return Ok(None);
+    }
+
+    match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(date) => Ok(Some(date)),
+        Err(_) => Err(format!("Invalid date, expected YYYY-MM-DD: {}", date)),
+    }
+}
+
+//
+// Optional card expiry ( MM/YY or MM/YYYY ), empty means no date,
+// returns the last day of the expiry month since cards are valid until then
+//
+pub fn parse_card_expiry(expiry: &str) -> Result<Option<NaiveDate>, String> {
+    let expiry = expiry.trim();
+    if expiry.is_empty() {
+        return Ok(None);
+    }
+
+    let invalid = || format!("Invalid card expiry, expected MM/YY: {}", expiry);
+
+    let (month, year) = match expiry.split_once('/') {
+        Some((month, year)) => (month.trim(), year.trim()),
+        None => return Err(invalid()),
+    };
+    if month.is_empty()
+        || month.len() > 2
+        || !month.chars().all(|c| c.is_ascii_digit())
+        || !year.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    let month: u32 = month.parse().map_err(|_| invalid())?;
+    let year: i32 = match year.len() {
+        2 => 2000 + year.parse::<i32>().map_err(|_| invalid())?,
+        4 => year.parse().map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+    if !(1..=12).contains(&month) {
+        return Err(format!("Invalid card expiry month: {}", month));
+    }
+
+    // The day before the first day of the next month
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    match NaiveDate::from_ymd_opt(next_year, next_month, 1).and_then(|date| date.pred_opt()) {
+        Some(date) => Ok(Some(date)),
+        None => Err(invalid()),
+    }
+}
+
+//
+// Optional endpoint, must be an http(s) URL when given
+//
+pub fn validate_endpoint(endpoint: &str) -> Result<(), String> {
+    let endpoint = endpoint.trim();
+    if endpoint.is_empty() {
+        return Ok(());
+    }
+
+    let lowercase = endpoint.to_ascii_lowercase();
+    if !(lowercase.starts_with("https://") || lowercase.starts_with("http://"))
+        || endpoint.contains(char::is_whitespace)
+    {
+        return Err(format!("Endpoint must be an http(s) URL: {}", endpoint));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_ibans_are_normalized() {
+        assert_eq!(validate_iban("GB82 WEST 1234 5698 7654 32").unwrap(), "GB82WEST12345698765432");
+        assert_eq!(validate_iban("nl91abna0417164300").unwrap(), "NL91ABNA0417164300");
+        assert_eq!(validate_iban("DE89-3704-0044-0532-0130-00").unwrap(), "DE89370400440532013000");
+        assert_eq!(validate_iban("NO9386011117947").unwrap(), "NO9386011117947");
+    }
+
+    #[test]
+    fn invalid_ibans_are_refused() {
+        // Checksum
+        assert_eq!(validate_iban("GB83WEST12345698765432").unwrap_err(), "IBAN checksum is invalid");
+        // Length of the country
+        assert_eq!(validate_iban("NL91ABNA04171643001").unwrap_err(), "IBAN for NL must be 18 characters long");
+        assert!(validate_iban("NL91ABNA041").is_err());
+        assert!(validate_iban("GB82WEST1234569876543!").is_err());
+        assert!(validate_iban("1282WEST12345698765432").is_err());
+        assert!(validate_iban("GB8XWEST12345698765432").is_err());
+        assert!(validate_iban("ÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄ").is_err());
+    }
+
+    #[test]
+    fn bics() {
+        assert_eq!(validate_bic("deutdeff").unwrap(), "DEUTDEFF");
+        assert_eq!(validate_bic("DEUT DE FF 500").unwrap(), "DEUTDEFF500");
+        assert_eq!(validate_bic("  ").unwrap(), "");
+        assert!(validate_bic("DEUTDEF").is_err());
+        assert!(validate_bic("DEU1DEFF").is_err());
+        assert!(validate_bic("DEUTDEF!").is_err());
+    }
+
+    #[test]
+    fn non_ascii_bics_are_refused_without_panicking() {
+        // 8 and 11 bytes long, slicing them by bytes would split a character
+        assert!(validate_bic("ÄÄÄÄ").is_err());
+        assert!(validate_bic("ééééAAA").is_err());
+        assert!(validate_bic("DEUTDEFé").is_err());
+    }
+}